@@ -0,0 +1,201 @@
+//! A local OpenID Connect provider to try single sign-on without a real one.
+//! Every authorization request is approved at once, for the email passed as
+//! `login_hint` or `MOCK_IDP_EMAIL`.
+//!
+//! ```sh
+//! cargo run --example mock_idp
+//! OIDC_ISSUER=http://localhost:9000 OIDC_CLIENT_ID=idp-console OIDC_DEFAULT_ROLE=viewer cargo run
+//! ```
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Form, Json, Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use reqwest::Url;
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair},
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+const KID: &str = "mock";
+
+struct Pending {
+    client_id: String,
+    redirect_uri: String,
+    code_challenge: String,
+    nonce: Option<String>,
+    email: String,
+}
+
+struct MockIdp {
+    issuer: String,
+    client_id: String,
+    email: String,
+    key: EncodingKey,
+    public_key: String,
+    codes: Mutex<HashMap<String, Pending>>,
+}
+
+type Params = HashMap<String, String>;
+
+#[tokio::main]
+async fn main() {
+    let port = std::env::var("MOCK_IDP_PORT").unwrap_or("9000".to_string());
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).expect("generate a key");
+    let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).expect("read the key");
+
+    let idp = Arc::new(MockIdp {
+        issuer: format!("http://localhost:{}", port),
+        client_id: std::env::var("MOCK_IDP_CLIENT_ID").unwrap_or("idp-console".to_string()),
+        email: std::env::var("MOCK_IDP_EMAIL").unwrap_or("staff@example.org".to_string()),
+        key: EncodingKey::from_ed_der(pkcs8.as_ref()),
+        public_key: URL_SAFE_NO_PAD.encode(pair.public_key().as_ref()),
+        codes: Mutex::new(HashMap::new()),
+    });
+
+    let app = Router::new()
+        .route("/.well-known/openid-configuration", get(discovery))
+        .route("/authorize", get(authorize))
+        .route("/token", post(token))
+        .route("/jwks", get(jwks))
+        .with_state(idp);
+
+    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port))
+        .await
+        .unwrap();
+    println!("Mock identity provider on http://localhost:{}", port);
+    axum::serve(listener, app).await.unwrap();
+}
+
+async fn discovery(State(idp): State<Arc<MockIdp>>) -> Response {
+    Json(json!({
+        "issuer": idp.issuer,
+        "authorization_endpoint": format!("{}/authorize", idp.issuer),
+        "token_endpoint": format!("{}/token", idp.issuer),
+        "jwks_uri": format!("{}/jwks", idp.issuer),
+        "response_types_supported": ["code"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": ["EdDSA"],
+        "code_challenge_methods_supported": ["S256"],
+    }))
+    .into_response()
+}
+
+async fn authorize(State(idp): State<Arc<MockIdp>>, Query(params): Query<Params>) -> Response {
+    let param = |name: &str| params.get(name).cloned().unwrap_or_default();
+
+    if param("response_type") != "code" || param("code_challenge_method") != "S256" {
+        return oauth_error("unsupported_response_type");
+    }
+    if param("client_id") != idp.client_id {
+        return oauth_error("unauthorized_client");
+    }
+    let mut redirect = match Url::parse(&param("redirect_uri")) {
+        Ok(url) => url,
+        Err(_) => return oauth_error("invalid_request"),
+    };
+
+    let code = random();
+    let pending = Pending {
+        client_id: param("client_id"),
+        redirect_uri: param("redirect_uri"),
+        code_challenge: param("code_challenge"),
+        nonce: params.get("nonce").cloned(),
+        email: params
+            .get("login_hint")
+            .cloned()
+            .unwrap_or(idp.email.clone()),
+    };
+    idp.codes.lock().unwrap().insert(code.clone(), pending);
+
+    redirect
+        .query_pairs_mut()
+        .append_pair("code", &code)
+        .append_pair("state", &param("state"));
+    (
+        StatusCode::FOUND,
+        [(header::LOCATION, redirect.to_string())],
+    )
+        .into_response()
+}
+
+async fn token(State(idp): State<Arc<MockIdp>>, Form(params): Form<Params>) -> Response {
+    let param = |name: &str| params.get(name).cloned().unwrap_or_default();
+
+    if param("grant_type") != "authorization_code" {
+        return oauth_error("unsupported_grant_type");
+    }
+    // A code works once, right or wrong.
+    let pending = match idp.codes.lock().unwrap().remove(&param("code")) {
+        Some(pending) => pending,
+        None => return oauth_error("invalid_grant"),
+    };
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(param("code_verifier").as_bytes()));
+    if pending.client_id != param("client_id")
+        || pending.redirect_uri != param("redirect_uri")
+        || pending.code_challenge != challenge
+    {
+        return oauth_error("invalid_grant");
+    }
+
+    let now = Utc::now().timestamp();
+    let claims = json!({
+        "iss": idp.issuer,
+        "aud": pending.client_id,
+        "sub": format!("{:x}", Sha256::digest(pending.email.as_bytes())),
+        "email": pending.email,
+        "email_verified": true,
+        "nonce": pending.nonce,
+        "iat": now,
+        "exp": now + 300,
+    });
+    let mut header = Header::new(Algorithm::EdDSA);
+    header.kid = Some(KID.to_string());
+    let id_token = encode(&header, &claims, &idp.key).unwrap();
+
+    Json(json!({
+        "access_token": random(),
+        "token_type": "Bearer",
+        "expires_in": 300,
+        "id_token": id_token,
+    }))
+    .into_response()
+}
+
+async fn jwks(State(idp): State<Arc<MockIdp>>) -> Response {
+    Json(json!({
+        "keys": [{
+            "kty": "OKP",
+            "crv": "Ed25519",
+            "x": idp.public_key,
+            "kid": KID,
+            "alg": "EdDSA",
+            "use": "sig",
+        }]
+    }))
+    .into_response()
+}
+
+fn oauth_error(error: &str) -> Response {
+    (StatusCode::BAD_REQUEST, Json(json!({ "error": error }))).into_response()
+}
+
+fn random() -> String {
+    let mut bytes = [0u8; 24];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
@@ -5,7 +5,44 @@ CREATE TABLE IF NOT EXISTS users (
   email             VARCHAR(64) NOT NULL UNIQUE,
   password_alg      VARCHAR(8) NOT NULL,
   password_hash     VARCHAR(255) NOT NULL,
-  created_at        timestamp NOT NULL DEFAULT NOW()
+  created_at        timestamptz NOT NULL DEFAULT NOW()
+);
+
+
+-- Accounts created before roles existed keep full access.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS role VARCHAR(16) NOT NULL DEFAULT 'admin';
+ALTER TABLE users ALTER COLUMN role SET DEFAULT 'viewer';
+ALTER TABLE users ADD COLUMN IF NOT EXISTS disabled_at timestamptz;
+
+-- The TOTP secret is pending until the first code confirms it. The last used
+-- time step keeps a code from being accepted twice.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_secret VARCHAR(64);
+ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_enabled_at timestamptz;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_last_step BIGINT;
+
+
+CREATE TABLE IF NOT EXISTS user_recovery_codes (
+  id                SERIAL PRIMARY KEY,
+  user_id           VARCHAR(36) NOT NULL,
+  code_hash         VARCHAR(128) NOT NULL,
+  used_at           timestamptz,
+
+  CONSTRAINT fk_user_recovery_codes
+    FOREIGN KEY(user_id)
+      REFERENCES users(id)
+        ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_recovery_codes_user ON user_recovery_codes (user_id);
+
+-- Recovery codes are Argon2 hashes, the earlier SHA-256 ones no longer work.
+ALTER TABLE user_recovery_codes ALTER COLUMN code_hash TYPE VARCHAR(128);
+DELETE FROM user_recovery_codes WHERE code_hash NOT LIKE '$argon2%';
+
+
+CREATE TABLE IF NOT EXISTS settings (
+  key               VARCHAR(64) NOT NULL PRIMARY KEY,
+  value             VARCHAR NOT NULL
 );
 
 
@@ -25,6 +62,67 @@ CREATE TABLE IF NOT EXISTS user_tokens (
 CREATE INDEX IF NOT EXISTS idx_user_token_token ON user_tokens (token);
 CREATE INDEX IF NOT EXISTS idx_user_token_type ON user_tokens (type);
 
+-- An access token and the chain of refresh tokens issued for one sign in share
+-- a family. Rotated refresh tokens are kept with used_at set to detect reuse.
+ALTER TABLE user_tokens ADD COLUMN IF NOT EXISTS family VARCHAR(36);
+ALTER TABLE user_tokens ADD COLUMN IF NOT EXISTS used_at timestamptz;
+CREATE INDEX IF NOT EXISTS idx_user_token_family ON user_tokens (family);
+
+-- The access token row of a family describes the session it belongs to.
+ALTER TABLE user_tokens ADD COLUMN IF NOT EXISTS device VARCHAR(128);
+ALTER TABLE user_tokens ADD COLUMN IF NOT EXISTS ip VARCHAR(64);
+ALTER TABLE user_tokens ADD COLUMN IF NOT EXISTS created_at timestamptz NOT NULL DEFAULT NOW();
+ALTER TABLE user_tokens ADD COLUMN IF NOT EXISTS last_seen_at timestamptz;
+
+
+-- Failed sign ins counted per email and per client IP.
+CREATE TABLE IF NOT EXISTS login_attempts (
+  kind              VARCHAR(8) NOT NULL,
+  value             VARCHAR(256) NOT NULL,
+  failures          INTEGER NOT NULL DEFAULT 0,
+  last_failed_at    timestamptz NOT NULL DEFAULT NOW(),
+  locked_until      timestamptz,
+
+  PRIMARY KEY (kind, value)
+);
+
+
+CREATE TABLE IF NOT EXISTS reset_requests (
+  kind              VARCHAR(8) NOT NULL,
+  value             VARCHAR(256) NOT NULL,
+  requests          INTEGER NOT NULL DEFAULT 0,
+  window_started_at timestamptz NOT NULL DEFAULT NOW(),
+
+  PRIMARY KEY (kind, value)
+);
+
+
+CREATE TABLE IF NOT EXISTS api_keys (
+  id                VARCHAR(36) NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+  user_id           VARCHAR(36) NOT NULL,
+  name              VARCHAR(64) NOT NULL,
+  prefix            VARCHAR(16) NOT NULL,
+  key_hash          VARCHAR(64) NOT NULL UNIQUE,
+  permissions       VARCHAR(32)[] NOT NULL,
+  created_at        timestamptz NOT NULL DEFAULT NOW(),
+  last_used_at      timestamptz,
+  revoked_at        timestamptz,
+
+  CONSTRAINT fk_api_keys_user
+    FOREIGN KEY(user_id)
+      REFERENCES users(id)
+        ON DELETE CASCADE
+);
+
+
+-- Single sign-on attempts waiting for the identity provider to redirect back.
+CREATE TABLE IF NOT EXISTS oidc_logins (
+  state             VARCHAR(64) NOT NULL PRIMARY KEY,
+  nonce             VARCHAR(64) NOT NULL,
+  code_verifier     VARCHAR(128) NOT NULL,
+  created_at        timestamptz NOT NULL DEFAULT NOW()
+);
+
 
 CREATE TABLE IF NOT EXISTS respondents (
   id                VARCHAR(36) NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
@@ -35,7 +133,7 @@ CREATE TABLE IF NOT EXISTS respondents (
   phone             VARCHAR(16) NOT NULL,
   region            VARCHAR(64) NOT NULL,
   children          SMALLINT NOT NULL DEFAULT 0,
-  created_at        timestamp NOT NULL DEFAULT NOW()
+  created_at        timestamptz NOT NULL DEFAULT NOW()
 );
 
 
@@ -44,22 +142,27 @@ CREATE TABLE IF NOT EXISTS forms (
   name                  VARCHAR(64) NOT NULL,
   form_limit            INT NOT NULL,
   status                VARCHAR(16) NOT NULL,
-  scheduled_start_date  timestamp NOT NULL,
-  scheduled_end_date    timestamp NOT NULL,
-  created_at            timestamp NOT NULL DEFAULT NOW(),
+  scheduled_start_date  timestamptz NOT NULL,
+  scheduled_end_date    timestamptz NOT NULL,
+  created_at            timestamptz NOT NULL DEFAULT NOW(),
   time_frame_duration   INT NOT NULL DEFAULT 0,
   exclude_form_ids      text[]
 );
 
 
+ALTER TABLE forms ADD COLUMN IF NOT EXISTS auto_schedule BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE forms ADD COLUMN IF NOT EXISTS calendar JSONB;
+ALTER TABLE forms ADD COLUMN IF NOT EXISTS time_zone VARCHAR(64) NOT NULL DEFAULT 'Europe/Kyiv';
+
+
 CREATE TABLE IF NOT EXISTS submissions (
   id                VARCHAR(36) NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
   form_id           VARCHAR(36) NOT NULL,
   respondent_id     VARCHAR(36) NOT NULL,
   sub_order         INT NOT NULL,
-  arrival_date      timestamp NOT NULL,
+  arrival_date      timestamptz NOT NULL,
   status            VARCHAR(16) NOT NULL,
-  created_at        timestamp NOT NULL DEFAULT NOW(),
+  created_at        timestamptz NOT NULL DEFAULT NOW(),
   
   CONSTRAINT fk_form
     FOREIGN KEY(form_id) 
@@ -71,3 +174,183 @@ CREATE TABLE IF NOT EXISTS submissions (
       REFERENCES respondents(id)
         ON DELETE CASCADE
 );
+
+
+DROP INDEX IF EXISTS uq_submissions_form_respondent;
+DROP INDEX IF EXISTS uq_submissions_form_order;
+
+-- Concurrent bookings used to leave duplicates behind, which the indexes below
+-- would fail on. The earliest submission of a respondent in a form stays,
+-- later ones are cancelled. Submissions sharing a place keep their arrival
+-- date and move past the last place of the form, a reflow closes the gaps.
+DO $$
+BEGIN
+  IF to_regclass('uq_submissions_active_form_respondent') IS NULL THEN
+    UPDATE submissions SET status = 'cancelled'
+    WHERE id IN (
+      SELECT id FROM (
+        SELECT id, ROW_NUMBER() OVER (
+          PARTITION BY form_id, respondent_id ORDER BY created_at, id
+        ) AS n
+        FROM submissions WHERE status <> 'cancelled'
+      ) AS t
+      WHERE t.n > 1
+    );
+  END IF;
+
+  IF to_regclass('uq_submissions_active_form_order') IS NULL THEN
+    UPDATE submissions AS s SET sub_order = t.new_order
+    FROM (
+      SELECT id,
+        MAX(sub_order) OVER (PARTITION BY form_id)
+          + ROW_NUMBER() OVER (PARTITION BY form_id ORDER BY sub_order, created_at, id) AS new_order,
+        ROW_NUMBER() OVER (PARTITION BY form_id, sub_order ORDER BY created_at, id) AS n
+      FROM submissions WHERE status <> 'cancelled'
+    ) AS t
+    WHERE s.id = t.id AND t.n > 1;
+  END IF;
+END $$;
+
+-- Cancelled submissions give their place back, so they are left out of both constraints.
+CREATE UNIQUE INDEX IF NOT EXISTS uq_submissions_active_form_respondent
+  ON submissions (form_id, respondent_id) WHERE status <> 'cancelled';
+CREATE UNIQUE INDEX IF NOT EXISTS uq_submissions_active_form_order
+  ON submissions (form_id, sub_order) WHERE status <> 'cancelled';
+
+
+CREATE TABLE IF NOT EXISTS submission_status_history (
+  id                SERIAL PRIMARY KEY,
+  submission_id     VARCHAR(36) NOT NULL,
+  old_status        VARCHAR(16),
+  new_status        VARCHAR(16) NOT NULL,
+  user_id           VARCHAR(36),
+  created_at        timestamptz NOT NULL DEFAULT NOW(),
+
+  CONSTRAINT fk_submission
+    FOREIGN KEY(submission_id)
+      REFERENCES submissions(id)
+        ON DELETE CASCADE,
+
+  CONSTRAINT fk_user
+    FOREIGN KEY(user_id)
+      REFERENCES users(id)
+        ON DELETE SET NULL
+);
+
+
+CREATE INDEX IF NOT EXISTS idx_submission_status_history_submission ON submission_status_history (submission_id);
+
+
+CREATE TABLE IF NOT EXISTS form_slots (
+  id                VARCHAR(36) NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+  form_id           VARCHAR(36) NOT NULL,
+  starts_at         timestamptz NOT NULL,
+  ends_at           timestamptz NOT NULL,
+  capacity          INT NOT NULL,
+
+  CONSTRAINT fk_form
+    FOREIGN KEY(form_id)
+      REFERENCES forms(id)
+        ON DELETE CASCADE
+);
+
+
+CREATE INDEX IF NOT EXISTS idx_form_slots_form ON form_slots (form_id, starts_at);
+
+ALTER TABLE submissions ADD COLUMN IF NOT EXISTS slot_id VARCHAR(36)
+  REFERENCES form_slots(id) ON DELETE SET NULL;
+CREATE INDEX IF NOT EXISTS idx_submissions_slot ON submissions (slot_id);
+
+
+CREATE TABLE IF NOT EXISTS form_waitlist (
+  id                VARCHAR(36) NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+  form_id           VARCHAR(36) NOT NULL,
+  respondent_id     VARCHAR(36) NOT NULL,
+  position          INT NOT NULL,
+  created_at        timestamptz NOT NULL DEFAULT NOW(),
+
+  CONSTRAINT uq_form_waitlist_form_respondent UNIQUE (form_id, respondent_id),
+
+  CONSTRAINT fk_form
+    FOREIGN KEY(form_id)
+      REFERENCES forms(id)
+        ON DELETE CASCADE,
+
+  CONSTRAINT fk_respondent
+    FOREIGN KEY(respondent_id)
+      REFERENCES respondents(id)
+        ON DELETE CASCADE
+);
+
+
+CREATE TABLE IF NOT EXISTS form_shifts (
+  id                SERIAL PRIMARY KEY,
+  form_id           VARCHAR(36) NOT NULL,
+  shift_seconds     BIGINT NOT NULL,
+  reason            TEXT,
+  submissions       INT NOT NULL,
+  user_id           VARCHAR(36),
+  created_at        timestamptz NOT NULL DEFAULT NOW(),
+
+  CONSTRAINT fk_form
+    FOREIGN KEY(form_id)
+      REFERENCES forms(id)
+        ON DELETE CASCADE,
+
+  CONSTRAINT fk_user
+    FOREIGN KEY(user_id)
+      REFERENCES users(id)
+        ON DELETE SET NULL
+);
+
+
+CREATE INDEX IF NOT EXISTS idx_form_shifts_form ON form_shifts (form_id);
+
+
+-- Append-only: no foreign keys, entries outlive the users and records they
+-- mention, and the trigger below rejects any change to them.
+CREATE TABLE IF NOT EXISTS audit_log (
+  id                BIGSERIAL PRIMARY KEY,
+  actor_id          VARCHAR(36),
+  actor_email       VARCHAR(64),
+  action            VARCHAR(32) NOT NULL,
+  entity            VARCHAR(32) NOT NULL,
+  entity_id         VARCHAR(36) NOT NULL,
+  changes           JSONB NOT NULL,
+  created_at        timestamptz NOT NULL DEFAULT NOW()
+);
+
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_entity ON audit_log (entity, entity_id);
+CREATE INDEX IF NOT EXISTS idx_audit_log_actor ON audit_log (actor_id);
+CREATE INDEX IF NOT EXISTS idx_audit_log_created_at ON audit_log (created_at);
+
+
+CREATE OR REPLACE FUNCTION audit_log_append_only() RETURNS trigger AS $$
+BEGIN
+  RAISE EXCEPTION 'audit_log is append-only';
+END $$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS trg_audit_log_append_only ON audit_log;
+CREATE TRIGGER trg_audit_log_append_only
+  BEFORE UPDATE OR DELETE OR TRUNCATE ON audit_log
+  FOR EACH STATEMENT EXECUTE FUNCTION audit_log_append_only();
+
+
+-- Older databases stored UTC values in plain timestamp columns.
+DO $$
+DECLARE
+  col RECORD;
+BEGIN
+  FOR col IN
+    SELECT table_name, column_name FROM information_schema.columns
+    WHERE table_schema = current_schema()
+      AND data_type = 'timestamp without time zone'
+      AND table_name IN ('users', 'respondents', 'forms', 'submissions', 'submission_status_history')
+  LOOP
+    EXECUTE format(
+      'ALTER TABLE %I ALTER COLUMN %I TYPE timestamptz USING %I AT TIME ZONE ''UTC''',
+      col.table_name, col.column_name, col.column_name
+    );
+  END LOOP;
+END $$;
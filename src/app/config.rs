@@ -1,3 +1,20 @@
+pub use super::utils::{jwt_keys::JwtKeys, trusted_proxies::TrustedProxies};
+
+use super::entities::role::Role;
+
 pub struct Config {
-    pub jwt_secret_key: String
-}
\ No newline at end of file
+    pub jwt_keys: JwtKeys,
+    pub form_scheduler_interval: u64,
+    pub access_token_ttl: i64,
+    pub refresh_token_ttl: i64,
+    pub reset_token_ttl: i64,
+    pub app_url: String,
+    pub login_max_attempts: i32,
+    pub login_lockout: i64,
+    pub totp_issuer: String,
+    pub oidc_default_role: Option<Role>,
+    pub argon2_memory: u32,
+    pub argon2_iterations: u32,
+    pub argon2_parallelism: u32,
+    pub trusted_proxies: TrustedProxies,
+}
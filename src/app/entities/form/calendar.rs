@@ -0,0 +1,93 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TimeRange {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkingCalendar {
+    pub opens_at: NaiveTime,
+    pub closes_at: NaiveTime,
+    #[serde(default)]
+    pub breaks: Vec<TimeRange>,
+    #[serde(default)]
+    pub excluded_weekdays: Vec<Weekday>,
+    #[serde(default)]
+    pub holidays: Vec<NaiveDate>,
+}
+
+impl WorkingCalendar {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.opens_at >= self.closes_at {
+            return Err("Opening time should be before closing time".to_string());
+        }
+
+        let break_is_valid =
+            |b: &TimeRange| b.start < b.end && b.start >= self.opens_at && b.end <= self.closes_at;
+        if !self.breaks.iter().all(break_is_valid) {
+            return Err("Breaks should be within opening hours".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Returns the open periods between `start` and `end`, in chronological order.
+    /// Opening hours, breaks and holidays are wall-clock values in `time_zone`.
+    pub fn open_periods(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        time_zone: &Tz,
+    ) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        let mut breaks = self.breaks.clone();
+        breaks.sort_by_key(|b| b.start);
+
+        let mut periods = vec![];
+        let mut day = start.with_timezone(time_zone).date_naive();
+        let last_day = end.with_timezone(time_zone).date_naive();
+
+        while day <= last_day {
+            let is_closed =
+                self.excluded_weekdays.contains(&day.weekday()) || self.holidays.contains(&day);
+
+            if !is_closed {
+                let mut push_period = |from: NaiveTime, to: NaiveTime| {
+                    let local = |time: NaiveTime| {
+                        time_zone
+                            .from_local_datetime(&day.and_time(time))
+                            .earliest()
+                            .map(|v| v.with_timezone(&Utc))
+                    };
+                    let (Some(period_start), Some(period_end)) = (local(from), local(to)) else {
+                        return;
+                    };
+                    let period_start = period_start.max(start);
+                    let period_end = period_end.min(end);
+                    if period_start < period_end {
+                        periods.push((period_start, period_end));
+                    }
+                };
+
+                let mut from = self.opens_at;
+                for b in breaks.iter() {
+                    if b.start > from {
+                        push_period(from, b.start);
+                    }
+                    from = from.max(b.end);
+                }
+                if self.closes_at > from {
+                    push_period(from, self.closes_at);
+                }
+            }
+
+            day += Duration::days(1);
+        }
+
+        periods
+    }
+}
@@ -1,9 +1,15 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 
-use self::status::FormStatus;
+use self::{calendar::WorkingCalendar, status::FormStatus};
+pub mod calendar;
+pub mod shift;
+pub mod slot;
 pub mod status;
 
+pub const DEFAULT_TIME_ZONE: Tz = Tz::Europe__Kyiv;
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Form {
@@ -16,4 +22,9 @@ pub struct Form {
     pub end_date: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
     pub exclude_form_ids: Vec<String>,
+    pub auto_schedule: bool,
+    pub calendar: Option<WorkingCalendar>,
+    pub time_zone: Tz,
+    pub local_start_date: NaiveDateTime,
+    pub local_end_date: NaiveDateTime,
 }
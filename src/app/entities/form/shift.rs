@@ -0,0 +1,15 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FormShift {
+    pub id: i32,
+    pub form_id: String,
+    pub shift_seconds: i64,
+    pub reason: Option<String>,
+    pub submissions: u32,
+    pub user_id: Option<String>,
+    pub user_email: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
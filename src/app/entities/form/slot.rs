@@ -0,0 +1,14 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FormSlot {
+    pub id: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub local_starts_at: NaiveDateTime,
+    pub local_ends_at: NaiveDateTime,
+    pub capacity: u16,
+    pub occupied: u16,
+}
@@ -8,6 +8,15 @@ pub enum FormStatus {
     Close,
 }
 
+impl FormStatus {
+    pub fn can_transition_to(&self, next: &FormStatus) -> bool {
+        matches!(
+            (self, next),
+            (FormStatus::Draft, FormStatus::Open) | (FormStatus::Open, FormStatus::Close)
+        )
+    }
+}
+
 impl FromStr for FormStatus {
     type Err = ();
 
@@ -39,3 +48,33 @@ impl Serialize for FormStatus {
         serializer.serialize_str(&self.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::FormStatus::{self, *};
+
+    const ALL: [FormStatus; 3] = [Draft, Open, Close];
+
+    #[test]
+    fn allows_only_draft_to_open_to_close() {
+        for from in &ALL {
+            for next in &ALL {
+                let expected = matches!((from, next), (Draft, Open) | (Open, Close));
+                assert_eq!(
+                    from.can_transition_to(next),
+                    expected,
+                    "{} -> {}",
+                    from,
+                    next
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn close_is_terminal() {
+        for next in &ALL {
+            assert!(!Close.can_transition_to(next), "close -> {}", next);
+        }
+    }
+}
@@ -1,4 +1,9 @@
+pub mod api_key;
+pub mod audit;
 pub mod form;
+pub mod oidc;
 pub mod respondent;
+pub mod role;
+pub mod session;
 pub mod submission;
 pub mod user;
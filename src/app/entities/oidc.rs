@@ -0,0 +1,6 @@
+/// What a pending single sign-on keeps until the identity provider redirects back.
+#[derive(Debug, Clone)]
+pub struct OidcLogin {
+    pub nonce: String,
+    pub code_verifier: String,
+}
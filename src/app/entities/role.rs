@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Admin,
+    Coordinator,
+    Registrar,
+    Viewer,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    ViewForms,
+    ManageForms,
+    ViewRespondents,
+    EditRespondents,
+    DeleteRespondents,
+    ViewSubmissions,
+    EditSubmissions,
+    DeleteSubmissions,
+    ManageUsers,
+}
+
+impl Role {
+    pub fn can(&self, permission: &Permission) -> bool {
+        match self {
+            Role::Admin => true,
+            Role::Coordinator => *permission != Permission::ManageUsers,
+            Role::Registrar => matches!(
+                permission,
+                Permission::ViewForms
+                    | Permission::ViewRespondents
+                    | Permission::EditRespondents
+                    | Permission::ViewSubmissions
+                    | Permission::EditSubmissions
+            ),
+            Role::Viewer => matches!(
+                permission,
+                Permission::ViewForms | Permission::ViewRespondents | Permission::ViewSubmissions
+            ),
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Role, Self::Err> {
+        match input {
+            "admin" => Ok(Role::Admin),
+            "coordinator" => Ok(Role::Coordinator),
+            "registrar" => Ok(Role::Registrar),
+            "viewer" => Ok(Role::Viewer),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Role::Admin => write!(f, "admin"),
+            Role::Coordinator => write!(f, "coordinator"),
+            Role::Registrar => write!(f, "registrar"),
+            Role::Viewer => write!(f, "viewer"),
+        }
+    }
+}
+
+impl FromStr for Permission {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Permission, Self::Err> {
+        match input {
+            "view_forms" => Ok(Permission::ViewForms),
+            "manage_forms" => Ok(Permission::ManageForms),
+            "view_respondents" => Ok(Permission::ViewRespondents),
+            "edit_respondents" => Ok(Permission::EditRespondents),
+            "delete_respondents" => Ok(Permission::DeleteRespondents),
+            "view_submissions" => Ok(Permission::ViewSubmissions),
+            "edit_submissions" => Ok(Permission::EditSubmissions),
+            "delete_submissions" => Ok(Permission::DeleteSubmissions),
+            "manage_users" => Ok(Permission::ManageUsers),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Permission::ViewForms => write!(f, "view_forms"),
+            Permission::ManageForms => write!(f, "manage_forms"),
+            Permission::ViewRespondents => write!(f, "view_respondents"),
+            Permission::EditRespondents => write!(f, "edit_respondents"),
+            Permission::DeleteRespondents => write!(f, "delete_respondents"),
+            Permission::ViewSubmissions => write!(f, "view_submissions"),
+            Permission::EditSubmissions => write!(f, "edit_submissions"),
+            Permission::DeleteSubmissions => write!(f, "delete_submissions"),
+            Permission::ManageUsers => write!(f, "manage_users"),
+        }
+    }
+}
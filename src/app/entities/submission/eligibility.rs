@@ -0,0 +1,17 @@
+use serde::Serialize;
+
+/// A submission of the respondent in a form the booked one excludes.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EligibilityConflict {
+    pub form_id: String,
+    pub form_name: String,
+    pub submission_id: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Eligibility {
+    pub eligible: bool,
+    pub conflicts: Vec<EligibilityConflict>,
+}
@@ -0,0 +1,16 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::status::SubmissionStatus;
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusChange {
+    pub id: i32,
+    pub submission_id: String,
+    pub old_status: Option<SubmissionStatus>,
+    pub new_status: SubmissionStatus,
+    pub user_id: Option<String>,
+    pub user_email: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
@@ -1,10 +1,14 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 use self::status::SubmissionStatus;
 
 use super::{form::Form, respondent::Respondent};
+pub mod eligibility;
+pub mod history;
+pub mod reflow;
 pub mod status;
+pub mod waitlist;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -13,7 +17,9 @@ pub struct Submission {
     pub form: Form,
     pub respondent: Respondent,
     pub arrival_date: DateTime<Utc>,
+    pub local_arrival_date: NaiveDateTime,
     pub sub_order: u32,
+    pub slot_id: Option<String>,
     pub status: SubmissionStatus,
     pub created_at: DateTime<Utc>,
 }
@@ -0,0 +1,20 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A submission that got a new place or arrival date after a reflow.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SubmissionMove {
+    pub submission_id: String,
+    pub respondent_id: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub phone: String,
+    pub old_order: u32,
+    pub new_order: u32,
+    pub old_arrival_date: DateTime<Utc>,
+    pub new_arrival_date: DateTime<Utc>,
+    pub local_old_arrival_date: NaiveDateTime,
+    pub local_new_arrival_date: NaiveDateTime,
+    pub shift_seconds: i64,
+}
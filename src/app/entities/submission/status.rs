@@ -6,6 +6,31 @@ pub enum SubmissionStatus {
     Received,
     Confirmed,
     Completed,
+    Cancelled,
+    NoShow,
+}
+
+impl SubmissionStatus {
+    pub fn can_transition_to(&self, next: &SubmissionStatus) -> bool {
+        match self {
+            SubmissionStatus::Received => matches!(
+                next,
+                SubmissionStatus::Confirmed
+                    | SubmissionStatus::Completed
+                    | SubmissionStatus::Cancelled
+                    | SubmissionStatus::NoShow
+            ),
+            SubmissionStatus::Confirmed => matches!(
+                next,
+                SubmissionStatus::Completed
+                    | SubmissionStatus::Cancelled
+                    | SubmissionStatus::NoShow
+            ),
+            // A respondent who missed the slot may still show up later the same day.
+            SubmissionStatus::NoShow => next == &SubmissionStatus::Completed,
+            SubmissionStatus::Completed | SubmissionStatus::Cancelled => false,
+        }
+    }
 }
 
 impl FromStr for SubmissionStatus {
@@ -16,6 +41,8 @@ impl FromStr for SubmissionStatus {
             "received" => Ok(SubmissionStatus::Received),
             "confirmed" => Ok(SubmissionStatus::Confirmed),
             "completed" => Ok(SubmissionStatus::Completed),
+            "cancelled" => Ok(SubmissionStatus::Cancelled),
+            "no_show" => Ok(SubmissionStatus::NoShow),
             _ => Err(()),
         }
     }
@@ -27,6 +54,8 @@ impl fmt::Display for SubmissionStatus {
             SubmissionStatus::Received => write!(f, "received"),
             SubmissionStatus::Confirmed => write!(f, "confirmed"),
             SubmissionStatus::Completed => write!(f, "completed"),
+            SubmissionStatus::Cancelled => write!(f, "cancelled"),
+            SubmissionStatus::NoShow => write!(f, "no_show"),
         }
     }
 }
@@ -39,3 +68,49 @@ impl Serialize for SubmissionStatus {
         serializer.serialize_str(&self.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::SubmissionStatus::{self, *};
+
+    const ALL: [SubmissionStatus; 5] = [Received, Confirmed, Completed, Cancelled, NoShow];
+
+    fn allowed(from: &SubmissionStatus) -> Vec<SubmissionStatus> {
+        match from {
+            Received => vec![Confirmed, Completed, Cancelled, NoShow],
+            Confirmed => vec![Completed, Cancelled, NoShow],
+            NoShow => vec![Completed],
+            Completed | Cancelled => vec![],
+        }
+    }
+
+    #[test]
+    fn allows_only_listed_transitions() {
+        for from in &ALL {
+            for next in &ALL {
+                assert_eq!(
+                    from.can_transition_to(next),
+                    allowed(from).contains(next),
+                    "{} -> {}",
+                    from,
+                    next
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn cancelled_and_completed_are_terminal() {
+        for next in &ALL {
+            assert!(!Cancelled.can_transition_to(next), "cancelled -> {}", next);
+            assert!(!Completed.can_transition_to(next), "completed -> {}", next);
+        }
+    }
+
+    #[test]
+    fn no_show_can_still_complete() {
+        assert!(NoShow.can_transition_to(&Completed));
+        assert!(!NoShow.can_transition_to(&Confirmed));
+        assert!(!NoShow.can_transition_to(&Received));
+    }
+}
@@ -0,0 +1,31 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::eligibility::EligibilityConflict;
+use crate::app::entities::respondent::Respondent;
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WaitlistEntry {
+    pub id: String,
+    pub form_id: String,
+    pub respondent: Respondent,
+    pub position: u32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Result of a booking: a submission while the form has free places,
+/// otherwise an entry at the end of the form waitlist.
+#[derive(Debug)]
+pub enum Booking {
+    Submission(String),
+    Waitlist(String),
+}
+
+/// What came of a booking request. It is turned down while the respondent
+/// holds a place in a form the booked one excludes.
+#[derive(Debug)]
+pub enum BookingOutcome {
+    Booked(Booking),
+    Excluded(Vec<EligibilityConflict>),
+}
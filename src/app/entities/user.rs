@@ -1,10 +1,13 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use super::role::Role;
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct UserToken {
-    pub token: String,
     pub used_for: String,
+    pub family: Option<String>,
+    pub used_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -12,9 +15,14 @@ pub struct UserToken {
 pub struct User {
     pub id: String,
     pub email: String,
+    pub role: Role,
     #[serde(skip_serializing)]
     pub password_alg: String,
     #[serde(skip_serializing)]
     pub password_hash: String,
+    pub disabled_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing)]
+    pub totp_secret: Option<String>,
+    pub totp_enabled_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
 }
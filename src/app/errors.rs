@@ -6,11 +6,21 @@ pub struct FieldError {
     pub field: String,
 }
 
+#[derive(Debug, PartialEq)]
+pub enum ErrorKind {
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    TooManyRequests,
+}
+
 #[derive(Debug, Serialize)]
 pub struct BaseError {
     pub message: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub fields: Option<Vec<FieldError>>,
+    #[serde(skip)]
+    pub kind: ErrorKind,
 }
 
 impl BaseError {
@@ -18,6 +28,43 @@ impl BaseError {
         Self {
             message: msg,
             fields: None,
+            kind: ErrorKind::BadRequest,
+        }
+    }
+
+    /// The credentials presented are invalid, expired or revoked.
+    pub fn unauthorized(msg: String) -> Self {
+        Self {
+            message: msg,
+            fields: None,
+            kind: ErrorKind::Unauthorized,
         }
     }
-}
\ No newline at end of file
+
+    pub fn forbidden() -> Self {
+        Self {
+            message: "You don't have permission to do this".to_string(),
+            fields: None,
+            kind: ErrorKind::Forbidden,
+        }
+    }
+
+    pub fn too_many_requests() -> Self {
+        Self {
+            message: "Too many failed attempts, try again later".to_string(),
+            fields: None,
+            kind: ErrorKind::TooManyRequests,
+        }
+    }
+
+    pub fn invalid_transition(field: &str, from: &str, to: &str) -> Self {
+        Self {
+            message: "Invalid status transition".to_string(),
+            fields: Some(vec![FieldError {
+                field: field.to_string(),
+                message: format!("Status can't be changed from {} to {}", from, to),
+            }]),
+            kind: ErrorKind::BadRequest,
+        }
+    }
+}
@@ -1,6 +1,6 @@
 pub mod config;
 pub mod entities;
-mod errors;
+pub mod errors;
 pub mod services;
 pub mod traits;
 mod types;
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::app::{
+    entities::{api_key::ApiKey, role::Permission},
+    errors::BaseError,
+    traits::repositories::user::TUserRepositories,
+    utils::{
+        api_key::{generate, hash},
+        validate::validate,
+    },
+};
+
+use super::current_user::CurrentUser;
+
+#[derive(Debug, Validate, Deserialize)]
+pub struct CreateInputData {
+    #[validate(length(min = 1, max = 64, message = "Name should be from 1 to 64 characters"))]
+    pub name: String,
+    #[validate(length(min = 1, message = "Choose at least one permission"))]
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedApiKey {
+    pub id: String,
+    pub key: String,
+}
+
+pub struct ApiKeyService<'a> {
+    user_rep: &'a (dyn TUserRepositories + Send + Sync),
+    current: &'a CurrentUser,
+}
+
+impl<'a> ApiKeyService<'a> {
+    pub fn new(
+        user_rep: &'a (dyn TUserRepositories + Send + Sync),
+        current: &'a CurrentUser,
+    ) -> Self {
+        Self { user_rep, current }
+    }
+
+    pub async fn get(&self) -> Result<Vec<ApiKey>, BaseError> {
+        let _ = match self.current.authorize(Permission::ManageUsers) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        Ok(self.user_rep.find_api_keys().await)
+    }
+
+    /// The key is returned only here, it can't be shown again later.
+    pub async fn create(&self, data: CreateInputData) -> Result<CreatedApiKey, BaseError> {
+        match validate(&data) {
+            Ok(_) => (),
+            Err(e) => return Err(e),
+        };
+
+        let user = match self.current.authorize(Permission::ManageUsers) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        let (key, prefix) = generate();
+        let mut permissions: Vec<String> = data.permissions.iter().map(|p| p.to_string()).collect();
+        permissions.sort();
+        permissions.dedup();
+
+        let res = self
+            .user_rep
+            .insert_api_key(&user.id, &data.name, &prefix, &hash(&key), permissions)
+            .await;
+
+        match res {
+            Ok(id) => Ok(CreatedApiKey { id, key }),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+
+    pub async fn revoke(&self, id: &str) -> Result<(), BaseError> {
+        let user = match self.current.authorize(Permission::ManageUsers) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        match self.user_rep.revoke_api_key(id, &user.id).await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(BaseError::new("API key not found".to_string())),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::app::{
+    entities::{audit::AuditEntry, role::Permission},
+    errors::BaseError,
+    traits::repositories::audit::TAuditRepositories,
+    utils::validate::validate,
+};
+
+use super::current_user::CurrentUser;
+
+const DEFAULT_LIMIT: u16 = 100;
+
+#[derive(Debug, Validate, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetQuery {
+    entity: Option<String>,
+    entity_id: Option<String>,
+    actor_id: Option<String>,
+    /// Inclusive.
+    from: Option<DateTime<Utc>>,
+    /// Exclusive.
+    to: Option<DateTime<Utc>>,
+    #[validate(range(min = 1, max = 1000, message = "Limit should be from 1 to 1000"))]
+    limit: Option<u16>,
+}
+
+pub struct AuditService<'a> {
+    audit_rep: &'a (dyn TAuditRepositories + Send + Sync),
+    current: &'a CurrentUser,
+}
+
+impl<'a> AuditService<'a> {
+    pub fn new(
+        audit_rep: &'a (dyn TAuditRepositories + Send + Sync),
+        current: &'a CurrentUser,
+    ) -> Self {
+        Self { audit_rep, current }
+    }
+
+    /// Newest entries first.
+    pub async fn get(&self, query: GetQuery) -> Result<Vec<AuditEntry>, BaseError> {
+        match validate(&query) {
+            Ok(_) => (),
+            Err(e) => return Err(e),
+        };
+
+        let _ = match self.current.authorize(Permission::ManageUsers) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        Ok(self
+            .audit_rep
+            .find(
+                query.entity,
+                query.entity_id,
+                query.actor_id,
+                query.from,
+                query.to,
+                query.limit.unwrap_or(DEFAULT_LIMIT) as i64,
+            )
+            .await)
+    }
+}
@@ -1,22 +1,33 @@
+use super::{
+    current_user::{AccessToken, CurrentUser},
+    two_factor::{check_code, confirm_setup, is_required, TotpSetup},
+};
 use crate::app::{
     config::Config,
+    entities::{role::Role, user::User},
     errors::BaseError,
     traits::repositories::user::TUserRepositories,
     utils::{
-        hash::{hash_pwd, verify_pwd},
-        jwt::JWT,
+        api_key::{hash, is_api_key},
+        hash::{hash_pwd, needs_rehash, verify_pwd_or_dummy},
+        jwt::{ClaimType, JWT},
         validate::validate,
     },
 };
-use serde::Deserialize;
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
 use validator::Validate;
 
+/// Failed sign ins allowed before the backoff starts.
+const BACKOFF_AFTER: i32 = 3;
+
 #[derive(Debug, Validate, Deserialize)]
 pub struct CreateInputData {
     #[validate(email(message = "Email is invalid"))]
     pub email: String,
     #[validate(length(min = 6, message = "Password is invalid"))]
     pub password: String,
+    pub role: Role,
 }
 
 #[derive(Debug, Validate, Deserialize)]
@@ -25,18 +36,76 @@ pub struct LoginInputData {
     email: String,
     #[validate(length(min = 6, message = "Password is invalid"))]
     password: String,
+    #[validate(length(max = 128, message = "Device label is too long"))]
+    device: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshInputData {
+    #[serde(rename = "refreshToken")]
+    refresh_token: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthTokens {
+    pub access_token: String,
+    pub refresh_token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovery_codes: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TwoFactorChallenge {
+    pub two_factor_token: String,
+    /// The user has to enroll first, the setup comes with a mailed link.
+    pub enrollment_required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub setup: Option<TotpSetup>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum SignIn {
+    Tokens(AuthTokens),
+    TwoFactor(TwoFactorChallenge),
+}
+
+#[derive(Debug, Validate, Deserialize)]
+pub struct TwoFactorInputData {
+    token: String,
+    #[validate(length(min = 6, max = 32, message = "Code is invalid"))]
+    code: String,
+    #[validate(length(max = 128, message = "Device label is too long"))]
+    device: Option<String>,
 }
 
 #[derive(Debug, Validate, Deserialize)]
 pub struct EmailInputData {
     #[validate(email(message = "Email is invalid"))]
-    email: String,
+    pub email: String,
 }
 
 #[derive(Debug, Validate, Deserialize)]
 pub struct PasswordInputData {
     #[validate(length(min = 6, message = "Password is invalid"))]
-    password: String,
+    pub password: String,
+}
+
+#[derive(Debug, Validate, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangePasswordInputData {
+    pub current_password: String,
+    #[validate(length(min = 6, message = "Password is invalid"))]
+    pub password: String,
+}
+
+#[derive(Debug, Validate, Deserialize)]
+pub struct ResetPasswordInputData {
+    pub token: String,
+    #[validate(length(min = 6, message = "Password is invalid"))]
+    pub password: String,
 }
 
 pub struct AuthService<'a> {
@@ -49,7 +118,12 @@ impl<'a> AuthService<'a> {
         Self { user_rep, config }
     }
 
-    pub async fn create(&self, signup_data: CreateInputData) -> Result<String, BaseError> {
+    /// `user_id` is the admin who adds the user, None when the app creates it.
+    pub async fn create(
+        &self,
+        signup_data: CreateInputData,
+        user_id: Option<&str>,
+    ) -> Result<String, BaseError> {
         match validate(&signup_data) {
             Ok(_) => (),
             Err(e) => return Err(e),
@@ -60,14 +134,19 @@ impl<'a> AuthService<'a> {
             return Err(BaseError::new("The email already using".to_string()));
         }
 
-        let (password_alg, password_hash) = match hash_pwd(&signup_data.password) {
+        let (password_alg, password_hash) = match hash_pwd(self.config, &signup_data.password) {
             Ok(res) => res,
             Err(e) => return Err(BaseError::new(e)),
         };
 
-        let result = self
-            .user_rep
-            .insert(&signup_data.email, &password_hash, &password_alg);
+        let role = signup_data.role.to_string();
+        let result = self.user_rep.insert(
+            &signup_data.email,
+            &password_hash,
+            &password_alg,
+            &role,
+            user_id,
+        );
 
         match result.await {
             Ok(id) => Ok(id),
@@ -75,50 +154,438 @@ impl<'a> AuthService<'a> {
         }
     }
 
-    pub async fn login(&self, data: LoginInputData) -> Result<String, BaseError> {
+    /// Signs in on a new device. Sessions on other devices stay signed in.
+    /// Users with 2FA, or who have to enroll in it, get a challenge to finish
+    /// with `verify_two_factor` instead of tokens.
+    pub async fn login(
+        &self,
+        data: LoginInputData,
+        ip: Option<String>,
+        user_agent: Option<String>,
+    ) -> Result<SignIn, BaseError> {
         match validate(&data) {
             Ok(_) => (),
             Err(e) => return Err(e),
         };
+
+        if self
+            .user_rep
+            .find_login_lock(&data.email, &ip)
+            .await
+            .is_some()
+        {
+            return Err(BaseError::too_many_requests());
+        }
+
         let user_result = self.user_rep.find_by_email(&data.email).await;
 
+        let hash = user_result.as_ref().map(|user| user.password_hash.as_str());
+        let verified = verify_pwd_or_dummy(self.config, hash, &data.password);
         let user = match user_result {
-            Some(user) => user,
-            None => return Err(BaseError::new("User not found".to_string())),
+            Some(user) if verified => user,
+            _ => {
+                return match self.login_failed(&data.email, &ip).await {
+                    Ok(_) => Err(BaseError::new("Email or password is incorrect".to_string())),
+                    Err(err) => Err(err),
+                }
+            }
         };
 
-        if !verify_pwd(&user.password_hash, &data.password) {
-            return Err(BaseError::new("Password is incorrect".to_string()));
+        // A disabled account is left as it is, lockout counters and hash included.
+        if user.disabled_at.is_some() {
+            return Err(BaseError::new("User is disabled".to_string()));
         }
 
-        let access_token = match JWT::new(&self.config).login(&user) {
-            Ok(token) => token,
-            Err(err) => return Err(BaseError::new(err)),
+        if let Err(err) = self.user_rep.clear_login_failures(&data.email).await {
+            return Err(BaseError::new(err));
+        }
+
+        if needs_rehash(self.config, &user.password_alg, &user.password_hash) {
+            self.rehash(&user, &data.password).await;
+        }
+
+        self.sign_in(&user, data.device, ip, user_agent).await
+    }
+
+    /// Continues a sign in once the user proved who they are, by password or
+    /// through the identity provider.
+    pub async fn sign_in(
+        &self,
+        user: &User,
+        device: Option<String>,
+        ip: Option<String>,
+        user_agent: Option<String>,
+    ) -> Result<SignIn, BaseError> {
+        // The password alone doesn't reveal the secret of a new enrollment,
+        // someone who only knows it could register their own authenticator.
+        let enrollment_required = match user.totp_enabled_at {
+            Some(_) => false,
+            None if is_required(self.user_rep).await => true,
+            None => {
+                return match self.start_session(user, device, ip, user_agent).await {
+                    Ok(tokens) => Ok(SignIn::Tokens(tokens)),
+                    Err(err) => Err(err),
+                }
+            }
         };
 
-        let res = self
+        match JWT::new(self.config).two_factor(user) {
+            Ok(token) => Ok(SignIn::TwoFactor(TwoFactorChallenge {
+                two_factor_token: token,
+                enrollment_required,
+                setup: None,
+            })),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+
+    /// Finishes a sign in with a code from the authenticator app or a
+    /// recovery code. During enrollment the first code turns 2FA on and the
+    /// recovery codes come back with the tokens.
+    pub async fn verify_two_factor(
+        &self,
+        data: TwoFactorInputData,
+        ip: Option<String>,
+        user_agent: Option<String>,
+    ) -> Result<AuthTokens, BaseError> {
+        match validate(&data) {
+            Ok(_) => (),
+            Err(e) => return Err(e),
+        };
+
+        let user_id = match JWT::new(self.config).parse(&data.token, Some(ClaimType::TwoFactor)) {
+            Ok(claim) => claim.sub,
+            Err(_) => return Err(BaseError::new("Token is expired".to_string())),
+        };
+
+        let user = match self.user_rep.find_by_id(&user_id).await {
+            Some(user) if user.disabled_at.is_none() => user,
+            _ => return Err(BaseError::new("Token is expired".to_string())),
+        };
+
+        if self
             .user_rep
-            .upsert_user_token(&user.id, &access_token, "WEB");
+            .find_login_lock(&user.email, &ip)
+            .await
+            .is_some()
+        {
+            return Err(BaseError::too_many_requests());
+        }
+
+        let recovery_codes = match user.totp_enabled_at {
+            Some(_) => match check_code(self.user_rep, &user, &data.code).await {
+                Ok(true) => None,
+                Ok(false) => return self.two_factor_failed(&user, &ip).await,
+                Err(err) => return Err(BaseError::new(err)),
+            },
+            None => match confirm_setup(self.config, self.user_rep, &user, &data.code).await {
+                Ok(codes) => Some(codes.recovery_codes),
+                Err(_) => return self.two_factor_failed(&user, &ip).await,
+            },
+        };
+
+        if let Err(err) = self.user_rep.clear_login_failures(&user.email).await {
+            return Err(BaseError::new(err));
+        }
+
+        match self.start_session(&user, data.device, ip, user_agent).await {
+            Ok(tokens) => Ok(AuthTokens {
+                recovery_codes,
+                ..tokens
+            }),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Exchanges a refresh token for a new pair. Every refresh token works once,
+    /// presenting it again revokes the whole family issued since the sign in.
+    pub async fn refresh(&self, data: RefreshInputData) -> Result<AuthTokens, BaseError> {
+        let user_id =
+            match JWT::new(self.config).parse(&data.refresh_token, Some(ClaimType::Refresh)) {
+                Ok(claim) => claim.sub,
+                Err(e) => return Err(BaseError::unauthorized(e)),
+            };
+
+        let stored = match self.user_rep.find_token(&data.refresh_token).await {
+            Some(token) if token.used_for == "REFRESH" => token,
+            _ => return Err(BaseError::unauthorized("Token is expired".to_string())),
+        };
+
+        if stored.used_at.is_some() {
+            return self.revoke_reused(&stored.family).await;
+        }
+
+        let user = match self.user_rep.find_by_id(&user_id).await {
+            Some(user) if user.disabled_at.is_none() => user,
+            _ => return Err(BaseError::unauthorized("User not found".to_string())),
+        };
+
+        // Sessions started before 2FA became mandatory end with the access token.
+        if user.totp_enabled_at.is_none() && is_required(self.user_rep).await {
+            return Err(BaseError::unauthorized(
+                "Two-factor authentication is required, sign in again".to_string(),
+            ));
+        }
+
+        let tokens = match self.issue_tokens(&user) {
+            Ok(tokens) => tokens,
+            Err(err) => return Err(err),
+        };
+
+        let res = self.user_rep.rotate_refresh_token(
+            &data.refresh_token,
+            &tokens.access_token,
+            &tokens.refresh_token,
+            Duration::days(self.config.refresh_token_ttl),
+        );
 
         match res.await {
-            Ok(_) => Ok(access_token),
+            Ok(true) => Ok(tokens),
+            Ok(false) => self.revoke_reused(&stored.family).await,
             Err(err) => Err(BaseError::new(err)),
         }
     }
 
+    /// Resolves the caller of a request from an access token or an API key.
+    /// API keys act for the user who created them.
+    pub async fn authenticate(&self, token: &str) -> Result<CurrentUser, BaseError> {
+        if is_api_key(token) {
+            return self.authenticate_key(token).await;
+        }
+
+        let user_id = match JWT::new(self.config).parse(token, Some(ClaimType::Login)) {
+            Ok(claim) => claim.sub,
+            Err(e) => return Err(BaseError::new(e)),
+        };
+
+        let user = match self.user_rep.find_by_id(&user_id).await {
+            Some(user) => user,
+            None => return Err(BaseError::new("User not found".to_string())),
+        };
+        if user.disabled_at.is_some() {
+            return Err(BaseError::new("User is disabled".to_string()));
+        }
+
+        let stored = match self.user_rep.find_token(token).await {
+            Some(stored) if stored.used_for == "WEB" => stored,
+            _ => return Err(BaseError::new("Token is expired".to_string())),
+        };
+
+        self.user_rep.touch_token(token).await;
+        Ok(CurrentUser {
+            user,
+            access_token: Some(AccessToken {
+                token: token.to_string(),
+                family: stored.family,
+            }),
+            key_permissions: None,
+        })
+    }
+
     pub async fn revoke_token(&self, token: &str) -> Result<(), BaseError> {
-        let user_id = match JWT::new(&self.config).parse(token, None) {
+        let user_id = match JWT::new(self.config).parse(token, None) {
             Ok(claim) => claim.sub,
             Err(e) => return Err(BaseError::new(e)),
         };
 
-        match self
+        let family = self.user_rep.find_token(token).await.and_then(|t| t.family);
+        let res = match family {
+            Some(family) => self.user_rep.remove_token_family(&family).await,
+            None => {
+                self.user_rep
+                    .remove_user_tokens(&user_id, vec![token])
+                    .await
+            }
+        };
+
+        match res {
+            Ok(_) => Ok(()),
+            Err(e) => Err(BaseError::new(e.to_string())),
+        }
+    }
+
+    /// Counts the failure against the email and the client IP. After a few
+    /// failures every next attempt waits twice as long, until the lockout.
+    async fn login_failed(&self, email: &str, ip: &Option<String>) -> Result<(), BaseError> {
+        let max_attempts = self.config.login_max_attempts;
+        let lockout = Duration::minutes(self.config.login_lockout);
+        let delay = move |failures: i32| login_delay(failures, max_attempts, lockout);
+
+        let res = self
             .user_rep
-            .remove_user_tokens(&user_id, vec![token])
-            .await
-        {
+            .record_login_failure(email, ip, lockout, &delay)
+            .await;
+
+        match res {
             Ok(_) => Ok(()),
-            Err(e) => return Err(BaseError::new(e.to_string())),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+
+    /// Stores the password again with the current Argon2 parameters. A failure
+    /// leaves the old hash in place, it still works.
+    async fn rehash(&self, user: &User, password: &str) {
+        if let Ok((password_alg, password_hash)) = hash_pwd(self.config, password) {
+            let _ = self
+                .user_rep
+                .rehash_password(&user.id, &user.password_hash, &password_hash, &password_alg)
+                .await;
+        }
+    }
+
+    async fn authenticate_key(&self, key: &str) -> Result<CurrentUser, BaseError> {
+        let key = match self.user_rep.find_api_key_by_hash(&hash(key)).await {
+            Some(key) => key,
+            None => return Err(BaseError::new("API key is not valid".to_string())),
+        };
+
+        let user = match self.user_rep.find_by_id(&key.user_id).await {
+            Some(user) if user.disabled_at.is_none() => user,
+            _ => return Err(BaseError::new("API key is not valid".to_string())),
+        };
+
+        self.user_rep.touch_api_key(&key.id).await;
+        Ok(CurrentUser {
+            user,
+            access_token: None,
+            key_permissions: Some(key.permissions),
+        })
+    }
+
+    async fn two_factor_failed(
+        &self,
+        user: &User,
+        ip: &Option<String>,
+    ) -> Result<AuthTokens, BaseError> {
+        match self.login_failed(&user.email, ip).await {
+            Ok(_) => Err(BaseError::new("Code is invalid".to_string())),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn start_session(
+        &self,
+        user: &User,
+        device: Option<String>,
+        ip: Option<String>,
+        user_agent: Option<String>,
+    ) -> Result<AuthTokens, BaseError> {
+        let tokens = match self.issue_tokens(user) {
+            Ok(tokens) => tokens,
+            Err(err) => return Err(err),
+        };
+
+        let device = device
+            .or(user_agent)
+            .map(|v| v.chars().take(128).collect::<String>());
+        let res = self.user_rep.insert_token_family(
+            &user.id,
+            &tokens.access_token,
+            &tokens.refresh_token,
+            &device,
+            &ip,
+            Duration::days(self.config.refresh_token_ttl),
+        );
+
+        match res.await {
+            Ok(_) => Ok(tokens),
+            Err(err) => Err(BaseError::new(err)),
         }
     }
+
+    fn issue_tokens(&self, user: &User) -> Result<AuthTokens, BaseError> {
+        let jwt = JWT::new(self.config);
+        let access_token = match jwt.login(user) {
+            Ok(token) => token,
+            Err(err) => return Err(BaseError::new(err)),
+        };
+        let refresh_token = match jwt.refresh(user) {
+            Ok(token) => token,
+            Err(err) => return Err(BaseError::new(err)),
+        };
+
+        Ok(AuthTokens {
+            access_token,
+            refresh_token,
+            recovery_codes: None,
+        })
+    }
+
+    async fn revoke_reused(&self, family: &Option<String>) -> Result<AuthTokens, BaseError> {
+        if let Some(family) = family {
+            if let Err(err) = self.user_rep.remove_token_family(family).await {
+                return Err(BaseError::new(err));
+            }
+        }
+        Err(BaseError::unauthorized(
+            "Token has already been used, sign in again".to_string(),
+        ))
+    }
+}
+
+/// How long sign ins wait after `failures` failed attempts in a row.
+fn login_delay(failures: i32, max_attempts: i32, lockout: Duration) -> Option<Duration> {
+    if failures >= max_attempts {
+        Some(lockout)
+    } else if failures >= BACKOFF_AFTER {
+        let seconds = 1_i64 << (failures - BACKOFF_AFTER).min(30);
+        Some(Duration::seconds(seconds).min(lockout))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_delay_before_the_backoff() {
+        let lockout = Duration::minutes(15);
+        for failures in 0..BACKOFF_AFTER {
+            assert_eq!(login_delay(failures, 10, lockout), None);
+        }
+    }
+
+    #[test]
+    fn delay_doubles_from_the_backoff_on() {
+        let lockout = Duration::minutes(15);
+        assert_eq!(
+            login_delay(BACKOFF_AFTER, 10, lockout),
+            Some(Duration::seconds(1))
+        );
+        assert_eq!(
+            login_delay(BACKOFF_AFTER + 1, 10, lockout),
+            Some(Duration::seconds(2))
+        );
+        assert_eq!(
+            login_delay(BACKOFF_AFTER + 4, 10, lockout),
+            Some(Duration::seconds(16))
+        );
+    }
+
+    #[test]
+    fn locks_out_at_max_attempts() {
+        let lockout = Duration::minutes(15);
+        assert_eq!(login_delay(9, 10, lockout), Some(Duration::seconds(64)));
+        assert_eq!(login_delay(10, 10, lockout), Some(lockout));
+        assert_eq!(login_delay(11, 10, lockout), Some(lockout));
+        // A limit below the backoff locks out right away.
+        assert_eq!(login_delay(2, 2, lockout), Some(lockout));
+    }
+
+    #[test]
+    fn delay_never_exceeds_the_lockout() {
+        let lockout = Duration::minutes(15);
+        assert_eq!(login_delay(20, 100, lockout), Some(lockout));
+    }
+
+    #[test]
+    fn shift_is_capped_at_30_bits() {
+        let lockout = Duration::days(365 * 100);
+        let capped = Some(Duration::seconds(1 << 30));
+        assert_eq!(login_delay(BACKOFF_AFTER + 30, i32::MAX, lockout), capped);
+        assert_eq!(login_delay(BACKOFF_AFTER + 31, i32::MAX, lockout), capped);
+        assert_eq!(login_delay(i32::MAX - 1, i32::MAX, lockout), capped);
+    }
 }
@@ -0,0 +1,47 @@
+use crate::app::{
+    entities::{role::Permission, user::User},
+    errors::BaseError,
+};
+
+/// The caller of a request, resolved once from its access token or API key.
+#[derive(Debug, Clone)]
+pub struct CurrentUser {
+    pub user: User,
+    /// The access token and its family, when the caller signed in.
+    pub access_token: Option<AccessToken>,
+    /// What an API key is limited to, on top of the role of its creator.
+    pub key_permissions: Option<Vec<Permission>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AccessToken {
+    pub token: String,
+    pub family: Option<String>,
+}
+
+impl CurrentUser {
+    pub fn can(&self, permission: &Permission) -> bool {
+        self.user.role.can(permission)
+            && self
+                .key_permissions
+                .as_ref()
+                .is_none_or(|permissions| permissions.contains(permission))
+    }
+
+    /// Returns the user if the role, and the API key if any, grant the permission.
+    pub fn authorize(&self, permission: Permission) -> Result<&User, BaseError> {
+        match self.can(&permission) {
+            true => Ok(&self.user),
+            false => Err(BaseError::forbidden()),
+        }
+    }
+
+    /// Account settings and sessions need a signed in user, API keys can't
+    /// change them.
+    pub fn signed_in(&self) -> Result<(&User, &AccessToken), BaseError> {
+        match self.access_token {
+            Some(ref access_token) => Ok((&self.user, access_token)),
+            None => Err(BaseError::forbidden()),
+        }
+    }
+}
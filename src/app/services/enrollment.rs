@@ -0,0 +1,136 @@
+use chrono::Duration;
+use serde::Deserialize;
+
+use crate::app::{
+    config::Config,
+    entities::user::User,
+    errors::BaseError,
+    traits::{mailer::TMailer, repositories::user::TUserRepositories},
+    utils::jwt::{ClaimType, JWT},
+};
+
+use super::{
+    auth::TwoFactorChallenge,
+    two_factor::{is_required, start_setup},
+};
+
+/// Enrollment links one user can ask for within an hour.
+const LINKS_PER_USER: i32 = 3;
+
+#[derive(Debug, Deserialize)]
+pub struct EnrollmentInputData {
+    pub token: String,
+}
+
+/// Enrollment in mandatory 2FA during a sign in. The secret goes only to
+/// whoever opens the link mailed to the account.
+pub struct EnrollmentService<'a> {
+    config: &'a Config,
+    user_rep: &'a (dyn TUserRepositories + Send + Sync),
+    mailer: &'a (dyn TMailer + Send + Sync),
+}
+
+impl<'a> EnrollmentService<'a> {
+    pub fn new(
+        config: &'a Config,
+        user_rep: &'a (dyn TUserRepositories + Send + Sync),
+        mailer: &'a (dyn TMailer + Send + Sync),
+    ) -> Self {
+        Self {
+            config,
+            user_rep,
+            mailer,
+        }
+    }
+
+    /// Mails the link, `token` is the challenge of a sign in that requires
+    /// enrollment.
+    pub async fn send_link(&self, data: EnrollmentInputData) -> Result<(), BaseError> {
+        let user = match self.find_user(&data.token, ClaimType::TwoFactor).await {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        if !is_required(self.user_rep).await {
+            return Err(BaseError::new(
+                "Two-factor authentication is not required".to_string(),
+            ));
+        }
+
+        let requests = self
+            .user_rep
+            .count_reset_request("enroll", &user.id, Duration::hours(1))
+            .await;
+        match requests {
+            Ok(requests) if requests > LINKS_PER_USER => return Err(BaseError::too_many_requests()),
+            Ok(_) => (),
+            Err(err) => return Err(BaseError::new(err)),
+        };
+
+        let token = match JWT::new(self.config).two_factor_setup(&user) {
+            Ok(token) => token,
+            Err(err) => return Err(BaseError::new(err)),
+        };
+
+        let body = format!(
+            "Use the link below to set up two-factor authentication. It expires in {} minutes.\n\n{}/two-factor-setup?token={}",
+            self.config.reset_token_ttl, self.config.app_url, token
+        );
+
+        match self
+            .mailer
+            .send(&user.email, "Two-factor authentication setup", &body)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                eprintln!("Failed to send a 2FA enrollment to {}: {}", user.email, err);
+                Err(BaseError::new("Failed to send the link".to_string()))
+            }
+        }
+    }
+
+    /// Opens the mailed link. Every opening stores a new secret, the sign in
+    /// goes on with the challenge and the first code from the app.
+    pub async fn open_link(
+        &self,
+        data: EnrollmentInputData,
+    ) -> Result<TwoFactorChallenge, BaseError> {
+        let user = match self.find_user(&data.token, ClaimType::TwoFactorSetup).await {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        let setup = match start_setup(self.config, self.user_rep, &user, true).await {
+            Ok(setup) => setup,
+            Err(err) => return Err(err),
+        };
+
+        match JWT::new(self.config).two_factor(&user) {
+            Ok(token) => Ok(TwoFactorChallenge {
+                two_factor_token: token,
+                enrollment_required: true,
+                setup: Some(setup),
+            }),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+
+    async fn find_user(&self, token: &str, claim_type: ClaimType) -> Result<User, BaseError> {
+        let user_id = match JWT::new(self.config).parse(token, Some(claim_type)) {
+            Ok(claim) => claim.sub,
+            Err(_) => return Err(BaseError::new("Token is expired".to_string())),
+        };
+
+        match self.user_rep.find_by_id(&user_id).await {
+            Some(user) if user.disabled_at.is_some() => {
+                Err(BaseError::new("Token is expired".to_string()))
+            }
+            Some(user) if user.totp_enabled_at.is_some() => Err(BaseError::new(
+                "Two-factor authentication is already enabled".to_string(),
+            )),
+            Some(user) => Ok(user),
+            None => Err(BaseError::new("Token is expired".to_string())),
+        }
+    }
+}
@@ -1,15 +1,26 @@
 use crate::app::{
-    config::Config,
-    entities::form::{status::FormStatus, Form},
+    entities::{
+        form::{
+            calendar::WorkingCalendar, shift::FormShift, slot::FormSlot, status::FormStatus, Form,
+            DEFAULT_TIME_ZONE,
+        },
+        role::Permission,
+        submission::reflow::SubmissionMove,
+    },
     errors::BaseError,
-    traits::repositories::{form::TFormRepositories, user::TUserRepositories},
-    utils::validate::{validate, validate_date_not_past},
+    traits::repositories::form::TFormRepositories,
+    utils::{
+        arrival_date::{calculate_arrival_date, time_frames},
+        validate::{validate, validate_date_not_past},
+    },
 };
 use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 use serde::Deserialize;
-use validator::Validate;
+use std::borrow::Cow;
+use validator::{Validate, ValidationError};
 
-use super::user::UserService;
+use super::current_user::CurrentUser;
 
 #[derive(Debug, Validate, Deserialize)]
 pub struct CreateFromData {
@@ -28,6 +39,12 @@ pub struct CreateFromData {
     pub end_date: DateTime<Utc>,
     #[serde(rename = "excludeFormIds")]
     pub exclude_form_ids: Vec<String>,
+    #[serde(rename = "autoSchedule", default)]
+    pub auto_schedule: bool,
+    #[validate(custom(function = "validate_calendar"))]
+    pub calendar: Option<WorkingCalendar>,
+    #[serde(rename = "timeZone")]
+    pub time_zone: Option<Tz>,
 }
 
 #[derive(Debug, Validate, Deserialize)]
@@ -42,23 +59,83 @@ pub struct UpdateFromData {
     pub end_date: Option<DateTime<Utc>>,
     #[serde(rename = "excludeFormIds")]
     pub exclude_form_ids: Option<Vec<String>>,
+    #[serde(rename = "autoSchedule")]
+    pub auto_schedule: Option<bool>,
+    #[validate(custom(function = "validate_calendar"))]
+    pub calendar: Option<WorkingCalendar>,
+    #[serde(rename = "timeZone")]
+    pub time_zone: Option<Tz>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SlotData {
+    #[serde(rename = "startsAt")]
+    pub starts_at: DateTime<Utc>,
+    #[serde(rename = "endsAt")]
+    pub ends_at: DateTime<Utc>,
+    pub capacity: u16,
+}
+
+/// Either a uniform `capacity` for every time frame of the form, or an
+/// explicit list of slots with their own capacity. The limit of the form is
+/// replaced with the total capacity of the slots.
+#[derive(Debug, Deserialize)]
+pub struct SetSlotsData {
+    pub capacity: Option<u16>,
+    pub slots: Option<Vec<SlotData>>,
+}
+
+/// Moves the whole distribution by `duration` seconds, negative values move it
+/// earlier.
+#[derive(Debug, Deserialize)]
+pub struct ShiftData {
+    pub duration: i64,
+    pub reason: Option<String>,
+}
+
+fn validate_calendar(value: &Option<WorkingCalendar>) -> Result<(), ValidationError> {
+    match value {
+        None => Ok(()),
+        Some(calendar) => match calendar.validate() {
+            Ok(_) => Ok(()),
+            Err(message) => Err(ValidationError::new("").with_message(Cow::from(message))),
+        },
+    }
+}
+
+fn check_open_time(
+    calendar: &Option<WorkingCalendar>,
+    start_date: DateTime<Utc>,
+    end_date: DateTime<Utc>,
+    time_zone: &Tz,
+) -> Result<(), BaseError> {
+    match calendar {
+        Some(calendar)
+            if calendar
+                .open_periods(start_date, end_date, time_zone)
+                .is_empty() =>
+        {
+            Err(BaseError::new(
+                "Working calendar has no open time between start and end dates".to_string(),
+            ))
+        }
+        _ => Ok(()),
+    }
 }
 
 pub struct FormService<'a> {
     form_repo: &'a (dyn TFormRepositories + Send + Sync),
-    user_service: UserService<'a>,
+    current: &'a CurrentUser,
 }
 
 impl<'a> FormService<'a> {
     pub fn new(
-        config: &'a Config,
         from_repo: &'a (dyn TFormRepositories + Send + Sync),
-        user_repo: &'a (dyn TUserRepositories + Send + Sync),
-        token: &'a str,
+        current: &'a CurrentUser,
     ) -> Self {
         Self {
             form_repo: from_repo,
-            user_service: UserService::new(config, user_repo, token),
+            current,
         }
     }
 
@@ -68,21 +145,31 @@ impl<'a> FormService<'a> {
             Err(e) => return Err(e),
         };
 
-        let _ = match self.user_service.get_current_user().await {
+        let user = match self.current.authorize(Permission::ManageForms) {
             Ok(user) => user,
             Err(err) => return Err(err),
         };
 
+        let time_zone = data.time_zone.unwrap_or(DEFAULT_TIME_ZONE);
+        match check_open_time(&data.calendar, data.start_date, data.end_date, &time_zone) {
+            Ok(_) => (),
+            Err(e) => return Err(e),
+        };
+
         let status = FormStatus::Draft.to_string();
 
         let result = self.form_repo.insert(
             &data.name,
             data.limit as i32,
             &status,
-            data.start_date.naive_utc(),
-            data.end_date.naive_utc(),
+            data.start_date,
+            data.end_date,
             data.time_frame_duration as i32,
             data.exclude_form_ids,
+            data.auto_schedule,
+            data.calendar,
+            time_zone.name(),
+            &user.id,
         );
 
         match result.await {
@@ -91,13 +178,20 @@ impl<'a> FormService<'a> {
         }
     }
 
-    pub async fn update(self, id: String, data: UpdateFromData) -> Result<(), BaseError> {
+    /// Updates the form. Changing the schedule reflows the existing submissions,
+    /// the returned list tells who has been moved. A raised limit promotes people
+    /// from the waitlist.
+    pub async fn update(
+        self,
+        id: String,
+        data: UpdateFromData,
+    ) -> Result<Vec<SubmissionMove>, BaseError> {
         match validate(&data) {
             Ok(_) => (),
             Err(e) => return Err(e),
         };
 
-        let _ = match self.user_service.get_current_user().await {
+        let user = match self.current.authorize(Permission::ManageForms) {
             Ok(user) => user,
             Err(err) => return Err(err),
         };
@@ -111,29 +205,89 @@ impl<'a> FormService<'a> {
             return Err(BaseError::new("Forbidden".to_owned()));
         }
 
+        let calendar = data.calendar.clone().or(form.calendar);
+        let start_date = data.start_date.unwrap_or(form.start_date);
+        let end_date = data.end_date.unwrap_or(form.end_date);
+        let time_zone = data.time_zone.unwrap_or(form.time_zone);
+        match check_open_time(&calendar, start_date, end_date, &time_zone) {
+            Ok(_) => (),
+            Err(e) => return Err(e),
+        };
+
+        let reschedule = data.limit.is_some()
+            || data.start_date.is_some()
+            || data.end_date.is_some()
+            || data.time_frame_duration.is_some()
+            || data.calendar.is_some()
+            || data.time_zone.is_some();
+        let updated = Form {
+            limit: data.limit.unwrap_or(form.limit),
+            time_frame_duration: data.time_frame_duration.unwrap_or(form.time_frame_duration),
+            start_date,
+            end_date,
+            calendar,
+            time_zone,
+            ..form
+        };
+        let arrival_date = |order: i32| calculate_arrival_date(&updated, order as u16);
+        let reflow: Option<&(dyn Fn(i32) -> DateTime<Utc> + Send + Sync)> = match reschedule {
+            true => Some(&arrival_date),
+            false => None,
+        };
+
         let result = self.form_repo.update(
             &id,
             data.name,
             data.limit.map(|x| x as i32),
-            data.start_date.map(|x| x.naive_utc()),
-            data.end_date.map(|x| x.naive_utc()),
+            data.start_date,
+            data.end_date,
             data.time_frame_duration.map(|x| x as i32),
             None,
             data.exclude_form_ids,
+            data.auto_schedule,
+            data.calendar,
+            data.time_zone.map(|v| v.name().to_string()),
+            reflow,
+            &user.id,
         );
         match result.await {
-            Ok(()) => Ok(()),
+            Ok(moves) => Ok(moves),
             Err(err) => Err(BaseError::new(err.to_string())),
         }
     }
 
+    /// Closes the gaps left by cancelled or deleted submissions and recalculates
+    /// arrival dates for the current schedule.
+    pub async fn reflow(&self, id: &str) -> Result<Vec<SubmissionMove>, BaseError> {
+        let user = match self.current.authorize(Permission::ManageForms) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        let form = match self.get_by_id(id).await {
+            Ok(form) => form,
+            Err(err) => return Err(err),
+        };
+
+        let arrival_date = |order: i32| calculate_arrival_date(&form, order as u16);
+
+        match self
+            .form_repo
+            .reflow(&form.id, &arrival_date, &user.id)
+            .await
+        {
+            Ok(moves) => Ok(moves),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+
     pub async fn delete(&self, id: &str) -> Result<(), BaseError> {
-        let _ = match self.user_service.get_current_user().await {
+        let user = match self.current.authorize(Permission::ManageForms) {
             Ok(user) => user,
             Err(err) => return Err(err),
         };
 
-        let form = match self.get_by_id(&id).await {
+        let form = match self.get_by_id(id).await {
             Ok(form) => form,
             Err(err) => return Err(err),
         };
@@ -142,14 +296,14 @@ impl<'a> FormService<'a> {
             return Err(BaseError::new("Forbidden".to_owned()));
         }
 
-        match self.form_repo.delete(id).await {
+        match self.form_repo.delete(id, &user.id).await {
             Ok(_) => Ok(()),
             Err(err) => Err(BaseError::new(err.to_string())),
         }
     }
 
     pub async fn status(&self, id: String, value: FormStatus) -> Result<(), BaseError> {
-        let _ = match self.user_service.get_current_user().await {
+        let user = match self.current.authorize(Permission::ManageForms) {
             Ok(user) => user,
             Err(err) => return Err(err),
         };
@@ -163,35 +317,165 @@ impl<'a> FormService<'a> {
             return Ok(());
         }
 
-        if form.status == FormStatus::Draft && value != FormStatus::Open {
-            return Err(BaseError::new("Forbidden".to_owned()));
-        }
-
-        if form.status == FormStatus::Open && value != FormStatus::Close {
+        if !form.status.can_transition_to(&value) {
             return Err(BaseError::new("Forbidden".to_owned()));
         }
 
         match self
             .form_repo
-            .update(
+            .update_status(
                 &id,
-                None,
-                None,
-                None,
-                None,
-                None,
-                Some(value.to_string()),
-                None,
+                &form.status.to_string(),
+                &value.to_string(),
+                Some(&user.id),
             )
             .await
         {
-            Ok(_) => Ok(()),
+            Ok(true) => Ok(()),
+            Ok(false) => Err(BaseError::new(
+                "Form status has been changed, try again".to_string(),
+            )),
             Err(err) => Err(BaseError::new(err.to_string())),
         }
     }
 
+    pub async fn shift(&self, id: &str, data: ShiftData) -> Result<FormShift, BaseError> {
+        let user = match self.current.authorize(Permission::ManageForms) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        let form = match self.get_by_id(id).await {
+            Ok(form) => form,
+            Err(err) => return Err(err),
+        };
+
+        if form.status == FormStatus::Close {
+            return Err(BaseError::new("Forbidden".to_owned()));
+        }
+
+        if data.duration == 0 {
+            return Err(BaseError::new("Duration should not be zero".to_string()));
+        }
+
+        // The shifted dates have to fit the working calendar like new ones do.
+        let check = |start_date: DateTime<Utc>,
+                     end_date: DateTime<Utc>,
+                     arrival_dates: Vec<DateTime<Utc>>| {
+            let calendar = match form.calendar {
+                Some(ref calendar) => calendar,
+                None => return Ok(()),
+            };
+            let periods = calendar.open_periods(start_date, end_date, &form.time_zone);
+            if periods.is_empty() {
+                return Err(
+                    "Working calendar has no open time between start and end dates".to_string(),
+                );
+            }
+            let is_open =
+                |date: &DateTime<Utc>| periods.iter().any(|(from, to)| from <= date && date < to);
+            match arrival_dates.iter().all(is_open) {
+                true => Ok(()),
+                false => Err("The shift moves submissions out of working hours".to_string()),
+            }
+        };
+
+        let reason = data.reason.filter(|v| !v.trim().is_empty());
+        match self
+            .form_repo
+            .shift(&form.id, data.duration, reason, &check, &user.id)
+            .await
+        {
+            Ok(shift) => Ok(shift),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+
+    pub async fn shifts(&self, id: &str) -> Result<Vec<FormShift>, BaseError> {
+        let form = match self.get_by_id(id).await {
+            Ok(form) => form,
+            Err(err) => return Err(err),
+        };
+
+        Ok(self.form_repo.find_shifts(&form.id).await)
+    }
+
+    pub async fn slots(&self, id: &str) -> Result<Vec<FormSlot>, BaseError> {
+        let form = match self.get_by_id(id).await {
+            Ok(form) => form,
+            Err(err) => return Err(err),
+        };
+
+        Ok(self.form_repo.find_slots(&form.id).await)
+    }
+
+    /// Returns the new limit of the form, which is the total slot capacity.
+    pub async fn set_slots(&self, id: &str, data: SetSlotsData) -> Result<i32, BaseError> {
+        let user = match self.current.authorize(Permission::ManageForms) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        let form = match self.get_by_id(id).await {
+            Ok(form) => form,
+            Err(err) => return Err(err),
+        };
+
+        if form.status != FormStatus::Draft {
+            return Err(BaseError::new("Forbidden".to_owned()));
+        }
+
+        let slots: Vec<(DateTime<Utc>, DateTime<Utc>, i32)> = match (data.capacity, data.slots) {
+            (Some(capacity), None) => {
+                let frames = time_frames(&form);
+                if frames.is_empty() {
+                    return Err(BaseError::new(
+                        "The form has no time frames to split into slots".to_string(),
+                    ));
+                }
+                frames
+                    .into_iter()
+                    .map(|(start, end)| (start, end, capacity as i32))
+                    .collect()
+            }
+            (None, Some(mut slots)) => {
+                slots.sort_by_key(|s| s.starts_at);
+                let is_valid = slots.iter().all(|s| {
+                    s.starts_at < s.ends_at
+                        && s.starts_at >= form.start_date
+                        && s.ends_at <= form.end_date
+                        && s.capacity > 0
+                });
+                let overlaps = slots.windows(2).any(|w| w[0].ends_at > w[1].starts_at);
+                if !is_valid || overlaps {
+                    return Err(BaseError::new(
+                        "Slots should not overlap and should be within the form dates".to_string(),
+                    ));
+                }
+                slots
+                    .into_iter()
+                    .map(|s| (s.starts_at, s.ends_at, s.capacity as i32))
+                    .collect()
+            }
+            _ => {
+                return Err(BaseError::new(
+                    "Either capacity or slots should be provided".to_string(),
+                ))
+            }
+        };
+
+        match self
+            .form_repo
+            .replace_slots(&form.id, slots, &user.id)
+            .await
+        {
+            Ok(limit) => Ok(limit),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+
     pub async fn get(&self) -> Result<Vec<Form>, BaseError> {
-        let _ = match self.user_service.get_current_user().await {
+        let _ = match self.current.authorize(Permission::ViewForms) {
             Ok(user) => user,
             Err(err) => return Err(err),
         };
@@ -199,11 +483,11 @@ impl<'a> FormService<'a> {
     }
 
     pub async fn get_by_id(&self, id: &str) -> Result<Form, BaseError> {
-        let _ = match self.user_service.get_current_user().await {
+        let _ = match self.current.authorize(Permission::ViewForms) {
             Ok(user) => user,
             Err(err) => return Err(err),
         };
-        match self.form_repo.find_by_id(&id).await {
+        match self.form_repo.find_by_id(id).await {
             Some(form) => Ok(form),
             None => Err(BaseError::new("Form not foound".to_string())),
         }
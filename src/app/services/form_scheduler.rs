@@ -0,0 +1,47 @@
+use chrono::{DateTime, Utc};
+
+use crate::app::{
+    entities::form::status::FormStatus, traits::repositories::form::TFormRepositories,
+};
+
+pub struct FormScheduler<'a> {
+    form_repo: &'a (dyn TFormRepositories + Send + Sync),
+}
+
+impl<'a> FormScheduler<'a> {
+    pub fn new(form_repo: &'a (dyn TFormRepositories + Send + Sync)) -> Self {
+        Self { form_repo }
+    }
+
+    pub async fn run(&self, now: DateTime<Utc>) {
+        for form in self.form_repo.find_scheduled(now).await {
+            let next = match form.status {
+                FormStatus::Draft => FormStatus::Open,
+                FormStatus::Open => FormStatus::Close,
+                FormStatus::Close => continue,
+            };
+
+            if !form.status.can_transition_to(&next) {
+                continue;
+            }
+
+            let result = self
+                .form_repo
+                .update_status(&form.id, &form.status.to_string(), &next.to_string(), None)
+                .await;
+
+            match result {
+                Ok(true) => println!(
+                    "Form scheduler: form {} ({}) changed from {} to {}",
+                    form.id, form.name, form.status, next
+                ),
+                // Another instance has already applied this transition.
+                Ok(false) => (),
+                Err(err) => eprintln!(
+                    "Form scheduler: failed to change form {} status: {}",
+                    form.id, err
+                ),
+            }
+        }
+    }
+}
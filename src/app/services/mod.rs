@@ -1,5 +1,14 @@
+pub mod api_key;
+pub mod audit;
 pub mod auth;
+pub mod current_user;
+pub mod enrollment;
 pub mod form;
+pub mod form_scheduler;
+pub mod oidc;
+pub mod password;
 pub mod respondent;
 pub mod submission;
+pub mod two_factor;
 pub mod user;
+pub mod user_admin;
@@ -0,0 +1,185 @@
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidateEmail};
+
+use crate::app::{
+    config::Config,
+    entities::user::User,
+    errors::BaseError,
+    traits::{oidc::TOidcProvider, repositories::user::TUserRepositories},
+    utils::{
+        pkce::{code_challenge, random_token},
+        validate::validate,
+    },
+};
+
+use super::auth::{AuthService, SignIn};
+
+/// Minutes to finish the sign in at the identity provider.
+const LOGIN_TTL: i64 = 10;
+
+/// Stored instead of a password hash for users created by single sign-on, so
+/// they can't sign in with a password until one is set for them.
+const SSO_PASSWORD_ALG: &str = "sso";
+
+#[derive(Debug, Serialize)]
+pub struct OidcStart {
+    pub url: String,
+    #[serde(skip)]
+    pub state: String,
+}
+
+#[derive(Debug, Validate, Deserialize)]
+pub struct OidcCallbackInputData {
+    code: String,
+    state: String,
+    #[validate(length(max = 128, message = "Device label is too long"))]
+    device: Option<String>,
+}
+
+pub struct OidcService<'a> {
+    config: &'a Config,
+    user_rep: &'a (dyn TUserRepositories + Send + Sync),
+    provider: Option<&'a (dyn TOidcProvider + Send + Sync)>,
+}
+
+impl<'a> OidcService<'a> {
+    pub fn new(
+        config: &'a Config,
+        user_rep: &'a (dyn TUserRepositories + Send + Sync),
+        provider: Option<&'a (dyn TOidcProvider + Send + Sync)>,
+    ) -> Self {
+        Self {
+            config,
+            user_rep,
+            provider,
+        }
+    }
+
+    /// Returns the identity provider URL to send the browser to. The state
+    /// has to come back with the callback from the same browser.
+    pub async fn start(&self) -> Result<OidcStart, BaseError> {
+        let provider = match self.provider {
+            Some(provider) => provider,
+            None => {
+                return Err(BaseError::new(
+                    "Single sign-on is not configured".to_string(),
+                ))
+            }
+        };
+
+        let state = random_token();
+        let nonce = random_token();
+        let code_verifier = random_token();
+
+        if let Err(err) = self
+            .user_rep
+            .insert_oidc_login(&state, &nonce, &code_verifier)
+            .await
+        {
+            return Err(BaseError::new(err));
+        }
+
+        match provider
+            .authorize_url(&state, &nonce, &code_challenge(&code_verifier))
+            .await
+        {
+            Ok(url) => Ok(OidcStart { url, state }),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+
+    /// Redeems the code from the identity provider and signs in the user with
+    /// its email. Unknown emails get an account with the default role, when
+    /// one is configured.
+    pub async fn callback(
+        &self,
+        data: OidcCallbackInputData,
+        browser_state: Option<String>,
+        ip: Option<String>,
+        user_agent: Option<String>,
+    ) -> Result<SignIn, BaseError> {
+        match validate(&data) {
+            Ok(_) => (),
+            Err(e) => return Err(e),
+        };
+
+        let provider = match self.provider {
+            Some(provider) => provider,
+            None => {
+                return Err(BaseError::new(
+                    "Single sign-on is not configured".to_string(),
+                ))
+            }
+        };
+
+        // A state from another browser means someone tries to sign the user in
+        // to their own account.
+        if browser_state.as_deref() != Some(data.state.as_str()) {
+            return Err(BaseError::new("Sign in is expired, try again".to_string()));
+        }
+
+        let login = match self
+            .user_rep
+            .take_oidc_login(&data.state, Duration::minutes(LOGIN_TTL))
+            .await
+        {
+            Some(login) => login,
+            None => return Err(BaseError::new("Sign in is expired, try again".to_string())),
+        };
+
+        let identity = match provider.exchange(&data.code, &login.code_verifier).await {
+            Ok(identity) => identity,
+            Err(err) => return Err(BaseError::new(err)),
+        };
+
+        if identity.nonce.as_deref() != Some(login.nonce.as_str()) {
+            return Err(BaseError::new("Sign in is expired, try again".to_string()));
+        }
+
+        if !identity.email_verified {
+            return Err(BaseError::new("Email is not verified".to_string()));
+        }
+
+        let user = match self.user_rep.find_by_email(&identity.email).await {
+            Some(user) => user,
+            None => match self.create_user(&identity.email).await {
+                Ok(user) => user,
+                Err(err) => return Err(err),
+            },
+        };
+
+        if user.disabled_at.is_some() {
+            return Err(BaseError::new("User is disabled".to_string()));
+        }
+
+        AuthService::new(self.config, self.user_rep)
+            .sign_in(&user, data.device, ip, user_agent)
+            .await
+    }
+
+    async fn create_user(&self, email: &str) -> Result<User, BaseError> {
+        let role = match self.config.oidc_default_role {
+            Some(ref role) => role.to_string(),
+            None => return Err(BaseError::new("User not found".to_string())),
+        };
+
+        if !email.validate_email() || email.len() > 64 {
+            return Err(BaseError::new("Email is invalid".to_string()));
+        }
+
+        let id = match self
+            .user_rep
+            .insert(email, "", SSO_PASSWORD_ALG, &role, None)
+            .await
+        {
+            Ok(id) => id,
+            Err(err) => return Err(BaseError::new(err)),
+        };
+
+        match self.user_rep.find_by_id(&id).await {
+            Some(user) => Ok(user),
+            None => Err(BaseError::new("User not found".to_string())),
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use chrono::Duration;
+
+use crate::app::{
+    config::Config,
+    entities::user::User,
+    errors::BaseError,
+    traits::{mailer::TMailer, repositories::user::TUserRepositories},
+    utils::{
+        hash::hash_pwd,
+        jwt::{ClaimType, JWT},
+        validate::validate,
+    },
+};
+
+use super::auth::{EmailInputData, ResetPasswordInputData};
+
+/// Reset links one email can ask for within an hour.
+const RESETS_PER_EMAIL: i32 = 3;
+/// Reset requests allowed from one IP within an hour.
+const RESETS_PER_IP: i32 = 20;
+
+pub struct PasswordService<'a> {
+    config: &'a Config,
+    user_rep: &'a (dyn TUserRepositories + Send + Sync),
+    mailer: &'a (dyn TMailer + Send + Sync),
+}
+
+impl<'a> PasswordService<'a> {
+    pub fn new(
+        config: &'a Config,
+        user_rep: &'a (dyn TUserRepositories + Send + Sync),
+        mailer: &'a (dyn TMailer + Send + Sync),
+    ) -> Self {
+        Self {
+            config,
+            user_rep,
+            mailer,
+        }
+    }
+
+    /// Mails a reset link. Unknown and disabled emails get the same answer,
+    /// so the endpoint can't be used to find out who has an account.
+    pub async fn forgot(&self, data: EmailInputData, ip: &Option<String>) -> Result<(), BaseError> {
+        match validate(&data) {
+            Ok(_) => (),
+            Err(e) => return Err(e),
+        };
+
+        // Counted before the lookup, so every email is throttled alike.
+        let email = data.email.to_lowercase();
+        let mut limits = vec![("EMAIL", email.as_str(), RESETS_PER_EMAIL)];
+        if let Some(ip) = ip {
+            limits.push(("IP", ip.as_str(), RESETS_PER_IP));
+        }
+        for (kind, value, limit) in limits {
+            match self
+                .user_rep
+                .count_reset_request(kind, value, Duration::hours(1))
+                .await
+            {
+                Ok(count) if count > limit => return Err(BaseError::too_many_requests()),
+                Ok(_) => (),
+                Err(err) => return Err(BaseError::new(err)),
+            }
+        }
+
+        let user = match self.user_rep.find_by_email(&data.email).await {
+            Some(user) if user.disabled_at.is_none() => user,
+            _ => return Ok(()),
+        };
+
+        if let Err(err) = self.send_reset_link(&user).await {
+            eprintln!("Failed to send a password reset to {}: {}", user.email, err);
+        }
+        Ok(())
+    }
+
+    /// Sets the password with a reset token. The token works once and every
+    /// session of the user is signed out.
+    pub async fn reset(&self, data: ResetPasswordInputData) -> Result<(), BaseError> {
+        match validate(&data) {
+            Ok(_) => (),
+            Err(e) => return Err(e),
+        };
+
+        if JWT::new(self.config)
+            .parse(&data.token, Some(ClaimType::Reset))
+            .is_err()
+        {
+            return Err(BaseError::new("Token is expired".to_string()));
+        }
+
+        let (password_alg, password_hash) = match hash_pwd(self.config, &data.password) {
+            Ok(res) => res,
+            Err(e) => return Err(BaseError::new(e)),
+        };
+
+        match self
+            .user_rep
+            .reset_password(&data.token, &password_hash, &password_alg)
+            .await
+        {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(BaseError::new("Token is expired".to_string())),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+
+    async fn send_reset_link(&self, user: &User) -> Result<(), String> {
+        let token = match JWT::new(self.config).reset(user) {
+            Ok(token) => token,
+            Err(err) => return Err(err),
+        };
+
+        match self.user_rep.insert_reset_token(&user.id, &token).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        let body = format!(
+            "Use the link below to set a new password. It expires in {} minutes.\n\n{}/reset-password?token={}",
+            self.config.reset_token_ttl, self.config.app_url, token
+        );
+
+        self.mailer.send(&user.email, "Password reset", &body).await
+    }
+}
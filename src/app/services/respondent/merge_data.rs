@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeSide {
+    #[default]
+    Target,
+    Source,
+}
+
+impl MergeSide {
+    pub fn pick<'v>(&self, target: &'v str, source: &'v str) -> &'v str {
+        let (preferred, fallback) = match self {
+            MergeSide::Target => (target, source),
+            MergeSide::Source => (source, target),
+        };
+        if preferred.trim().is_empty() {
+            fallback
+        } else {
+            preferred
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MergeData {
+    #[serde(rename = "formId", alias = "fromId")]
+    pub from_id: String,
+    #[serde(default)]
+    pub phone: MergeSide,
+    #[serde(default, rename = "IDPCode")]
+    pub idp_code: MergeSide,
+    #[serde(default)]
+    pub children: MergeSide,
+    #[serde(default)]
+    pub region: MergeSide,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeSummary {
+    pub respondent_id: String,
+    pub removed_respondent_id: String,
+    pub moved_submission_ids: Vec<String>,
+    pub dropped_submission_ids: Vec<String>,
+    pub updated_fields: Vec<String>,
+}
@@ -1,25 +1,23 @@
 use serde::Deserialize;
 
 use crate::app::{
-    config::Config,
-    entities::respondent::Respondent,
+    entities::{form::Form, respondent::Respondent, role::Permission},
     errors::BaseError,
-    traits::repositories::{respondent::TRespondentRepositories, user::TUserRepositories},
-    utils::validate::validate,
+    traits::repositories::respondent::TRespondentRepositories,
+    utils::{arrival_date::calculate_arrival_date, validate::validate},
 };
 
-use self::{create_data::CreateData, update_data::UpdateData};
+use self::{
+    create_data::CreateData,
+    merge_data::{MergeData, MergeSide, MergeSummary},
+    update_data::UpdateData,
+};
 
-use super::user::UserService;
+use super::current_user::CurrentUser;
 pub mod create_data;
+pub mod merge_data;
 pub mod update_data;
 
-#[derive(Debug, Deserialize)]
-pub struct MergeData {
-    #[serde(rename = "formId")]
-    from_id: String,
-}
-
 #[derive(Debug, Deserialize)]
 pub struct GetQuery {
     name: Option<String>,
@@ -29,19 +27,17 @@ pub struct GetQuery {
 
 pub struct RespondentService<'a> {
     respondent_repo: &'a (dyn TRespondentRepositories + Send + Sync),
-    user_service: UserService<'a>,
+    current: &'a CurrentUser,
 }
 
 impl<'a> RespondentService<'a> {
     pub fn new(
-        config: &'a Config,
         respondent_repo: &'a (dyn TRespondentRepositories + Send + Sync),
-        user_repo: &'a (dyn TUserRepositories + Send + Sync),
-        token: &'a str,
+        current: &'a CurrentUser,
     ) -> Self {
         Self {
-            respondent_repo: respondent_repo,
-            user_service: UserService::new(config, user_repo, token),
+            respondent_repo,
+            current,
         }
     }
 
@@ -51,7 +47,7 @@ impl<'a> RespondentService<'a> {
             Err(e) => return Err(e),
         };
 
-        let _ = match self.user_service.get_current_user().await {
+        let user = match self.current.authorize(Permission::EditRespondents) {
             Ok(user) => user,
             Err(err) => return Err(err),
         };
@@ -64,6 +60,7 @@ impl<'a> RespondentService<'a> {
             &data.region,
             data.children as i16,
             &data.idp_code,
+            &user.id,
         );
 
         match result.await {
@@ -78,7 +75,7 @@ impl<'a> RespondentService<'a> {
             Err(e) => return Err(e),
         };
 
-        let _ = match self.user_service.get_current_user().await {
+        let user = match self.current.authorize(Permission::EditRespondents) {
             Ok(user) => user,
             Err(err) => return Err(err),
         };
@@ -93,6 +90,7 @@ impl<'a> RespondentService<'a> {
             &data.region,
             &children,
             &data.idp_code,
+            &user.id,
         );
         match result.await {
             Ok(()) => Ok(()),
@@ -101,19 +99,19 @@ impl<'a> RespondentService<'a> {
     }
 
     pub async fn delete(&self, id: String) -> Result<(), BaseError> {
-        let _ = match self.user_service.get_current_user().await {
+        let user = match self.current.authorize(Permission::DeleteRespondents) {
             Ok(user) => user,
             Err(err) => return Err(err),
         };
 
-        match self.respondent_repo.delete(&id).await {
+        match self.respondent_repo.delete(&id, &user.id).await {
             Ok(_) => Ok(()),
             Err(err) => Err(BaseError::new(err.to_string())),
         }
     }
 
     pub async fn get(&self, query: GetQuery) -> Result<Vec<Respondent>, BaseError> {
-        let _ = match self.user_service.get_current_user().await {
+        let _ = match self.current.authorize(Permission::ViewRespondents) {
             Ok(user) => user,
             Err(err) => return Err(err),
         };
@@ -125,36 +123,100 @@ impl<'a> RespondentService<'a> {
     }
 
     pub async fn get_by_id(&self, id: &str) -> Result<Respondent, BaseError> {
-        let _ = match self.user_service.get_current_user().await {
+        let _ = match self.current.authorize(Permission::ViewRespondents) {
             Ok(user) => user,
             Err(err) => return Err(err),
         };
-        match self.respondent_repo.find_by_id(&id).await {
+        match self.respondent_repo.find_by_id(id).await {
             Some(respondent) => Ok(respondent),
             None => Err(BaseError::new("Respondent not found".to_string())),
         }
     }
 
-    pub async fn merge(&self, id: &str, data: &MergeData) -> Result<(), BaseError> {
-        let _ = match self.user_service.get_current_user().await {
+    pub async fn merge(&self, id: &str, data: &MergeData) -> Result<MergeSummary, BaseError> {
+        let user = match self.current.authorize(Permission::DeleteRespondents) {
             Ok(user) => user,
             Err(err) => return Err(err),
         };
 
         if data.from_id == id {
-            return Ok(());
+            return Err(BaseError::new(
+                "Respondent can't be merged into itself".to_string(),
+            ));
         }
 
-        let _ = match self.get_by_id(id).await {
+        let target = match self.get_by_id(id).await {
             Ok(data) => data,
             Err(err) => return Err(err),
         };
 
-        let _ = match self.get_by_id(&data.from_id).await {
+        let source = match self.get_by_id(&data.from_id).await {
             Ok(data) => data,
             Err(err) => return Err(err),
         };
 
-        Ok(())
+        let mut updated_fields: Vec<String> = vec![];
+
+        let phone = data.phone.pick(&target.phone, &source.phone).to_string();
+        let phone = if phone != target.phone {
+            updated_fields.push("phone".to_string());
+            Some(phone)
+        } else {
+            None
+        };
+
+        let region = data.region.pick(&target.region, &source.region).to_string();
+        let region = if region != target.region {
+            updated_fields.push("region".to_string());
+            Some(region)
+        } else {
+            None
+        };
+
+        let idp_code = data
+            .idp_code
+            .pick(
+                target.idp_code.as_deref().unwrap_or(""),
+                source.idp_code.as_deref().unwrap_or(""),
+            )
+            .to_string();
+        let idp_code = if !idp_code.is_empty() && Some(&idp_code) != target.idp_code.as_ref() {
+            updated_fields.push("IDPCode".to_string());
+            Some(idp_code)
+        } else {
+            None
+        };
+
+        let children = match data.children {
+            MergeSide::Source if source.children != target.children => {
+                updated_fields.push("children".to_string());
+                Some(source.children as i16)
+            }
+            _ => None,
+        };
+
+        // Places freed by dropped submissions go to the waitlist of the form.
+        let arrival_date = |form: &Form, order: i32| calculate_arrival_date(form, order as u16);
+        let result = self.respondent_repo.merge(
+            &target.id,
+            &source.id,
+            &arrival_date,
+            &phone,
+            &region,
+            &children,
+            &idp_code,
+            &user.id,
+        );
+
+        match result.await {
+            Ok((moved_submission_ids, dropped_submission_ids)) => Ok(MergeSummary {
+                respondent_id: target.id,
+                removed_respondent_id: source.id,
+                moved_submission_ids,
+                dropped_submission_ids,
+                updated_fields,
+            }),
+            Err(err) => Err(BaseError::new(err)),
+        }
     }
 }
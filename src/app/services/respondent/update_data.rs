@@ -29,7 +29,7 @@ pub struct UpdateData {
 fn validate_first_name(value: &Option<String>) -> Result<(), ValidationError> {
     match value {
         None => Ok(()),
-        Some(v) => match Name::parse(&v) {
+        Some(v) => match Name::parse(v) {
             Ok(_) => Ok(()),
             Err(_) => Err(ValidationError::new("")
                 .with_message(Cow::from("Прізвище містить заборонені символ"))),
@@ -41,7 +41,7 @@ fn validate_last_name(value: &Option<String>) -> Result<(), ValidationError> {
     match value {
         None => Ok(()),
         Some(v) => {
-            match Name::parse(&v) {
+            match Name::parse(v) {
                 Ok(_) => Ok(()),
                 Err(_) => Err(ValidationError::new("")
                     .with_message(Cow::from("Ім'я містить заборонені символи"))),
@@ -53,7 +53,7 @@ fn validate_last_name(value: &Option<String>) -> Result<(), ValidationError> {
 fn validate_phone(value: &Option<String>) -> Result<(), ValidationError> {
     match value {
         None => Ok(()),
-        Some(v) => match Phone::parse(&v) {
+        Some(v) => match Phone::parse(v) {
             Ok(_) => Ok(()),
             Err(_) => {
                 Err(ValidationError::new("").with_message(Cow::from("Номер телефону неправильний")))
@@ -65,7 +65,7 @@ fn validate_phone(value: &Option<String>) -> Result<(), ValidationError> {
 fn validate_passport(value: &Option<String>) -> Result<(), ValidationError> {
     match value {
         None => Ok(()),
-        Some(v) => match Passport::parse(&v) {
+        Some(v) => match Passport::parse(v) {
             Ok(_) => Ok(()),
             Err(_) => {
                 Err(ValidationError::new("").with_message(Cow::from("Паспортні дані неправильні")))
@@ -77,7 +77,7 @@ fn validate_passport(value: &Option<String>) -> Result<(), ValidationError> {
 fn validate_region(value: &Option<String>) -> Result<(), ValidationError> {
     match value {
         None => Ok(()),
-        Some(v) => match Region::parse(&v) {
+        Some(v) => match Region::parse(v) {
             Ok(_) => Ok(()),
             Err(_) => {
                 Err(ValidationError::new("").with_message(Cow::from("Область є обов'язковою")))
@@ -3,17 +3,25 @@ use std::str::FromStr;
 use serde::Deserialize;
 
 use crate::app::{
-    config::Config,
-    entities::submission::{status::SubmissionStatus, Submission},
-    errors::BaseError,
+    entities::{
+        role::Permission,
+        submission::{
+            eligibility::Eligibility,
+            history::StatusChange,
+            status::SubmissionStatus,
+            waitlist::{Booking, BookingOutcome, WaitlistEntry},
+            Submission,
+        },
+    },
+    errors::{BaseError, ErrorKind, FieldError},
     traits::repositories::{
         form::TFormRepositories, respondent::TRespondentRepositories,
-        submission::TSubmissionRepositories, user::TUserRepositories,
+        submission::TSubmissionRepositories,
     },
     utils::arrival_date::calculate_arrival_date,
 };
 
-use super::{form::FormService, respondent::RespondentService, user::UserService};
+use super::{current_user::CurrentUser, form::FormService, respondent::RespondentService};
 
 #[derive(Debug, Deserialize)]
 pub struct GetQuery {
@@ -23,104 +31,142 @@ pub struct GetQuery {
 
 pub struct SubmissionService<'a> {
     sub_rep: &'a (dyn TSubmissionRepositories + Send + Sync),
-    user_service: UserService<'a>,
+    current: &'a CurrentUser,
     form_service: FormService<'a>,
     respondent_service: RespondentService<'a>,
 }
 
 impl<'a> SubmissionService<'a> {
     pub fn new(
-        config: &'a Config,
         sub_rep: &'a (dyn TSubmissionRepositories + Send + Sync),
-        user_rep: &'a (dyn TUserRepositories + Send + Sync),
         form_rep: &'a (dyn TFormRepositories + Send + Sync),
         resp_rep: &'a (dyn TRespondentRepositories + Send + Sync),
-        token: &'a str,
+        current: &'a CurrentUser,
     ) -> Self {
         Self {
             sub_rep,
-            respondent_service: RespondentService::new(config, resp_rep, user_rep, token),
-            user_service: UserService::new(config, user_rep, token),
-            form_service: FormService::new(&config, form_rep, user_rep, &token),
+            respondent_service: RespondentService::new(resp_rep, current),
+            current,
+            form_service: FormService::new(form_rep, current),
         }
     }
 
-    pub async fn create(&self, form_id: &str, respondent_id: &str) -> Result<String, BaseError> {
-        let _ = match self.user_service.get_current_user().await {
+    /// Books a place in the form, or puts the respondent on the form waitlist
+    /// when there are no free places left.
+    pub async fn create(&self, form_id: &str, respondent_id: &str) -> Result<Booking, BaseError> {
+        let user = match self.current.authorize(Permission::EditSubmissions) {
             Ok(user) => user,
             Err(err) => return Err(err),
         };
 
-        let form = match self.form_service.get_by_id(&form_id).await {
+        let form = match self.form_service.get_by_id(form_id).await {
             Ok(form) => form,
             Err(err) => return Err(err),
         };
 
-        let _ = match self.respondent_service.get_by_id(&respondent_id).await {
+        let _ = match self.respondent_service.get_by_id(respondent_id).await {
             Ok(form) => form,
             Err(err) => return Err(err),
         };
 
-        let submissions = self.sub_rep.find(Some(form_id.to_string()), None).await;
-        let respondent_sub = submissions
-            .iter()
-            .find(|s| s.respondent.id == respondent_id);
-
-        if respondent_sub.is_some() {
-            return Err(BaseError::new(
-                "This respondent already have submission".to_string(),
-            ));
-        }
-
-        let sub_order = submissions.len() + 1;
-
-        if form.limit < sub_order as u16 {
-            return Err(BaseError::new("Forbidden".to_string()));
-        }
-
-        let arrival_date = calculate_arrival_date(&form, sub_order as u16);
+        let arrival_date = |order: i32| calculate_arrival_date(&form, order as u16);
 
         let insert_result = self
             .sub_rep
             .insert(
-                &form_id,
-                &respondent_id,
-                arrival_date.naive_utc(),
-                sub_order as i32,
+                form_id,
+                respondent_id,
                 &SubmissionStatus::Received.to_string(),
+                &user.id,
+                &arrival_date,
             )
             .await;
 
-        match insert_result {
-            Ok(id) => Ok(id),
-            Err(err) => Err(BaseError::new(err)),
-        }
+        // The exclusions are checked while the booking holds the respondent,
+        // so concurrent bookings into forms excluding each other can't both pass.
+        let conflicts = match insert_result {
+            Ok(BookingOutcome::Booked(booking)) => return Ok(booking),
+            Ok(BookingOutcome::Excluded(conflicts)) => conflicts,
+            Err(err) => return Err(BaseError::new(err)),
+        };
+
+        let fields = conflicts
+            .iter()
+            .map(|c| FieldError {
+                field: "respondentId".to_string(),
+                message: format!(
+                    "Respondent already has submission {} in excluded form \"{}\" ({})",
+                    c.submission_id, c.form_name, c.form_id
+                ),
+            })
+            .collect();
+        Err(BaseError {
+            message: "Respondent is not eligible for this form".to_string(),
+            fields: Some(fields),
+            kind: ErrorKind::BadRequest,
+        })
+    }
+
+    pub async fn eligibility(
+        &self,
+        form_id: &str,
+        respondent_id: &str,
+    ) -> Result<Eligibility, BaseError> {
+        let _ = match self.current.authorize(Permission::ViewSubmissions) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        let form = match self.form_service.get_by_id(form_id).await {
+            Ok(form) => form,
+            Err(err) => return Err(err),
+        };
+
+        let _ = match self.respondent_service.get_by_id(respondent_id).await {
+            Ok(respondent) => respondent,
+            Err(err) => return Err(err),
+        };
+
+        let conflicts = self.sub_rep.find_conflicts(&form.id, respondent_id).await;
+
+        Ok(Eligibility {
+            eligible: conflicts.is_empty(),
+            conflicts,
+        })
     }
 
     pub async fn delete(&self, id: &str) -> Result<(), BaseError> {
-        let _ = match self.user_service.get_current_user().await {
+        let user = match self.current.authorize(Permission::DeleteSubmissions) {
             Ok(user) => user,
             Err(err) => return Err(err),
         };
 
-        match self.sub_rep.delete(&id).await {
+        let submission = match self.sub_rep.find_by_id(id).await {
+            Some(sub) => sub,
+            None => return Err(BaseError::new("Submission not found".to_string())),
+        };
+
+        // A freed place goes to the head of the waitlist.
+        let arrival_date = |order: i32| calculate_arrival_date(&submission.form, order as u16);
+
+        match self.sub_rep.delete(id, &user.id, &arrival_date).await {
             Ok(_) => Ok(()),
-            Err(err) => return Err(BaseError::new(err)),
+            Err(err) => Err(BaseError::new(err)),
         }
     }
 
     pub async fn status(&self, id: &str, status: &str) -> Result<(), BaseError> {
-        let sub_status = match SubmissionStatus::from_str(&status) {
+        let sub_status = match SubmissionStatus::from_str(status) {
             Ok(value) => value,
             Err(_) => return Err(BaseError::new("Status is not valid".to_string())),
         };
 
-        let _ = match self.user_service.get_current_user().await {
+        let user = match self.current.authorize(Permission::EditSubmissions) {
             Ok(user) => user,
             Err(err) => return Err(err),
         };
 
-        let submission = match self.sub_rep.find_by_id(&id).await {
+        let submission = match self.sub_rep.find_by_id(id).await {
             Some(sub) => sub,
             None => return Err(BaseError::new("Submission not found".to_string())),
         };
@@ -129,14 +175,106 @@ impl<'a> SubmissionService<'a> {
             return Ok(());
         }
 
-        match self.sub_rep.update(id, &Some(status.to_string())).await {
+        if !submission.status.can_transition_to(&sub_status) {
+            return Err(BaseError::invalid_transition(
+                "status",
+                &submission.status.to_string(),
+                status,
+            ));
+        }
+
+        let arrival_date = |order: i32| calculate_arrival_date(&submission.form, order as u16);
+
+        match self
+            .sub_rep
+            .update(id, &Some(status.to_string()), &user.id, &arrival_date)
+            .await
+        {
             Ok(_) => Ok(()),
-            Err(err) => return Err(BaseError::new(err)),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+
+    pub async fn history(&self, id: &str) -> Result<Vec<StatusChange>, BaseError> {
+        let _ = match self.current.authorize(Permission::ViewSubmissions) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        if self.sub_rep.find_by_id(id).await.is_none() {
+            return Err(BaseError::new("Submission not found".to_string()));
+        }
+
+        Ok(self.sub_rep.find_history(id).await)
+    }
+
+    pub async fn waitlist(&self, form_id: &str) -> Result<Vec<WaitlistEntry>, BaseError> {
+        let _ = match self.current.authorize(Permission::ViewSubmissions) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        let form = match self.form_service.get_by_id(form_id).await {
+            Ok(form) => form,
+            Err(err) => return Err(err),
+        };
+
+        Ok(self.sub_rep.find_waitlist(&form.id).await)
+    }
+
+    pub async fn move_waitlist_entry(
+        &self,
+        form_id: &str,
+        id: &str,
+        position: u32,
+    ) -> Result<(), BaseError> {
+        if position == 0 {
+            return Err(BaseError::new("Position should be min 1".to_string()));
+        }
+
+        let user = match self.current.authorize(Permission::EditSubmissions) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        let form = match self.form_service.get_by_id(form_id).await {
+            Ok(form) => form,
+            Err(err) => return Err(err),
+        };
+
+        match self
+            .sub_rep
+            .move_waitlist_entry(&form.id, id, position as usize, &user.id)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+
+    pub async fn remove_waitlist_entry(&self, form_id: &str, id: &str) -> Result<(), BaseError> {
+        let user = match self.current.authorize(Permission::EditSubmissions) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        let form = match self.form_service.get_by_id(form_id).await {
+            Ok(form) => form,
+            Err(err) => return Err(err),
+        };
+
+        match self
+            .sub_rep
+            .delete_waitlist_entry(&form.id, id, &user.id)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(err) => Err(BaseError::new(err)),
         }
     }
 
     pub async fn get(&self, query: GetQuery) -> Result<Vec<Submission>, BaseError> {
-        let _ = match self.user_service.get_current_user().await {
+        let _ = match self.current.authorize(Permission::ViewSubmissions) {
             Ok(user) => user,
             Err(err) => return Err(err),
         };
@@ -0,0 +1,314 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::app::{
+    config::Config,
+    entities::{role::Permission, user::User},
+    errors::BaseError,
+    traits::repositories::user::TUserRepositories,
+    utils::{
+        hash::{hash_pwd, verify_pwd},
+        totp::{
+            generate_recovery_codes, generate_secret, normalize_recovery_code, provisioning_uri,
+            verify,
+        },
+        validate::validate,
+    },
+};
+
+use super::current_user::CurrentUser;
+
+const REQUIRED_SETTING: &str = "two_factor_required";
+
+#[derive(Debug, Serialize)]
+pub struct TotpSetup {
+    pub secret: String,
+    pub uri: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Validate, Deserialize)]
+pub struct CodeInputData {
+    #[validate(length(min = 6, max = 32, message = "Code is invalid"))]
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyData {
+    pub two_factor_required: bool,
+}
+
+pub struct TwoFactorService<'a> {
+    config: &'a Config,
+    user_rep: &'a (dyn TUserRepositories + Send + Sync),
+    current: &'a CurrentUser,
+}
+
+impl<'a> TwoFactorService<'a> {
+    pub fn new(
+        config: &'a Config,
+        user_rep: &'a (dyn TUserRepositories + Send + Sync),
+        current: &'a CurrentUser,
+    ) -> Self {
+        Self {
+            config,
+            user_rep,
+            current,
+        }
+    }
+
+    /// Starts enrollment with a new secret. It only takes effect once a code
+    /// from the authenticator app is confirmed with `enable`.
+    pub async fn setup(&self) -> Result<TotpSetup, BaseError> {
+        let (user, _) = match self.current.signed_in() {
+            Ok(res) => res,
+            Err(err) => return Err(err),
+        };
+
+        if user.totp_enabled_at.is_some() {
+            return Err(BaseError::new(
+                "Two-factor authentication is already enabled".to_string(),
+            ));
+        }
+
+        start_setup(self.config, self.user_rep, user, true).await
+    }
+
+    pub async fn enable(&self, data: CodeInputData) -> Result<RecoveryCodes, BaseError> {
+        match validate(&data) {
+            Ok(_) => (),
+            Err(e) => return Err(e),
+        };
+
+        let (user, _) = match self.current.signed_in() {
+            Ok(res) => res,
+            Err(err) => return Err(err),
+        };
+
+        if user.totp_enabled_at.is_some() {
+            return Err(BaseError::new(
+                "Two-factor authentication is already enabled".to_string(),
+            ));
+        }
+
+        confirm_setup(self.config, self.user_rep, user, &data.code).await
+    }
+
+    pub async fn disable(&self, data: CodeInputData) -> Result<(), BaseError> {
+        if is_required(self.user_rep).await {
+            return Err(BaseError::new(
+                "Two-factor authentication is required".to_string(),
+            ));
+        }
+
+        let user = match self.enabled_user(&data).await {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        match self.user_rep.disable_totp(&user.id, &user.id).await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+
+    /// Replaces the recovery codes, the old ones stop working.
+    pub async fn recovery_codes(&self, data: CodeInputData) -> Result<RecoveryCodes, BaseError> {
+        let user = match self.enabled_user(&data).await {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        let (codes, hashes) = match new_recovery_codes(self.config) {
+            Ok(res) => res,
+            Err(err) => return Err(BaseError::new(err)),
+        };
+        match self.user_rep.replace_recovery_codes(&user.id, hashes).await {
+            Ok(_) => Ok(RecoveryCodes {
+                recovery_codes: codes,
+            }),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+
+    pub async fn policy(&self) -> Result<PolicyData, BaseError> {
+        let _ = match self.current.authorize(Permission::ManageUsers) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        Ok(PolicyData {
+            two_factor_required: is_required(self.user_rep).await,
+        })
+    }
+
+    /// When 2FA is required, users without it enroll during their next sign in,
+    /// through a link mailed to them.
+    pub async fn set_policy(&self, data: PolicyData) -> Result<(), BaseError> {
+        let current = match self.current.authorize(Permission::ManageUsers) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        let value = data.two_factor_required.to_string();
+        let res = self
+            .user_rep
+            .set_setting(REQUIRED_SETTING, &value, &current.id)
+            .await;
+        match res {
+            Ok(_) => Ok(()),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+
+    /// Turns 2FA off for a user who lost their authenticator and recovery codes.
+    pub async fn reset(&self, user_id: &str) -> Result<(), BaseError> {
+        let current = match self.current.authorize(Permission::ManageUsers) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        let user = match self.user_rep.find_by_id(user_id).await {
+            Some(user) => user,
+            None => return Err(BaseError::new("User not found".to_string())),
+        };
+
+        match self.user_rep.disable_totp(&user.id, &current.id).await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+
+    async fn enabled_user(&self, data: &CodeInputData) -> Result<User, BaseError> {
+        match validate(data) {
+            Ok(_) => (),
+            Err(e) => return Err(e),
+        };
+
+        let (user, _) = match self.current.signed_in() {
+            Ok(res) => res,
+            Err(err) => return Err(err),
+        };
+
+        if user.totp_enabled_at.is_none() {
+            return Err(BaseError::new(
+                "Two-factor authentication is not enabled".to_string(),
+            ));
+        }
+
+        match check_code(self.user_rep, user, &data.code).await {
+            Ok(true) => Ok(user.clone()),
+            Ok(false) => Err(BaseError::new("Code is invalid".to_string())),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+}
+
+pub async fn is_required(user_rep: &(dyn TUserRepositories + Send + Sync)) -> bool {
+    user_rep.find_setting(REQUIRED_SETTING).await.as_deref() == Some("true")
+}
+
+/// Returns the pending secret of the user, or stores a new one when there is
+/// none or `renew` is set.
+pub async fn start_setup(
+    config: &Config,
+    user_rep: &(dyn TUserRepositories + Send + Sync),
+    user: &User,
+    renew: bool,
+) -> Result<TotpSetup, BaseError> {
+    let secret = match &user.totp_secret {
+        Some(secret) if !renew => secret.clone(),
+        _ => {
+            let secret = generate_secret();
+            if let Err(err) = user_rep.set_totp_secret(&user.id, &secret).await {
+                return Err(BaseError::new(err));
+            }
+            secret
+        }
+    };
+
+    match provisioning_uri(&secret, &config.totp_issuer, &user.email) {
+        Ok(uri) => Ok(TotpSetup { secret, uri }),
+        Err(err) => Err(BaseError::new(err)),
+    }
+}
+
+/// Enables 2FA once a code matches the pending secret.
+pub async fn confirm_setup(
+    config: &Config,
+    user_rep: &(dyn TUserRepositories + Send + Sync),
+    user: &User,
+    code: &str,
+) -> Result<RecoveryCodes, BaseError> {
+    let secret = match &user.totp_secret {
+        Some(secret) => secret,
+        None => {
+            return Err(BaseError::new(
+                "Set up two-factor authentication first".to_string(),
+            ))
+        }
+    };
+
+    let step = match verify(secret, code) {
+        Some(step) => step,
+        None => return Err(BaseError::new("Code is invalid".to_string())),
+    };
+
+    let (codes, hashes) = match new_recovery_codes(config) {
+        Ok(res) => res,
+        Err(err) => return Err(BaseError::new(err)),
+    };
+    match user_rep.enable_totp(&user.id, step, hashes).await {
+        Ok(_) => Ok(RecoveryCodes {
+            recovery_codes: codes,
+        }),
+        Err(err) => Err(BaseError::new(err)),
+    }
+}
+
+/// Accepts a code from the authenticator app or an unused recovery code.
+/// Either works only once.
+pub async fn check_code(
+    user_rep: &(dyn TUserRepositories + Send + Sync),
+    user: &User,
+    code: &str,
+) -> Result<bool, String> {
+    let secret = match &user.totp_secret {
+        Some(secret) if user.totp_enabled_at.is_some() => secret,
+        _ => return Ok(false),
+    };
+
+    if let Some(step) = verify(secret, code) {
+        return user_rep.use_totp_step(&user.id, step).await;
+    }
+
+    // Salted hashes can't be looked up, every unused code is tried in turn.
+    let code = normalize_recovery_code(code);
+    let matched = user_rep
+        .find_recovery_codes(&user.id)
+        .await
+        .into_iter()
+        .find(|(_, hash)| verify_pwd(hash, &code));
+    match matched {
+        Some((code_id, _)) => user_rep.use_recovery_code(&user.id, code_id).await,
+        None => Ok(false),
+    }
+}
+
+fn new_recovery_codes(config: &Config) -> Result<(Vec<String>, Vec<String>), String> {
+    let codes = generate_recovery_codes();
+    let mut hashes = vec![];
+    for code in &codes {
+        match hash_pwd(config, &normalize_recovery_code(code)) {
+            Ok((_, hash)) => hashes.push(hash),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok((codes, hashes))
+}
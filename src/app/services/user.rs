@@ -1,51 +1,113 @@
 use crate::app::{
     config::Config,
-    entities::user::User,
+    entities::session::Session,
     errors::BaseError,
     traits::repositories::user::TUserRepositories,
-    utils::jwt::{ClaimType, JWT},
+    utils::{
+        hash::{hash_pwd, verify_pwd},
+        validate::validate,
+    },
 };
+use chrono::Duration;
+
+use super::{auth::ChangePasswordInputData, current_user::CurrentUser};
 
 pub struct UserService<'a> {
     config: &'a Config,
     user_rep: &'a (dyn TUserRepositories + Send + Sync),
-    token: &'a str,
+    current: &'a CurrentUser,
 }
 
 impl<'a> UserService<'a> {
     pub fn new(
         config: &'a Config,
         user_rep: &'a (dyn TUserRepositories + Send + Sync),
-        token: &'a str,
+        current: &'a CurrentUser,
     ) -> Self {
         Self {
             config,
             user_rep,
-            token,
+            current,
         }
     }
 
-    pub async fn get_current_user(&self) -> Result<User, BaseError> {
-        let id = match self.id_from_token(self.token) {
-            Ok(id) => id,
-            Err(e) => return Err(e),
+    pub async fn sessions(&self) -> Result<Vec<Session>, BaseError> {
+        let (user, access_token) = match self.current.signed_in() {
+            Ok(res) => res,
+            Err(err) => return Err(err),
+        };
+
+        Ok(self
+            .user_rep
+            .find_sessions(
+                &user.id,
+                &access_token.token,
+                Duration::days(self.config.refresh_token_ttl),
+            )
+            .await)
+    }
+
+    pub async fn revoke_session(&self, id: i32) -> Result<(), BaseError> {
+        let (user, _) = match self.current.signed_in() {
+            Ok(res) => res,
+            Err(err) => return Err(err),
         };
-        let user = match self.user_rep.find_by_id(id.as_str()).await {
-            Some(user) => user,
-            None => return Err(BaseError::new("User not found".to_string())),
+
+        match self.user_rep.remove_session(&user.id, id).await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(BaseError::new("Session not found".to_string())),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+
+    /// Signs the user out everywhere, including the current session.
+    pub async fn revoke_sessions(&self) -> Result<(), BaseError> {
+        let (user, _) = match self.current.signed_in() {
+            Ok(res) => res,
+            Err(err) => return Err(err),
         };
-        let tokens = self.user_rep.find_tokens(&id).await;
 
-        match tokens.iter().find(|t| t.token == self.token) {
-            Some(_) => Ok(user),
-            None => Err(BaseError::new("Token is expired".to_string())),
+        match self.user_rep.remove_all_tokens(&user.id).await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(BaseError::new(err)),
         }
     }
 
-    fn id_from_token(&self, token: &str) -> Result<String, BaseError> {
-        match JWT::new(&self.config).parse(token, Some(ClaimType::Login)) {
-            Ok(claim) => Ok(claim.sub),
+    /// Changes the password of the current user and signs out their other
+    /// sessions.
+    pub async fn change_password(&self, data: ChangePasswordInputData) -> Result<(), BaseError> {
+        match validate(&data) {
+            Ok(_) => (),
+            Err(e) => return Err(e),
+        };
+
+        let (user, access_token) = match self.current.signed_in() {
+            Ok(res) => res,
+            Err(err) => return Err(err),
+        };
+
+        if !verify_pwd(&user.password_hash, &data.current_password) {
+            return Err(BaseError::new("Password is incorrect".to_string()));
+        }
+
+        let (password_alg, password_hash) = match hash_pwd(self.config, &data.password) {
+            Ok(res) => res,
             Err(e) => return Err(BaseError::new(e)),
+        };
+
+        match self
+            .user_rep
+            .update_password(
+                &user.id,
+                &password_hash,
+                &password_alg,
+                &access_token.family,
+                &user.id,
+            )
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(err) => Err(BaseError::new(err)),
         }
     }
 }
@@ -0,0 +1,214 @@
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::app::{
+    config::Config,
+    entities::{
+        role::{Permission, Role},
+        user::User,
+    },
+    errors::BaseError,
+    traits::repositories::user::TUserRepositories,
+    utils::{hash::hash_pwd, validate::validate},
+};
+
+use super::{
+    auth::{AuthService, CreateInputData, PasswordInputData},
+    current_user::CurrentUser,
+};
+
+#[derive(Debug, Validate, Deserialize)]
+pub struct UpdateInputData {
+    #[validate(email(message = "Email is invalid"))]
+    pub email: Option<String>,
+    pub role: Option<Role>,
+}
+
+pub struct UserAdminService<'a> {
+    config: &'a Config,
+    user_rep: &'a (dyn TUserRepositories + Send + Sync),
+    current: &'a CurrentUser,
+}
+
+impl<'a> UserAdminService<'a> {
+    pub fn new(
+        config: &'a Config,
+        user_rep: &'a (dyn TUserRepositories + Send + Sync),
+        current: &'a CurrentUser,
+    ) -> Self {
+        Self {
+            config,
+            user_rep,
+            current,
+        }
+    }
+
+    pub async fn get(&self) -> Result<Vec<User>, BaseError> {
+        let _ = match self.current.authorize(Permission::ManageUsers) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        Ok(self.user_rep.find().await)
+    }
+
+    pub async fn get_by_id(&self, id: &str) -> Result<User, BaseError> {
+        let _ = match self.current.authorize(Permission::ManageUsers) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        match self.user_rep.find_by_id(id).await {
+            Some(user) => Ok(user),
+            None => Err(BaseError::new("User not found".to_string())),
+        }
+    }
+
+    pub async fn create(&self, data: CreateInputData) -> Result<String, BaseError> {
+        let user = match self.current.authorize(Permission::ManageUsers) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        AuthService::new(self.config, self.user_rep)
+            .create(data, Some(&user.id))
+            .await
+    }
+
+    pub async fn update(&self, id: &str, data: UpdateInputData) -> Result<(), BaseError> {
+        match validate(&data) {
+            Ok(_) => (),
+            Err(e) => return Err(e),
+        };
+
+        let current = match self.current.authorize(Permission::ManageUsers) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        let user = match self.user_rep.find_by_id(id).await {
+            Some(user) => user,
+            None => return Err(BaseError::new("User not found".to_string())),
+        };
+
+        // Keeps at least one admin able to manage users.
+        if current.id == user.id && data.role.as_ref().is_some_and(|r| *r != Role::Admin) {
+            return Err(BaseError::new("You can't change your own role".to_string()));
+        }
+
+        if let Some(ref email) = data.email {
+            if let Some(other) = self.user_rep.find_by_email(email).await {
+                if other.id != user.id {
+                    return Err(BaseError::new("The email already using".to_string()));
+                }
+            }
+        }
+
+        let role = data.role.map(|r| r.to_string());
+        match self
+            .user_rep
+            .update(&user.id, data.email, role, &current.id)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+
+    /// Disabled users can't sign in, and their sessions are revoked at once.
+    pub async fn set_disabled(&self, id: &str, disabled: bool) -> Result<(), BaseError> {
+        let current = match self.current.authorize(Permission::ManageUsers) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        let user = match self.user_rep.find_by_id(id).await {
+            Some(user) => user,
+            None => return Err(BaseError::new("User not found".to_string())),
+        };
+
+        if current.id == user.id {
+            return Err(BaseError::new("You can't disable yourself".to_string()));
+        }
+
+        match self
+            .user_rep
+            .set_disabled(&user.id, disabled, &current.id)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+
+    /// Sets a new password and signs the user out everywhere.
+    pub async fn reset_password(&self, id: &str, data: PasswordInputData) -> Result<(), BaseError> {
+        match validate(&data) {
+            Ok(_) => (),
+            Err(e) => return Err(e),
+        };
+
+        let current = match self.current.authorize(Permission::ManageUsers) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        let user = match self.user_rep.find_by_id(id).await {
+            Some(user) => user,
+            None => return Err(BaseError::new("User not found".to_string())),
+        };
+
+        let (password_alg, password_hash) = match hash_pwd(self.config, &data.password) {
+            Ok(res) => res,
+            Err(e) => return Err(BaseError::new(e)),
+        };
+
+        match self
+            .user_rep
+            .update_password(&user.id, &password_hash, &password_alg, &None, &current.id)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+
+    /// Clears the failed sign ins of the user, lifting the lockout.
+    pub async fn unlock(&self, id: &str) -> Result<(), BaseError> {
+        let current = match self.current.authorize(Permission::ManageUsers) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        let user = match self.user_rep.find_by_id(id).await {
+            Some(user) => user,
+            None => return Err(BaseError::new("User not found".to_string())),
+        };
+
+        match self.user_rep.unlock(&user.id, &current.id).await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+
+    pub async fn delete(&self, id: &str) -> Result<(), BaseError> {
+        let current = match self.current.authorize(Permission::ManageUsers) {
+            Ok(user) => user,
+            Err(err) => return Err(err),
+        };
+
+        let user = match self.user_rep.find_by_id(id).await {
+            Some(user) => user,
+            None => return Err(BaseError::new("User not found".to_string())),
+        };
+
+        if current.id == user.id {
+            return Err(BaseError::new("You can't delete yourself".to_string()));
+        }
+
+        match self.user_rep.delete(&user.id, &current.id).await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(BaseError::new(err)),
+        }
+    }
+}
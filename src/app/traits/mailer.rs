@@ -0,0 +1,6 @@
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait TMailer {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> Result<(), String>;
+}
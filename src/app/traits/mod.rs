@@ -1 +1,3 @@
+pub mod mailer;
+pub mod oidc;
 pub mod repositories;
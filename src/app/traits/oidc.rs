@@ -0,0 +1,22 @@
+use async_trait::async_trait;
+
+/// The user as the identity provider vouches for them in the ID token.
+#[derive(Debug, Clone)]
+pub struct OidcIdentity {
+    pub email: String,
+    pub email_verified: bool,
+    pub nonce: Option<String>,
+}
+
+#[async_trait]
+pub trait TOidcProvider {
+    async fn authorize_url(
+        &self,
+        state: &str,
+        nonce: &str,
+        code_challenge: &str,
+    ) -> Result<String, String>;
+    /// Redeems the authorization code and checks the signature, issuer,
+    /// audience and expiry of the returned ID token.
+    async fn exchange(&self, code: &str, code_verifier: &str) -> Result<OidcIdentity, String>;
+}
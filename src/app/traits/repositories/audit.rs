@@ -0,0 +1,17 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::app::entities::audit::AuditEntry;
+
+#[async_trait]
+pub trait TAuditRepositories {
+    async fn find(
+        &self,
+        entity: Option<String>,
+        entity_id: Option<String>,
+        actor_id: Option<String>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Vec<AuditEntry>;
+}
@@ -1,32 +1,79 @@
 use async_trait::async_trait;
-use chrono::NaiveDateTime;
+use chrono::{DateTime, Utc};
 
-use crate::app::entities::form::Form;
+use crate::app::entities::{
+    form::{calendar::WorkingCalendar, shift::FormShift, slot::FormSlot, Form},
+    submission::reflow::SubmissionMove,
+};
 #[async_trait]
 pub trait TFormRepositories {
+    #[allow(clippy::too_many_arguments)]
     async fn insert(
         &self,
         name: &str,
         form_limit: i32,
         status: &str,
-        start_date: NaiveDateTime,
-        end_date: NaiveDateTime,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
         time_frame_duration: i32,
         exclude_form_ids: Vec<String>,
+        auto_schedule: bool,
+        calendar: Option<WorkingCalendar>,
+        time_zone: &str,
+        user_id: &str,
     ) -> Result<String, String>;
     async fn find(&self) -> Vec<Form>;
     async fn find_by_id(&self, id: &str) -> Option<Form>;
+    #[allow(clippy::too_many_arguments)]
     async fn update(
         &self,
         id: &str,
         name: Option<String>,
         form_limit: Option<i32>,
-        start_date: Option<NaiveDateTime>,
-        end_date: Option<NaiveDateTime>,
+        start_date: Option<DateTime<Utc>>,
+        end_date: Option<DateTime<Utc>>,
         time_frame_duration: Option<i32>,
         status: Option<String>,
         exclude_form_ids: Option<Vec<String>>,
-    ) -> Result<(), String>;
+        auto_schedule: Option<bool>,
+        calendar: Option<WorkingCalendar>,
+        time_zone: Option<String>,
+        reflow: Option<&(dyn Fn(i32) -> DateTime<Utc> + Send + Sync)>,
+        user_id: &str,
+    ) -> Result<Vec<SubmissionMove>, String>;
+    async fn reflow(
+        &self,
+        id: &str,
+        arrival_date: &(dyn Fn(i32) -> DateTime<Utc> + Send + Sync),
+        user_id: &str,
+    ) -> Result<Vec<SubmissionMove>, String>;
+    async fn find_scheduled(&self, now: DateTime<Utc>) -> Vec<Form>;
+    /// `user_id` is None for changes made by the scheduler.
+    async fn update_status(
+        &self,
+        id: &str,
+        from: &str,
+        to: &str,
+        user_id: Option<&str>,
+    ) -> Result<bool, String>;
+    async fn find_slots(&self, form_id: &str) -> Vec<FormSlot>;
+    async fn replace_slots(
+        &self,
+        form_id: &str,
+        slots: Vec<(DateTime<Utc>, DateTime<Utc>, i32)>,
+        user_id: &str,
+    ) -> Result<i32, String>;
+    async fn shift(
+        &self,
+        id: &str,
+        seconds: i64,
+        reason: Option<String>,
+        check: &(dyn Fn(DateTime<Utc>, DateTime<Utc>, Vec<DateTime<Utc>>) -> Result<(), String>
+              + Send
+              + Sync),
+        user_id: &str,
+    ) -> Result<FormShift, String>;
+    async fn find_shifts(&self, id: &str) -> Vec<FormShift>;
 
-    async fn delete(&self, id: &str) -> Result<(), String>;
+    async fn delete(&self, id: &str, user_id: &str) -> Result<(), String>;
 }
@@ -1,3 +1,4 @@
+pub mod audit;
 pub mod form;
 pub mod respondent;
 pub mod submission;
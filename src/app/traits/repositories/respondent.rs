@@ -1,9 +1,11 @@
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 
-use crate::app::entities::respondent::Respondent;
+use crate::app::entities::{form::Form, respondent::Respondent};
 
 #[async_trait]
 pub trait TRespondentRepositories {
+    #[allow(clippy::too_many_arguments)]
     async fn insert(
         &self,
         first_name: &str,
@@ -13,10 +15,12 @@ pub trait TRespondentRepositories {
         region: &str,
         children: i16,
         idp_code: &Option<String>,
+        user_id: &str,
     ) -> Result<String, String>;
     async fn find(&self, by_name: Option<String>, by_passport: Option<String>) -> Vec<Respondent>;
     async fn find_by_id(&self, id: &str) -> Option<Respondent>;
-    async fn delete(&self, id: &str) -> Result<(), String>;
+    async fn delete(&self, id: &str, user_id: &str) -> Result<(), String>;
+    #[allow(clippy::too_many_arguments)]
     async fn update(
         &self,
         id: &str,
@@ -27,5 +31,18 @@ pub trait TRespondentRepositories {
         phone: &Option<String>,
         children: &Option<i16>,
         idp_code: &Option<String>,
+        user_id: &str,
     ) -> Result<(), String>;
+    #[allow(clippy::too_many_arguments)]
+    async fn merge(
+        &self,
+        target_id: &str,
+        source_id: &str,
+        arrival_date: &(dyn for<'f> Fn(&'f Form, i32) -> DateTime<Utc> + Send + Sync),
+        phone: &Option<String>,
+        region: &Option<String>,
+        children: &Option<i16>,
+        idp_code: &Option<String>,
+        user_id: &str,
+    ) -> Result<(Vec<String>, Vec<String>), String>;
 }
@@ -1,7 +1,12 @@
 use async_trait::async_trait;
-use chrono::NaiveDateTime;
+use chrono::{DateTime, Utc};
 
-use crate::app::entities::submission::Submission;
+use crate::app::entities::submission::{
+    eligibility::EligibilityConflict,
+    history::StatusChange,
+    waitlist::{BookingOutcome, WaitlistEntry},
+    Submission,
+};
 
 #[async_trait]
 pub trait TSubmissionRepositories {
@@ -9,13 +14,40 @@ pub trait TSubmissionRepositories {
         &self,
         form_id: &str,
         respondent_id: &str,
-        arrival_date: NaiveDateTime,
-        sub_order: i32,
         status: &str,
-    ) -> Result<String, String>;
+        user_id: &str,
+        arrival_date: &(dyn Fn(i32) -> DateTime<Utc> + Send + Sync),
+    ) -> Result<BookingOutcome, String>;
+    async fn find_conflicts(&self, form_id: &str, respondent_id: &str) -> Vec<EligibilityConflict>;
     async fn find(&self, by_form: Option<String>, by_respondent: Option<String>)
         -> Vec<Submission>;
     async fn find_by_id(&self, id: &str) -> Option<Submission>;
-    async fn delete(&self, id: &str) -> Result<(), String>;
-    async fn update(&self, id: &str, status: &Option<String>) -> Result<(), String>;
+    async fn delete(
+        &self,
+        id: &str,
+        user_id: &str,
+        arrival_date: &(dyn Fn(i32) -> DateTime<Utc> + Send + Sync),
+    ) -> Result<(), String>;
+    async fn update(
+        &self,
+        id: &str,
+        status: &Option<String>,
+        user_id: &str,
+        arrival_date: &(dyn Fn(i32) -> DateTime<Utc> + Send + Sync),
+    ) -> Result<(), String>;
+    async fn find_history(&self, id: &str) -> Vec<StatusChange>;
+    async fn find_waitlist(&self, form_id: &str) -> Vec<WaitlistEntry>;
+    async fn move_waitlist_entry(
+        &self,
+        form_id: &str,
+        id: &str,
+        position: usize,
+        user_id: &str,
+    ) -> Result<(), String>;
+    async fn delete_waitlist_entry(
+        &self,
+        form_id: &str,
+        id: &str,
+        user_id: &str,
+    ) -> Result<(), String>;
 }
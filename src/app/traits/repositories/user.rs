@@ -1,19 +1,122 @@
 use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
 
-use crate::app::entities::user::{User, UserToken};
+use crate::app::entities::{
+    api_key::ApiKey,
+    oidc::OidcLogin,
+    session::Session,
+    user::{User, UserToken},
+};
 #[async_trait]
 pub trait TUserRepositories {
-    async fn insert(&self, email: &str, p_hash: &str, p_alg: &str) -> Result<String, String>;
+    async fn insert(
+        &self,
+        email: &str,
+        p_hash: &str,
+        p_alg: &str,
+        role: &str,
+        user_id: Option<&str>,
+    ) -> Result<String, String>;
     async fn find_by_email(&self, email: &str) -> Option<User>;
     async fn find_by_id(&self, id: &str) -> Option<User>;
-    async fn upsert_user_token(
+    async fn find(&self) -> Vec<User>;
+    async fn update(
+        &self,
+        id: &str,
+        email: Option<String>,
+        role: Option<String>,
+        actor_id: &str,
+    ) -> Result<(), String>;
+    async fn set_disabled(&self, id: &str, disabled: bool, actor_id: &str) -> Result<(), String>;
+    async fn update_password(
+        &self,
+        id: &str,
+        p_hash: &str,
+        p_alg: &str,
+        keep_family: &Option<String>,
+        actor_id: &str,
+    ) -> Result<(), String>;
+    async fn rehash_password(
+        &self,
+        id: &str,
+        old_hash: &str,
+        p_hash: &str,
+        p_alg: &str,
+    ) -> Result<(), String>;
+    async fn insert_reset_token(&self, user_id: &str, token: &str) -> Result<(), String>;
+    async fn reset_password(&self, token: &str, p_hash: &str, p_alg: &str) -> Result<bool, String>;
+    async fn delete(&self, id: &str, actor_id: &str) -> Result<(), String>;
+    async fn find_login_lock(&self, email: &str, ip: &Option<String>) -> Option<DateTime<Utc>>;
+    async fn record_login_failure(
+        &self,
+        email: &str,
+        ip: &Option<String>,
+        window: Duration,
+        lockout: &(dyn Fn(i32) -> Option<Duration> + Send + Sync),
+    ) -> Result<(), String>;
+    async fn clear_login_failures(&self, email: &str) -> Result<(), String>;
+    async fn count_reset_request(
+        &self,
+        kind: &str,
+        value: &str,
+        window: Duration,
+    ) -> Result<i32, String>;
+    async fn unlock(&self, id: &str, actor_id: &str) -> Result<(), String>;
+    async fn set_totp_secret(&self, id: &str, secret: &str) -> Result<(), String>;
+    async fn enable_totp(&self, id: &str, step: i64, codes: Vec<String>) -> Result<(), String>;
+    async fn disable_totp(&self, id: &str, actor_id: &str) -> Result<(), String>;
+    async fn use_totp_step(&self, id: &str, step: i64) -> Result<bool, String>;
+    async fn find_recovery_codes(&self, id: &str) -> Vec<(i32, String)>;
+    async fn use_recovery_code(&self, id: &str, code_id: i32) -> Result<bool, String>;
+    async fn replace_recovery_codes(&self, id: &str, codes: Vec<String>) -> Result<(), String>;
+    async fn find_setting(&self, key: &str) -> Option<String>;
+    async fn set_setting(&self, key: &str, value: &str, actor_id: &str) -> Result<(), String>;
+    async fn insert_api_key(
         &self,
         user_id: &str,
-        token: &str,
-        used_for: &str,
+        name: &str,
+        prefix: &str,
+        key_hash: &str,
+        permissions: Vec<String>,
+    ) -> Result<String, String>;
+    async fn find_api_keys(&self) -> Vec<ApiKey>;
+    async fn find_api_key_by_hash(&self, key_hash: &str) -> Option<ApiKey>;
+    async fn revoke_api_key(&self, id: &str, actor_id: &str) -> Result<bool, String>;
+    async fn touch_api_key(&self, id: &str);
+    async fn insert_oidc_login(
+        &self,
+        state: &str,
+        nonce: &str,
+        code_verifier: &str,
+    ) -> Result<(), String>;
+    async fn take_oidc_login(&self, state: &str, max_age: Duration) -> Option<OidcLogin>;
+    async fn insert_token_family(
+        &self,
+        user_id: &str,
+        access_token: &str,
+        refresh_token: &str,
+        device: &Option<String>,
+        ip: &Option<String>,
+        ttl: Duration,
+    ) -> Result<(), String>;
+    async fn rotate_refresh_token(
+        &self,
+        refresh_token: &str,
+        access_token: &str,
+        new_refresh_token: &str,
+        ttl: Duration,
     ) -> Result<bool, String>;
+    async fn find_token(&self, token: &str) -> Option<UserToken>;
+    async fn remove_token_family(&self, family: &str) -> Result<(), String>;
+    async fn touch_token(&self, token: &str);
+    async fn find_sessions(
+        &self,
+        user_id: &str,
+        current_token: &str,
+        ttl: Duration,
+    ) -> Vec<Session>;
+    async fn remove_session(&self, user_id: &str, id: i32) -> Result<bool, String>;
+    async fn remove_all_tokens(&self, user_id: &str) -> Result<(), String>;
 
     async fn remove_user_tokens(&self, user_id: &str, tokens: Vec<&str>) -> Result<(), String>;
-
-    async fn find_tokens(&self, user_id: &str) -> Vec<UserToken>;
 }
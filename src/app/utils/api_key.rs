@@ -0,0 +1,22 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+const PREFIX: &str = "idp_";
+
+/// Returns a new key and the part of it that is kept in clear to tell keys apart.
+pub fn generate() -> (String, String) {
+    let mut bytes = [0u8; 24];
+    OsRng.fill_bytes(&mut bytes);
+    let secret: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    let key = format!("{}{}", PREFIX, secret);
+    let prefix = key[..PREFIX.len() + 8].to_string();
+    (key, prefix)
+}
+
+pub fn hash(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(PREFIX)
+}
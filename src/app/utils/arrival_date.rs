@@ -1,11 +1,361 @@
-use crate::app::entities::form::Form;
-use chrono::{DateTime, Utc};
+use crate::app::entities::form::{calendar::WorkingCalendar, Form};
+use chrono::{DateTime, Duration, LocalResult, TimeZone, Utc};
 
 pub fn calculate_arrival_date(form: &Form, order: u16) -> DateTime<Utc> {
+    if let Some(ref calendar) = form.calendar {
+        return calculate_by_calendar(form, calendar, order);
+    }
+
     let index =
         form.end_date.timestamp() - form.start_date.timestamp() - form.time_frame_duration as i64;
     let t = order as f64 / form.limit as f64;
     let exact_secs = (t * index as f64 + form.start_date.timestamp() as f64) as i64 - 1;
-    let secs = exact_secs - (exact_secs % form.time_frame_duration as i64);
-    DateTime::from_timestamp(secs, 0).unwrap()
+    let exact = DateTime::from_timestamp(exact_secs, 0).unwrap();
+    round_to_frame(form, exact)
+}
+
+// Frames are aligned on the form's local wall clock, so a 30 minute frame starts
+// at :00 and :30 in Kyiv regardless of the current UTC offset.
+fn round_to_frame(form: &Form, date: DateTime<Utc>) -> DateTime<Utc> {
+    let frame = form.time_frame_duration as i64;
+    if frame == 0 {
+        return date;
+    }
+
+    let local_secs = date
+        .with_timezone(&form.time_zone)
+        .naive_local()
+        .and_utc()
+        .timestamp();
+    let rounded = DateTime::from_timestamp(local_secs - local_secs.rem_euclid(frame), 0)
+        .unwrap()
+        .naive_utc();
+
+    match form.time_zone.from_local_datetime(&rounded) {
+        LocalResult::Single(value) => value.with_timezone(&Utc),
+        LocalResult::Ambiguous(value, _) => value.with_timezone(&Utc),
+        // The frame start falls into a DST gap, keep the unrounded time.
+        LocalResult::None => date,
+    }
+}
+
+/// Splits the form schedule into whole `time_frame_duration` windows. With a
+/// calendar only open periods are used, otherwise the whole start..end range
+/// is split on local wall-clock frame boundaries.
+pub fn time_frames(form: &Form) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+    let frame = form.time_frame_duration as i64;
+    if frame == 0 {
+        return vec![];
+    }
+
+    let periods = match form.calendar {
+        Some(ref calendar) => {
+            calendar.open_periods(form.start_date, form.end_date, &form.time_zone)
+        }
+        None => {
+            let first = round_to_frame(form, form.start_date);
+            let first = if first < form.start_date {
+                first + Duration::seconds(frame)
+            } else {
+                first
+            };
+            vec![(first, form.end_date)]
+        }
+    };
+
+    let mut frames = vec![];
+    for (start, end) in periods {
+        let mut from = start;
+        while from + Duration::seconds(frame) <= end {
+            frames.push((from, from + Duration::seconds(frame)));
+            from += Duration::seconds(frame);
+        }
+    }
+    frames
+}
+
+// Splits every open period into whole frames and spreads `limit` places evenly
+// over all of them, so an arrival never falls into a break or a closed day.
+fn calculate_by_calendar(form: &Form, calendar: &WorkingCalendar, order: u16) -> DateTime<Utc> {
+    let frame = (form.time_frame_duration as i64).max(1);
+    let frames: Vec<(DateTime<Utc>, i64)> = calendar
+        .open_periods(form.start_date, form.end_date, &form.time_zone)
+        .into_iter()
+        .map(|(start, end)| (start, (end - start).num_seconds() / frame))
+        .filter(|(_, count)| *count > 0)
+        .collect();
+
+    let total: i64 = frames.iter().map(|(_, count)| count).sum();
+    if total == 0 || form.limit == 0 {
+        return form.start_date;
+    }
+
+    let mut index = (order.max(1) as i64 - 1) * total / form.limit as i64;
+    for (start, count) in frames.iter() {
+        if index < *count {
+            return DateTime::from_timestamp(start.timestamp() + index * frame, 0).unwrap();
+        }
+        index -= count;
+    }
+
+    form.start_date
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, NaiveTime, Utc, Weekday};
+
+    use super::*;
+    use crate::app::entities::form::{calendar::TimeRange, status::FormStatus, DEFAULT_TIME_ZONE};
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn time(value: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(value, "%H:%M").unwrap()
+    }
+
+    fn form(start: &str, end: &str, limit: u16, frame: u16) -> Form {
+        let start_date = utc(start);
+        let end_date = utc(end);
+        Form {
+            id: "form".to_string(),
+            name: "Form".to_string(),
+            limit,
+            status: FormStatus::Open,
+            time_frame_duration: frame,
+            start_date,
+            end_date,
+            created_at: start_date,
+            exclude_form_ids: vec![],
+            auto_schedule: false,
+            calendar: None,
+            time_zone: DEFAULT_TIME_ZONE,
+            local_start_date: start_date.naive_utc(),
+            local_end_date: end_date.naive_utc(),
+        }
+    }
+
+    fn calendar(opens_at: &str, closes_at: &str) -> WorkingCalendar {
+        WorkingCalendar {
+            opens_at: time(opens_at),
+            closes_at: time(closes_at),
+            breaks: vec![],
+            excluded_weekdays: vec![],
+            holidays: vec![],
+        }
+    }
+
+    // Monday and Tuesday in Kyiv (UTC+2), open 09:00-11:00 in hour frames,
+    // which makes four frames: 07:00Z and 08:00Z on both days.
+    fn two_days(limit: u16) -> Form {
+        let mut form = form("2026-11-01T22:00:00Z", "2026-11-03T22:00:00Z", limit, 3600);
+        form.calendar = Some(calendar("09:00", "11:00"));
+        form
+    }
+
+    #[test]
+    fn calendar_shares_frames_when_limit_exceeds_them() {
+        let form = two_days(10);
+        let dates: Vec<DateTime<Utc>> = (1..=10)
+            .map(|order| calculate_arrival_date(&form, order))
+            .collect();
+
+        assert_eq!(dates[0], utc("2026-11-02T07:00:00Z"));
+        assert_eq!(dates[2], utc("2026-11-02T07:00:00Z"));
+        assert_eq!(dates[3], utc("2026-11-02T08:00:00Z"));
+        assert_eq!(dates[9], utc("2026-11-03T08:00:00Z"));
+        assert!(dates.windows(2).all(|pair| pair[0] <= pair[1]));
+        let frames = time_frames(&form);
+        assert!(dates
+            .iter()
+            .all(|date| frames.iter().any(|(start, _)| start == date)));
+    }
+
+    #[test]
+    fn calendar_puts_first_order_at_first_frame() {
+        let form = two_days(4);
+        assert_eq!(
+            calculate_arrival_date(&form, 1),
+            utc("2026-11-02T07:00:00Z")
+        );
+        // Order 0 doesn't exist, it is treated as the first one.
+        assert_eq!(
+            calculate_arrival_date(&form, 0),
+            utc("2026-11-02T07:00:00Z")
+        );
+    }
+
+    #[test]
+    fn calendar_puts_last_order_at_last_frame() {
+        let form = two_days(4);
+        assert_eq!(
+            calculate_arrival_date(&form, 2),
+            utc("2026-11-02T08:00:00Z")
+        );
+        assert_eq!(
+            calculate_arrival_date(&form, 3),
+            utc("2026-11-03T07:00:00Z")
+        );
+        assert_eq!(
+            calculate_arrival_date(&form, 4),
+            utc("2026-11-03T08:00:00Z")
+        );
+    }
+
+    #[test]
+    fn calendar_skips_breaks_and_closed_days() {
+        let mut form = form("2026-11-01T22:00:00Z", "2026-11-04T22:00:00Z", 3, 3600);
+        let mut calendar = calendar("09:00", "12:00");
+        calendar.breaks = vec![TimeRange {
+            start: time("10:00"),
+            end: time("11:00"),
+        }];
+        calendar.excluded_weekdays = vec![Weekday::Tue];
+        form.calendar = Some(calendar);
+
+        // Frames: Mon 07:00Z, Mon 09:00Z, Wed 07:00Z, Wed 09:00Z.
+        assert_eq!(
+            calculate_arrival_date(&form, 1),
+            utc("2026-11-02T07:00:00Z")
+        );
+        assert_eq!(
+            calculate_arrival_date(&form, 2),
+            utc("2026-11-02T09:00:00Z")
+        );
+        assert_eq!(
+            calculate_arrival_date(&form, 3),
+            utc("2026-11-04T07:00:00Z")
+        );
+    }
+
+    #[test]
+    fn calendar_without_open_frames_falls_back_to_start_date() {
+        let mut closed = two_days(4);
+        if let Some(ref mut calendar) = closed.calendar {
+            calendar.excluded_weekdays = vec![Weekday::Mon, Weekday::Tue];
+        }
+        assert_eq!(calculate_arrival_date(&closed, 1), closed.start_date);
+        assert_eq!(calculate_arrival_date(&closed, 4), closed.start_date);
+
+        // Opening hours shorter than one frame leave no frame either.
+        let mut short = two_days(4);
+        short.calendar = Some(calendar("09:00", "09:30"));
+        assert_eq!(calculate_arrival_date(&short, 2), short.start_date);
+
+        let no_places = two_days(0);
+        assert_eq!(calculate_arrival_date(&no_places, 1), no_places.start_date);
+    }
+
+    // Kyiv moves from 03:00 EET to 04:00 EEST on 2026-03-29 at 01:00Z and
+    // back from 04:00 EEST to 03:00 EET on 2026-10-25 at 01:00Z.
+
+    #[test]
+    fn rounds_to_frames_of_the_local_wall_clock() {
+        let form = form("2026-11-02T00:00:00Z", "2026-11-30T00:00:00Z", 10, 1800);
+        // 09:10 EET and 09:10 EEST both round down to 09:00 local.
+        assert_eq!(
+            round_to_frame(&form, utc("2026-11-02T07:10:00Z")),
+            utc("2026-11-02T07:00:00Z")
+        );
+        assert_eq!(
+            round_to_frame(&form, utc("2026-07-01T06:10:00Z")),
+            utc("2026-07-01T06:00:00Z")
+        );
+
+        // Half-hour offsets align hours to the local clock, not to UTC.
+        let mut kolkata = form.clone();
+        kolkata.time_frame_duration = 3600;
+        kolkata.time_zone = chrono_tz::Asia::Kolkata;
+        assert_eq!(
+            round_to_frame(&kolkata, utc("2026-11-02T04:00:00Z")),
+            utc("2026-11-02T03:30:00Z")
+        );
+    }
+
+    #[test]
+    fn keeps_the_time_when_the_frame_start_falls_into_the_gap() {
+        let form = form("2026-03-28T00:00:00Z", "2026-03-30T00:00:00Z", 10, 5400);
+        // 04:15 EEST rounds to 03:00, which doesn't exist that night.
+        let date = utc("2026-03-29T01:15:00Z");
+        assert_eq!(round_to_frame(&form, date), date);
+        // 04:30 EEST is a frame start of its own.
+        assert_eq!(
+            round_to_frame(&form, utc("2026-03-29T01:40:00Z")),
+            utc("2026-03-29T01:30:00Z")
+        );
+    }
+
+    #[test]
+    fn picks_the_earlier_offset_for_a_repeated_frame_start() {
+        let form = form("2026-10-24T00:00:00Z", "2026-10-26T00:00:00Z", 10, 3600);
+        // 03:40 EET rounds to 03:00, first seen as 03:00 EEST.
+        assert_eq!(
+            round_to_frame(&form, utc("2026-10-25T01:40:00Z")),
+            utc("2026-10-25T00:00:00Z")
+        );
+        // 03:40 EEST rounds to the same 03:00 EEST.
+        assert_eq!(
+            round_to_frame(&form, utc("2026-10-25T00:40:00Z")),
+            utc("2026-10-25T00:00:00Z")
+        );
+    }
+
+    #[test]
+    fn splits_whole_frames_across_spring_forward() {
+        // 00:10 EET to 06:00 EEST, five real hours.
+        let form = form("2026-03-28T22:10:00Z", "2026-03-29T03:00:00Z", 10, 3600);
+        let starts: Vec<DateTime<Utc>> = time_frames(&form)
+            .into_iter()
+            .map(|(start, end)| {
+                assert_eq!(end - start, Duration::hours(1));
+                start
+            })
+            .collect();
+
+        // The first frame starts at the next local hour, 01:00 EET.
+        assert_eq!(
+            starts,
+            vec![
+                utc("2026-03-28T23:00:00Z"),
+                utc("2026-03-29T00:00:00Z"),
+                utc("2026-03-29T01:00:00Z"),
+                utc("2026-03-29T02:00:00Z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_whole_frames_across_fall_back() {
+        // 02:00 EEST to 05:00 EET, four real hours with 03:00 twice.
+        let form = form("2026-10-24T23:00:00Z", "2026-10-25T03:00:00Z", 10, 3600);
+        let frames = time_frames(&form);
+
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0].0, utc("2026-10-24T23:00:00Z"));
+        assert!(frames.windows(2).all(|pair| pair[0].1 == pair[1].0));
+        assert_eq!(frames[3].1, utc("2026-10-25T03:00:00Z"));
+    }
+
+    #[test]
+    fn calendar_frames_follow_local_hours_on_dst_days() {
+        // Open 02:00-05:00 local: two real hours in spring, four in autumn.
+        let mut spring = form("2026-03-28T22:00:00Z", "2026-03-29T21:00:00Z", 10, 3600);
+        spring.calendar = Some(calendar("02:00", "05:00"));
+        let starts: Vec<DateTime<Utc>> = time_frames(&spring).into_iter().map(|f| f.0).collect();
+        assert_eq!(
+            starts,
+            vec![utc("2026-03-29T00:00:00Z"), utc("2026-03-29T01:00:00Z")]
+        );
+
+        let mut autumn = form("2026-10-24T21:00:00Z", "2026-10-25T22:00:00Z", 10, 3600);
+        autumn.calendar = Some(calendar("02:00", "05:00"));
+        let frames = time_frames(&autumn);
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0].0, utc("2026-10-24T23:00:00Z"));
+        assert_eq!(frames[3].1, utc("2026-10-25T03:00:00Z"));
+    }
 }
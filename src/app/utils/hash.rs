@@ -1,11 +1,19 @@
 use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
-    Argon2,
+    Algorithm, Argon2, Params, Version,
 };
 
-pub fn hash_pwd(pwd: &str) -> Result<(String, String), String> {
+use std::sync::OnceLock;
+
+use crate::app::config::Config;
+
+/// Hash the sign in checks when there is no real one, made on first use with
+/// the configured parameters.
+static DUMMY_HASH: OnceLock<Option<String>> = OnceLock::new();
+
+pub fn hash_pwd(config: &Config, pwd: &str) -> Result<(String, String), String> {
+    let argon2 = hasher(config)?;
     let salt = SaltString::generate(&mut OsRng);
-    let argon2 = Argon2::default();
     let result = argon2.hash_password(pwd.as_bytes(), &salt);
     match result {
         Ok(hash) => Ok((hash.algorithm.to_string(), hash.to_string())),
@@ -13,6 +21,8 @@ pub fn hash_pwd(pwd: &str) -> Result<(String, String), String> {
     }
 }
 
+/// Checks the password with the algorithm and parameters stored in the hash,
+/// so older hashes keep working after the configuration changes.
 pub fn verify_pwd(hash: &str, pwd: &str) -> bool {
     let parsed_hash = PasswordHash::new(hash);
 
@@ -21,8 +31,55 @@ pub fn verify_pwd(hash: &str, pwd: &str) -> bool {
     }
     let argon2 = Argon2::default();
     let res = argon2.verify_password(pwd.as_bytes(), &parsed_hash.unwrap());
-    match res {
-        Ok(_) => true,
-        Err(_) => false,
+    res.is_ok()
+}
+
+/// Same as `verify_pwd`, but a missing or unusable hash still costs a full
+/// Argon2 run, so the response time doesn't tell which accounts exist.
+pub fn verify_pwd_or_dummy(config: &Config, hash: Option<&str>, pwd: &str) -> bool {
+    if let Some(hash) = hash.filter(|hash| PasswordHash::new(hash).is_ok()) {
+        return verify_pwd(hash, pwd);
+    }
+
+    let dummy = DUMMY_HASH.get_or_init(|| match hash_pwd(config, "dummy password") {
+        Ok((_, hash)) => Some(hash),
+        Err(_) => None,
+    });
+    if let Some(dummy) = dummy {
+        verify_pwd(dummy, pwd);
     }
-}
\ No newline at end of file
+    false
+}
+
+/// Tells whether the hash was made with another algorithm or with other
+/// parameters than the configured ones.
+pub fn needs_rehash(config: &Config, alg: &str, hash: &str) -> bool {
+    let parsed_hash = match PasswordHash::new(hash) {
+        Ok(hash) => hash,
+        Err(_) => return true,
+    };
+    let params = match Params::try_from(&parsed_hash) {
+        Ok(params) => params,
+        Err(_) => return true,
+    };
+
+    alg != Algorithm::Argon2id.as_str()
+        || parsed_hash.algorithm != Algorithm::Argon2id.ident()
+        || parsed_hash.version != Some(Version::V0x13.into())
+        || params.m_cost() != config.argon2_memory
+        || params.t_cost() != config.argon2_iterations
+        || params.p_cost() != config.argon2_parallelism
+}
+
+fn hasher(config: &Config) -> Result<Argon2<'static>, String> {
+    let params = Params::new(
+        config.argon2_memory,
+        config.argon2_iterations,
+        config.argon2_parallelism,
+        None,
+    );
+    match params {
+        Ok(params) => Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params)),
+        Err(err) => Err(err.to_string()),
+    }
+}
@@ -1,14 +1,20 @@
-use crate::app::{config::Config, entities::user::User};
-use chrono::Utc;
-use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use crate::app::{config::Config, entities::user::User, utils::jwt_keys::JwtKeys};
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, decode_header, encode, Header, Validation};
 use serde::{Deserialize, Serialize};
 use std::{fmt, time::SystemTime};
 
+/// Minutes to enter the 2FA code after the password was accepted.
+const TWO_FACTOR_TOKEN_TTL: i64 = 5;
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum ClaimType {
     Refresh,
     Login,
+    Reset,
+    TwoFactor,
+    TwoFactorSetup,
 }
 
 impl fmt::Display for ClaimType {
@@ -16,6 +22,9 @@ impl fmt::Display for ClaimType {
         match self {
             ClaimType::Refresh => write!(f, "Refresh"),
             ClaimType::Login => write!(f, "Login"),
+            ClaimType::Reset => write!(f, "Reset"),
+            ClaimType::TwoFactor => write!(f, "TwoFactor"),
+            ClaimType::TwoFactorSetup => write!(f, "TwoFactorSetup"),
         }
     }
 }
@@ -28,14 +37,21 @@ pub struct Claims {
     pub iat: SystemTime,
 }
 
-pub struct JWT {
-    secret: String,
+#[allow(clippy::upper_case_acronyms)]
+pub struct JWT<'a> {
+    keys: &'a JwtKeys,
+    access_token_ttl: Duration,
+    refresh_token_ttl: Duration,
+    reset_token_ttl: Duration,
 }
 
-impl JWT {
-    pub fn new(config: &Config) -> Self {
+impl<'a> JWT<'a> {
+    pub fn new(config: &'a Config) -> Self {
         Self {
-            secret: config.jwt_secret_key.clone(),
+            keys: &config.jwt_keys,
+            access_token_ttl: Duration::minutes(config.access_token_ttl),
+            refresh_token_ttl: Duration::days(config.refresh_token_ttl),
+            reset_token_ttl: Duration::minutes(config.reset_token_ttl),
         }
     }
 
@@ -43,18 +59,69 @@ impl JWT {
         let claims = Claims {
             sub: user.id.to_owned(),
             claim_type: ClaimType::Login.to_string(),
-            exp: self.get_expiration(7),
+            exp: self.get_expiration(self.access_token_ttl),
+            iat: SystemTime::now(),
+        };
+        self.create(&claims)
+    }
+
+    pub fn refresh(&self, user: &User) -> Result<String, String> {
+        let claims = Claims {
+            sub: user.id.to_owned(),
+            claim_type: ClaimType::Refresh.to_string(),
+            exp: self.get_expiration(self.refresh_token_ttl),
+            iat: SystemTime::now(),
+        };
+        self.create(&claims)
+    }
+
+    pub fn reset(&self, user: &User) -> Result<String, String> {
+        let claims = Claims {
+            sub: user.id.to_owned(),
+            claim_type: ClaimType::Reset.to_string(),
+            exp: self.get_expiration(self.reset_token_ttl),
+            iat: SystemTime::now(),
+        };
+        self.create(&claims)
+    }
+
+    /// A short-lived proof of the password check, exchanged for tokens
+    /// together with a 2FA code.
+    pub fn two_factor(&self, user: &User) -> Result<String, String> {
+        let claims = Claims {
+            sub: user.id.to_owned(),
+            claim_type: ClaimType::TwoFactor.to_string(),
+            exp: self.get_expiration(Duration::minutes(TWO_FACTOR_TOKEN_TTL)),
+            iat: SystemTime::now(),
+        };
+        self.create(&claims)
+    }
+
+    /// Mailed to a user who has to enroll in 2FA, it proves access to the
+    /// mailbox before the secret is shown. Lives as long as a reset link.
+    pub fn two_factor_setup(&self, user: &User) -> Result<String, String> {
+        let claims = Claims {
+            sub: user.id.to_owned(),
+            claim_type: ClaimType::TwoFactorSetup.to_string(),
+            exp: self.get_expiration(self.reset_token_ttl),
             iat: SystemTime::now(),
         };
         self.create(&claims)
     }
 
     pub fn parse(&self, token: &str, claim_type: Option<ClaimType>) -> Result<Claims, String> {
-        let token_message = decode::<Claims>(
-            &token,
-            &DecodingKey::from_secret(self.secret.as_ref()),
-            &Validation::new(Algorithm::HS256),
-        );
+        let header = match decode_header(token) {
+            Ok(header) => header,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        // Tokens signed with a retired key stay valid until the key expires.
+        let key = match self.keys.find(header.kid.as_deref()) {
+            Some(key) if key.algorithm == header.alg => key,
+            _ => return Err("Token is not valid".to_string()),
+        };
+
+        let token_message = decode::<Claims>(token, &key.decoding, &Validation::new(key.algorithm));
 
         let claims = match token_message {
             Ok(data) => data.claims,
@@ -69,11 +136,14 @@ impl JWT {
     }
 
     fn create(&self, claims: &Claims) -> Result<String, String> {
-        let token_res = encode(
-            &Header::default(),
-            &claims,
-            &EncodingKey::from_secret(self.secret.as_ref()),
-        );
+        let key = match self.keys.active() {
+            Some(key) => key,
+            None => return Err("The signing key is expired".to_string()),
+        };
+
+        let mut header = Header::new(key.algorithm);
+        header.kid = Some(key.kid.clone());
+        let token_res = encode(&header, &claims, &key.encoding);
 
         match token_res {
             Ok(token) => Ok(token),
@@ -81,10 +151,111 @@ impl JWT {
         }
     }
 
-    fn get_expiration(&self, days: u8) -> usize {
+    fn get_expiration(&self, ttl: Duration) -> usize {
         Utc::now()
-            .checked_add_signed(chrono::Duration::days(days as i64))
+            .checked_add_signed(ttl)
             .expect("valid timestamp")
             .timestamp() as usize
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::{Algorithm, EncodingKey};
+    use serde_json::json;
+
+    use super::*;
+    use crate::app::utils::jwt_keys::tests::keys_file;
+
+    fn jwt(keys: &JwtKeys) -> JWT<'_> {
+        JWT {
+            keys,
+            access_token_ttl: Duration::minutes(15),
+            refresh_token_ttl: Duration::days(30),
+            reset_token_ttl: Duration::minutes(60),
+        }
+    }
+
+    fn claims(claim_type: ClaimType) -> Claims {
+        Claims {
+            sub: "user".to_string(),
+            claim_type: claim_type.to_string(),
+            exp: (Utc::now() + Duration::minutes(5)).timestamp() as usize,
+            iat: SystemTime::now(),
+        }
+    }
+
+    fn keys(active: &str) -> JwtKeys {
+        let file = keys_file(
+            &format!("jwt-{}", active),
+            json!([
+                { "kid": "ed1", "alg": "EdDSA", "privateKey": "ed" },
+                { "kid": "hs2", "alg": "HS256", "secret": "second secret" },
+            ]),
+        );
+        JwtKeys::load(
+            Some("legacy secret".to_string()),
+            Some(file),
+            Some(active.to_string()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn round_trips_with_the_active_key() {
+        for active in ["default", "ed1", "hs2"] {
+            let keys = keys(active);
+            let jwt = jwt(&keys);
+            let token = jwt.create(&claims(ClaimType::Login)).unwrap();
+
+            assert_eq!(decode_header(&token).unwrap().kid.as_deref(), Some(active));
+            let parsed = jwt.parse(&token, Some(ClaimType::Login)).unwrap();
+            assert_eq!(parsed.sub, "user");
+        }
+    }
+
+    #[test]
+    fn rejects_another_claim_type() {
+        let keys = keys("ed1");
+        let jwt = jwt(&keys);
+        let token = jwt.create(&claims(ClaimType::TwoFactor)).unwrap();
+
+        assert!(jwt.parse(&token, Some(ClaimType::Login)).is_err());
+        assert!(jwt.parse(&token, None).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_header_algorithm_other_than_the_key_one() {
+        let keys = keys("ed1");
+        let jwt = jwt(&keys);
+
+        // An HS256 token claiming the EdDSA key, signed with its public part.
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("ed1".to_string());
+        let public = keys.jwks()[0].x.clone().unwrap();
+        let forged = encode(
+            &header,
+            &claims(ClaimType::Login),
+            &EncodingKey::from_secret(public.as_bytes()),
+        )
+        .unwrap();
+        assert_eq!(
+            jwt.parse(&forged, None).err(),
+            Some("Token is not valid".to_string())
+        );
+    }
+
+    #[test]
+    fn verifies_tokens_without_kid_with_the_default_key() {
+        let keys = keys("ed1");
+        let jwt = jwt(&keys);
+        let header = Header::new(Algorithm::HS256);
+        let secret = |value: &str| EncodingKey::from_secret(value.as_bytes());
+
+        let legacy = encode(&header, &claims(ClaimType::Login), &secret("legacy secret")).unwrap();
+        assert!(jwt.parse(&legacy, Some(ClaimType::Login)).is_ok());
+
+        let other = encode(&header, &claims(ClaimType::Login), &secret("other secret")).unwrap();
+        assert!(jwt.parse(&other, Some(ClaimType::Login)).is_err());
+    }
+}
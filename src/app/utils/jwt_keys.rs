@@ -0,0 +1,375 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use serde::{Deserialize, Serialize};
+
+/// Kid of the `JWT_SECRET_KEY` key, also used for tokens issued without a kid.
+pub const DEFAULT_KID: &str = "default";
+
+/// Public part of an asymmetric key, as published in the JWKS.
+#[derive(Debug, Clone, Serialize)]
+pub struct Jwk {
+    pub kty: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crv: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub e: Option<String>,
+    pub kid: String,
+    pub alg: String,
+    #[serde(rename = "use")]
+    pub key_use: String,
+}
+
+pub struct SigningKey {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pub encoding: EncodingKey,
+    pub decoding: DecodingKey,
+    /// None for shared secrets, which must never be published.
+    pub jwk: Option<Jwk>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl SigningKey {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|at| at <= Utc::now())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KeyData {
+    kid: String,
+    alg: String,
+    secret: Option<String>,
+    /// Path to a PEM file with the PKCS#8 (or PKCS#1 for RSA) private key.
+    private_key: Option<String>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+pub struct JwtKeys {
+    active: String,
+    keys: Vec<SigningKey>,
+}
+
+impl JwtKeys {
+    /// Builds the key set from the legacy secret and a JSON file listing
+    /// `{ kid, alg, secret | privateKey, expiresAt? }` entries.
+    pub fn load(
+        secret: Option<String>,
+        keys_file: Option<String>,
+        active: Option<String>,
+    ) -> Result<Self, String> {
+        let mut keys = vec![];
+
+        if let Some(secret) = secret {
+            keys.push(SigningKey {
+                kid: DEFAULT_KID.to_string(),
+                algorithm: Algorithm::HS256,
+                encoding: EncodingKey::from_secret(secret.as_ref()),
+                decoding: DecodingKey::from_secret(secret.as_ref()),
+                jwk: None,
+                expires_at: None,
+            });
+        }
+
+        if let Some(path) = keys_file {
+            let content = match std::fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(err) => return Err(format!("{}: {}", path, err)),
+            };
+            let list: Vec<KeyData> = match serde_json::from_str(&content) {
+                Ok(list) => list,
+                Err(err) => return Err(format!("{}: {}", path, err)),
+            };
+            for data in list {
+                if keys.iter().any(|k| k.kid == data.kid) {
+                    return Err(format!("Duplicate key {}", data.kid));
+                }
+                match load_key(data) {
+                    Ok(key) => keys.push(key),
+                    Err(err) => return Err(err),
+                };
+            }
+        }
+
+        let active = active.unwrap_or(DEFAULT_KID.to_string());
+        match keys.iter().find(|k| k.kid == active) {
+            Some(key) if key.is_expired() => Err(format!("Active key {} is expired", active)),
+            Some(_) => Ok(Self { active, keys }),
+            None => Err(format!("Active key {} is not found", active)),
+        }
+    }
+
+    /// The key new tokens are signed with.
+    pub fn active(&self) -> Option<&SigningKey> {
+        self.find(Some(&self.active))
+    }
+
+    /// Any key still accepted for verification.
+    pub fn find(&self, kid: Option<&str>) -> Option<&SigningKey> {
+        let kid = kid.unwrap_or(DEFAULT_KID);
+        self.keys.iter().find(|k| k.kid == kid && !k.is_expired())
+    }
+
+    pub fn jwks(&self) -> Vec<Jwk> {
+        self.keys
+            .iter()
+            .filter(|k| !k.is_expired())
+            .filter_map(|k| k.jwk.clone())
+            .collect()
+    }
+}
+
+fn load_key(data: KeyData) -> Result<SigningKey, String> {
+    let kid = data.kid;
+    let (algorithm, encoding, decoding, jwk) =
+        match (data.alg.as_str(), data.secret, data.private_key) {
+            ("HS256", Some(secret), None) => (
+                Algorithm::HS256,
+                EncodingKey::from_secret(secret.as_ref()),
+                DecodingKey::from_secret(secret.as_ref()),
+                None,
+            ),
+            ("EdDSA", None, Some(path)) => {
+                let (pem, der) = match read_pem(&path) {
+                    Ok(res) => res,
+                    Err(err) => return Err(format!("Key {}: {}", kid, err)),
+                };
+                let encoding = match EncodingKey::from_ed_pem(&pem) {
+                    Ok(key) => key,
+                    Err(err) => return Err(format!("Key {}: {}", kid, err)),
+                };
+                let pair = match Ed25519KeyPair::from_pkcs8_maybe_unchecked(&der) {
+                    Ok(pair) => pair,
+                    Err(err) => return Err(format!("Key {}: {}", kid, err)),
+                };
+                let x = URL_SAFE_NO_PAD.encode(pair.public_key().as_ref());
+                let decoding = match DecodingKey::from_ed_components(&x) {
+                    Ok(key) => key,
+                    Err(err) => return Err(format!("Key {}: {}", kid, err)),
+                };
+                let jwk = Jwk {
+                    kty: "OKP".to_string(),
+                    crv: Some("Ed25519".to_string()),
+                    x: Some(x),
+                    n: None,
+                    e: None,
+                    kid: kid.clone(),
+                    alg: data.alg,
+                    key_use: "sig".to_string(),
+                };
+                (Algorithm::EdDSA, encoding, decoding, Some(jwk))
+            }
+            ("RS256", None, Some(path)) => {
+                let (pem, der) = match read_pem(&path) {
+                    Ok(res) => res,
+                    Err(err) => return Err(format!("Key {}: {}", kid, err)),
+                };
+                let encoding = match EncodingKey::from_rsa_pem(&pem) {
+                    Ok(key) => key,
+                    Err(err) => return Err(format!("Key {}: {}", kid, err)),
+                };
+                let pair = match ring::rsa::KeyPair::from_pkcs8(&der)
+                    .or_else(|_| ring::rsa::KeyPair::from_der(&der))
+                {
+                    Ok(pair) => pair,
+                    Err(err) => return Err(format!("Key {}: {}", kid, err)),
+                };
+                let public = ring::rsa::PublicKeyComponents::<Vec<u8>>::from(pair.public());
+                let n = URL_SAFE_NO_PAD.encode(&public.n);
+                let e = URL_SAFE_NO_PAD.encode(&public.e);
+                let decoding = match DecodingKey::from_rsa_components(&n, &e) {
+                    Ok(key) => key,
+                    Err(err) => return Err(format!("Key {}: {}", kid, err)),
+                };
+                let jwk = Jwk {
+                    kty: "RSA".to_string(),
+                    crv: None,
+                    x: None,
+                    n: Some(n),
+                    e: Some(e),
+                    kid: kid.clone(),
+                    alg: data.alg,
+                    key_use: "sig".to_string(),
+                };
+                (Algorithm::RS256, encoding, decoding, Some(jwk))
+            }
+            (alg @ ("HS256" | "EdDSA" | "RS256"), _, _) => {
+                return Err(format!(
+                    "Key {}: {} needs {}",
+                    kid,
+                    alg,
+                    if alg == "HS256" {
+                        "a secret"
+                    } else {
+                        "a privateKey"
+                    }
+                ))
+            }
+            (alg, _, _) => return Err(format!("Key {}: unsupported algorithm {}", kid, alg)),
+        };
+
+    Ok(SigningKey {
+        kid,
+        algorithm,
+        encoding,
+        decoding,
+        jwk,
+        expires_at: data.expires_at,
+    })
+}
+
+/// Returns the file content together with the DER it wraps.
+fn read_pem(path: &str) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let content = match std::fs::read(path) {
+        Ok(content) => content,
+        Err(err) => return Err(format!("{}: {}", path, err)),
+    };
+    match pem::parse(&content) {
+        Ok(parsed) => Ok((content, parsed.contents().to_vec())),
+        Err(err) => Err(format!("{}: {}", path, err)),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use ring::rand::SystemRandom;
+    use serde_json::json;
+    use std::{
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+
+    static FILES: AtomicUsize = AtomicUsize::new(0);
+
+    /// Writes a fresh Ed25519 key and a keys file listing `entries`, where
+    /// `"privateKey": "ed"` stands for that key.
+    pub fn keys_file(name: &str, entries: serde_json::Value) -> String {
+        let dir = std::env::temp_dir().join(format!(
+            "jwt-keys-{}-{}-{}",
+            std::process::id(),
+            name,
+            FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key_path: PathBuf = dir.join("ed.pem");
+        let pem = pem::encode(&pem::Pem::new("PRIVATE KEY", pkcs8.as_ref().to_vec()));
+        std::fs::write(&key_path, pem).unwrap();
+
+        let content = entries.to_string().replace(
+            "\"privateKey\":\"ed\"",
+            &format!("\"privateKey\":{:?}", key_path),
+        );
+        let path = dir.join("keys.json");
+        std::fs::write(&path, content).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn rotated() -> JwtKeys {
+        let file = keys_file(
+            "rotated",
+            json!([
+                { "kid": "ed1", "alg": "EdDSA", "privateKey": "ed" },
+                { "kid": "hs2", "alg": "HS256", "secret": "second secret" },
+                { "kid": "old", "alg": "HS256", "secret": "old secret", "expiresAt": "2020-01-01T00:00:00Z" },
+                { "kid": "next", "alg": "HS256", "secret": "next secret", "expiresAt": "2100-01-01T00:00:00Z" },
+            ]),
+        );
+        JwtKeys::load(
+            Some("legacy secret".to_string()),
+            Some(file),
+            Some("ed1".to_string()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn selects_keys_by_kid() {
+        let keys = rotated();
+        assert_eq!(keys.active().unwrap().kid, "ed1");
+        assert_eq!(keys.active().unwrap().algorithm, Algorithm::EdDSA);
+        assert_eq!(keys.find(Some("hs2")).unwrap().kid, "hs2");
+        assert_eq!(keys.find(Some("next")).unwrap().kid, "next");
+        assert!(keys.find(Some("unknown")).is_none());
+    }
+
+    #[test]
+    fn falls_back_to_the_default_key_without_kid() {
+        let keys = rotated();
+        assert_eq!(keys.find(None).unwrap().kid, DEFAULT_KID);
+
+        let file = keys_file(
+            "no-default",
+            json!([{ "kid": "ed1", "alg": "EdDSA", "privateKey": "ed" }]),
+        );
+        let keys = JwtKeys::load(None, Some(file), Some("ed1".to_string())).unwrap();
+        assert!(keys.find(None).is_none());
+    }
+
+    #[test]
+    fn rejects_retired_keys_past_expiry() {
+        let keys = rotated();
+        assert!(keys.find(Some("old")).is_none());
+
+        let file = keys_file(
+            "expired-active",
+            json!([{ "kid": "old", "alg": "HS256", "secret": "old", "expiresAt": "2020-01-01T00:00:00Z" }]),
+        );
+        let err = JwtKeys::load(None, Some(file), Some("old".to_string()));
+        assert_eq!(err.err(), Some("Active key old is expired".to_string()));
+    }
+
+    #[test]
+    fn never_publishes_shared_secrets() {
+        let keys = rotated();
+        let kids: Vec<String> = keys.jwks().into_iter().map(|jwk| jwk.kid).collect();
+        assert_eq!(kids, vec!["ed1".to_string()]);
+
+        let jwk = &keys.jwks()[0];
+        assert_eq!(jwk.kty, "OKP");
+        assert_eq!(jwk.alg, "EdDSA");
+        assert!(jwk.x.is_some());
+    }
+
+    #[test]
+    fn refuses_invalid_key_sets() {
+        let duplicate = keys_file(
+            "duplicate",
+            json!([{ "kid": "default", "alg": "HS256", "secret": "other" }]),
+        );
+        let err = JwtKeys::load(Some("secret".to_string()), Some(duplicate), None);
+        assert_eq!(err.err(), Some("Duplicate key default".to_string()));
+
+        let mismatched = keys_file(
+            "mismatched",
+            json!([{ "kid": "hs", "alg": "HS256", "privateKey": "ed" }]),
+        );
+        assert!(JwtKeys::load(None, Some(mismatched), Some("hs".to_string())).is_err());
+
+        let unsupported = keys_file(
+            "unsupported",
+            json!([{ "kid": "none", "alg": "none", "secret": "x" }]),
+        );
+        assert!(JwtKeys::load(None, Some(unsupported), Some("none".to_string())).is_err());
+
+        let err = JwtKeys::load(
+            Some("secret".to_string()),
+            None,
+            Some("missing".to_string()),
+        );
+        assert_eq!(
+            err.err(),
+            Some("Active key missing is not found".to_string())
+        );
+    }
+}
@@ -1,4 +1,9 @@
+pub mod api_key;
 pub mod arrival_date;
 pub mod hash;
 pub mod jwt;
+pub mod jwt_keys;
+pub mod pkce;
+pub mod totp;
+pub mod trusted_proxies;
 pub mod validate;
@@ -0,0 +1,84 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::Utc;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+const STEP: u64 = 30;
+const RECOVERY_CODES: usize = 10;
+/// 80 random bits per recovery code, 16 base32 characters.
+const RECOVERY_CODE_BYTES: usize = 10;
+
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    OsRng.fill_bytes(&mut bytes);
+    Secret::Raw(bytes.to_vec()).to_encoded().to_string()
+}
+
+/// The `otpauth://` URI authenticator apps read from a QR code.
+pub fn provisioning_uri(secret: &str, issuer: &str, email: &str) -> Result<String, String> {
+    match build(secret, issuer, email) {
+        Ok(totp) => Ok(totp.get_url()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Returns the time step the code belongs to. One step of clock drift is
+/// accepted in both directions.
+pub fn verify(secret: &str, code: &str) -> Option<i64> {
+    let totp = match build(secret, "", "") {
+        Ok(totp) => totp,
+        Err(_) => return None,
+    };
+    let now = Utc::now().timestamp() as u64;
+
+    [now - STEP, now, now + STEP]
+        .into_iter()
+        .find(|time| totp.generate(*time) == code)
+        .map(|time| (time / STEP) as i64)
+}
+
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            let mut bytes = [0u8; RECOVERY_CODE_BYTES];
+            OsRng.fill_bytes(&mut bytes);
+            let code = Secret::Raw(bytes.to_vec()).to_encoded().to_string();
+            code.as_bytes()
+                .chunks(4)
+                .map(|chunk| String::from_utf8_lossy(chunk).to_lowercase())
+                .collect::<Vec<String>>()
+                .join("-")
+        })
+        .collect()
+}
+
+/// Recovery codes are hashed and compared ignoring case and dashes.
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn build(secret: &str, issuer: &str, email: &str) -> Result<TOTP, String> {
+    let bytes = match Secret::Encoded(secret.to_string()).to_bytes() {
+        Ok(bytes) => bytes,
+        Err(err) => return Err(err.to_string()),
+    };
+    let issuer = match issuer.is_empty() {
+        true => None,
+        false => Some(issuer.to_string()),
+    };
+
+    match TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        STEP,
+        bytes,
+        issuer,
+        email.to_string(),
+    ) {
+        Ok(totp) => Ok(totp),
+        Err(err) => Err(err.to_string()),
+    }
+}
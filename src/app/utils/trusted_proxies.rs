@@ -0,0 +1,164 @@
+use std::net::IpAddr;
+
+/// Reverse proxies allowed to tell the client address in `X-Forwarded-For`.
+/// Without any, the header is ignored: the client sets it to anything.
+#[derive(Debug, Default)]
+pub struct TrustedProxies {
+    networks: Vec<(IpAddr, u8)>,
+}
+
+impl TrustedProxies {
+    /// Reads a comma separated list of addresses and CIDR ranges.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut networks = vec![];
+        for item in value.split(',').map(|v| v.trim()).filter(|v| !v.is_empty()) {
+            let (address, prefix) = match item.split_once('/') {
+                Some((address, prefix)) => (address, Some(prefix)),
+                None => (item, None),
+            };
+            let address: IpAddr = match address.parse() {
+                Ok(address) => address,
+                Err(_) => return Err(format!("Invalid proxy address {}", item)),
+            };
+            let width = bit_width(&address);
+            let prefix = match prefix.map(|v| v.parse::<u8>()) {
+                None => width,
+                Some(Ok(prefix)) if prefix <= width => prefix,
+                Some(_) => return Err(format!("Invalid proxy address {}", item)),
+            };
+            networks.push((address, prefix));
+        }
+        Ok(Self { networks })
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.networks.iter().any(|(network, prefix)| {
+            bit_width(network) == bit_width(ip) && {
+                let shift = bit_width(ip) - prefix;
+                let mask = u128::MAX.checked_shl(shift as u32).unwrap_or(0);
+                bits(network) & mask == bits(ip) & mask
+            }
+        })
+    }
+
+    /// The socket peer, unless it is a trusted proxy. Then the rightmost
+    /// forwarded hop that isn't one, as every hop left of it could be made up.
+    pub fn client_ip(&self, peer: Option<IpAddr>, forwarded_for: Option<&str>) -> Option<IpAddr> {
+        let mut client = peer?;
+        if !self.contains(&client) {
+            return Some(client);
+        }
+
+        for hop in forwarded_for.unwrap_or_default().rsplit(',') {
+            match hop.trim().parse::<IpAddr>() {
+                Ok(ip) if self.contains(&ip) => client = ip,
+                Ok(ip) => return Some(ip),
+                Err(_) => break,
+            }
+        }
+        Some(client)
+    }
+}
+
+fn bit_width(ip: &IpAddr) -> u8 {
+    match ip {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+fn bits(ip: &IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(ip) => u32::from(*ip) as u128,
+        IpAddr::V6(ip) => u128::from(*ip),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_addresses_and_ranges() {
+        let proxies = TrustedProxies::parse(" 10.0.0.0/8, 192.168.1.1 ,,2001:db8::/32").unwrap();
+        assert!(proxies.contains(&ip("10.200.3.4")));
+        assert!(proxies.contains(&ip("192.168.1.1")));
+        assert!(!proxies.contains(&ip("192.168.1.2")));
+
+        assert!(TrustedProxies::parse("nope").is_err());
+        assert!(TrustedProxies::parse("10.0.0.0/33").is_err());
+        assert!(TrustedProxies::parse("2001:db8::/129").is_err());
+        assert!(TrustedProxies::parse("10.0.0.0/x").is_err());
+    }
+
+    #[test]
+    fn matches_ipv6_ranges() {
+        let proxies = TrustedProxies::parse("2001:db8::/32, fd00::1").unwrap();
+        assert!(proxies.contains(&ip("2001:db8:ffff::1")));
+        assert!(!proxies.contains(&ip("2001:db9::1")));
+        assert!(proxies.contains(&ip("fd00::1")));
+        assert!(!proxies.contains(&ip("fd00::2")));
+
+        // Ranges of one family never match addresses of the other.
+        let everything = TrustedProxies::parse("::/0").unwrap();
+        assert!(everything.contains(&ip("2001:db9::1")));
+        assert!(!everything.contains(&ip("127.0.0.1")));
+        let all_v4 = TrustedProxies::parse("0.0.0.0/0").unwrap();
+        assert!(all_v4.contains(&ip("203.0.113.7")));
+        assert!(!all_v4.contains(&ip("::1")));
+    }
+
+    #[test]
+    fn ignores_the_header_from_an_untrusted_peer() {
+        let proxies = TrustedProxies::parse("10.0.0.0/8").unwrap();
+        let client = proxies.client_ip(Some(ip("203.0.113.7")), Some("10.0.0.1, 1.2.3.4"));
+        assert_eq!(client, Some(ip("203.0.113.7")));
+
+        let none = TrustedProxies::default();
+        let client = none.client_ip(Some(ip("10.0.0.1")), Some("1.2.3.4"));
+        assert_eq!(client, Some(ip("10.0.0.1")));
+    }
+
+    #[test]
+    fn walks_back_through_trusted_proxies() {
+        let proxies = TrustedProxies::parse("10.0.0.0/8").unwrap();
+        let peer = Some(ip("10.0.0.1"));
+
+        let client = proxies.client_ip(peer, Some("203.0.113.7, 10.0.0.5, 10.0.0.2"));
+        assert_eq!(client, Some(ip("203.0.113.7")));
+
+        // Hops left of the first untrusted one are the client's to make up.
+        let client = proxies.client_ip(peer, Some("1.1.1.1, 203.0.113.7, 10.0.0.2"));
+        assert_eq!(client, Some(ip("203.0.113.7")));
+
+        // Only proxies in the chain: the leftmost one is the client.
+        let client = proxies.client_ip(peer, Some("10.0.0.3, 10.0.0.2"));
+        assert_eq!(client, Some(ip("10.0.0.3")));
+
+        assert_eq!(proxies.client_ip(peer, None), peer);
+        assert_eq!(proxies.client_ip(None, Some("203.0.113.7")), None);
+    }
+
+    #[test]
+    fn stops_at_a_garbage_hop() {
+        let proxies = TrustedProxies::parse("10.0.0.0/8").unwrap();
+        let peer = Some(ip("10.0.0.1"));
+
+        let client = proxies.client_ip(peer, Some("203.0.113.7, garbage, 10.0.0.2"));
+        assert_eq!(client, Some(ip("10.0.0.2")));
+
+        let client = proxies.client_ip(peer, Some("203.0.113.7, unknown"));
+        assert_eq!(client, peer);
+    }
+
+    #[test]
+    fn walks_ipv6_chains() {
+        let proxies = TrustedProxies::parse("2001:db8::/32").unwrap();
+        let client = proxies.client_ip(Some(ip("2001:db8::1")), Some("2001:db9::7, 2001:db8:1::2"));
+        assert_eq!(client, Some(ip("2001:db9::7")));
+    }
+}
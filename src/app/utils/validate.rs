@@ -1,7 +1,7 @@
 use chrono::{DateTime, Utc};
 use validator::{Validate, ValidationError};
 
-use crate::app::errors::{BaseError, FieldError};
+use crate::app::errors::{BaseError, ErrorKind, FieldError};
 
 pub fn validate<T: Validate>(data: &T) -> Result<(), BaseError> {
     match data.validate() {
@@ -14,10 +14,11 @@ pub fn validate<T: Validate>(data: &T) -> Result<(), BaseError> {
                     message: f.1.first().unwrap().clone().message.unwrap().to_string(),
                 })
             });
-            return Err(BaseError {
+            Err(BaseError {
                 message: "".to_string(),
                 fields: Some(errors),
-            });
+                kind: ErrorKind::BadRequest,
+            })
         }
     }
 }
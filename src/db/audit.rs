@@ -0,0 +1,166 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{Pool, Transaction};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeSet;
+use tokio_postgres::types::ToSql;
+
+use crate::app::{entities::audit::AuditEntry, traits::repositories::audit::TAuditRepositories};
+
+/// Secrets and bookkeeping that never go to the log.
+const HIDDEN_COLUMNS: &[&str] = &[
+    "password_hash",
+    "totp_secret",
+    "totp_last_step",
+    "key_hash",
+    "last_used_at",
+];
+
+pub struct AuditRepository {
+    pool: Pool,
+}
+
+impl AuditRepository {
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl TAuditRepositories for AuditRepository {
+    async fn find(
+        &self,
+        entity: Option<String>,
+        entity_id: Option<String>,
+        actor_id: Option<String>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Vec<AuditEntry> {
+        let mut r#where = String::new();
+        let mut conditions: Vec<String> = vec![];
+        let mut fields: Vec<&(dyn ToSql + Sync)> = vec![];
+
+        if let Some(ref value) = entity {
+            fields.push(value);
+            conditions.push(format!("entity = ${}", fields.len()));
+        }
+        if let Some(ref value) = entity_id {
+            fields.push(value);
+            conditions.push(format!("entity_id = ${}", fields.len()));
+        }
+        if let Some(ref value) = actor_id {
+            fields.push(value);
+            conditions.push(format!("actor_id = ${}", fields.len()));
+        }
+        if let Some(ref value) = from {
+            fields.push(value);
+            conditions.push(format!("created_at >= ${}", fields.len()));
+        }
+        if let Some(ref value) = to {
+            fields.push(value);
+            conditions.push(format!("created_at < ${}", fields.len()));
+        }
+
+        if !conditions.is_empty() {
+            r#where = format!("WHERE {}", conditions.join(" AND "))
+        }
+
+        fields.push(&limit);
+        let statement = format!(
+            "SELECT * FROM audit_log {} ORDER BY created_at DESC, id DESC LIMIT ${}",
+            r#where,
+            fields.len()
+        );
+        let res = self
+            .pool
+            .get()
+            .await
+            .unwrap()
+            .query(&statement, &fields)
+            .await;
+        match res {
+            Ok(rows) => rows.iter().map(AuditEntry::from_row).collect(),
+            Err(_err) => vec![],
+        }
+    }
+}
+
+/// Reads a record of `table` as JSON, to tell what a change did to it.
+pub async fn snapshot(
+    tx: &Transaction<'_>,
+    table: &str,
+    id: &str,
+) -> Result<Option<Value>, String> {
+    let statement = format!(
+        "SELECT to_jsonb(t) - $2::TEXT[] AS data FROM {} AS t WHERE t.id = $1 FOR UPDATE",
+        table
+    );
+    match tx.query_opt(&statement, &[&id, &HIDDEN_COLUMNS]).await {
+        Ok(row) => Ok(row.map(|row| row.get::<&str, Value>("data"))),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Appends an entry for a change made in the transaction, so it is kept or
+/// rolled back together with the change. An update that left every field as
+/// it was isn't recorded.
+pub async fn record(
+    tx: &Transaction<'_>,
+    actor_id: Option<&str>,
+    action: &str,
+    entity: &str,
+    entity_id: &str,
+    before: Option<Value>,
+    after: Option<Value>,
+) -> Result<(), String> {
+    let changes = diff(before, after);
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    let statement = "
+        INSERT INTO audit_log (actor_id, actor_email, action, entity, entity_id, changes)
+        VALUES ($1::VARCHAR, (SELECT email FROM users WHERE id = $1::VARCHAR), $2, $3, $4, $5)
+    ";
+    let res = tx
+        .execute(
+            statement,
+            &[
+                &actor_id,
+                &action,
+                &entity,
+                &entity_id,
+                &Value::Object(changes),
+            ],
+        )
+        .await;
+
+    match res {
+        Ok(_) => Ok(()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn diff(before: Option<Value>, after: Option<Value>) -> Map<String, Value> {
+    let before = as_object(before);
+    let after = as_object(after);
+    let keys: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+
+    let mut changes = Map::new();
+    for key in keys {
+        let old = before.get(key).unwrap_or(&Value::Null);
+        let new = after.get(key).unwrap_or(&Value::Null);
+        if old != new {
+            changes.insert(key.to_string(), json!({ "before": old, "after": new }));
+        }
+    }
+    changes
+}
+
+fn as_object(value: Option<Value>) -> Map<String, Value> {
+    match value {
+        Some(Value::Object(map)) => map,
+        _ => Map::new(),
+    }
+}
@@ -1,9 +1,23 @@
 use async_trait::async_trait;
-use chrono::NaiveDateTime;
-use deadpool_postgres::Pool;
-use tokio_postgres::types::ToSql;
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{Pool, Transaction};
+use serde_json::{json, Value};
+use std::{str::FromStr, time::SystemTime};
+use tokio_postgres::types::{Json, ToSql};
 
-use crate::app::{entities::form::Form, traits::repositories::form::TFormRepositories};
+use crate::app::{
+    entities::{
+        form::{calendar::WorkingCalendar, shift::FormShift, slot::FormSlot, Form},
+        submission::{reflow::SubmissionMove, status::SubmissionStatus},
+    },
+    traits::repositories::form::TFormRepositories,
+};
+
+use super::{
+    audit::{record, snapshot},
+    from_row::parse_time_zone,
+    submissions::promote_waitlist,
+};
 
 pub struct FormRepository {
     pool: Pool,
@@ -22,20 +36,26 @@ impl TFormRepositories for FormRepository {
         name: &str,
         form_limit: i32,
         status: &str,
-        start_date: NaiveDateTime,
-        end_date: NaiveDateTime,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
         time_frame_duration: i32,
         exclude_form_ids: Vec<String>,
+        auto_schedule: bool,
+        calendar: Option<WorkingCalendar>,
+        time_zone: &str,
+        user_id: &str,
     ) -> Result<String, String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
         let statement ="
-            INSERT INTO forms (name, form_limit, status, scheduled_start_date, scheduled_end_date, time_frame_duration, exclude_form_ids) 
-            VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *
+            INSERT INTO forms (name, form_limit, status, scheduled_start_date, scheduled_end_date, time_frame_duration, exclude_form_ids, auto_schedule, calendar, time_zone) 
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING *
         ";
-        let res = self
-            .pool
-            .get()
-            .await
-            .unwrap()
+        let res = tx
             .query_one(
                 statement,
                 &[
@@ -46,23 +66,40 @@ impl TFormRepositories for FormRepository {
                     &end_date,
                     &time_frame_duration,
                     &exclude_form_ids,
+                    &auto_schedule,
+                    &calendar.map(Json),
+                    &time_zone,
                 ],
             )
             .await;
 
-        match res {
-            Ok(row) => Ok(row.get::<&str, String>("id")),
+        let id = match res {
+            Ok(row) => row.get::<&str, String>("id"),
             Err(err) => match err.as_db_error() {
-                Some(err) => Err(err.message().to_string()),
-                None => Err(err.to_string()),
+                Some(err) => return Err(err.message().to_string()),
+                None => return Err(err.to_string()),
             },
+        };
+
+        let after = match snapshot(&tx, "forms", &id).await {
+            Ok(after) => after,
+            Err(err) => return Err(err),
+        };
+        match record(&tx, Some(user_id), "create", "form", &id, None, after).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(id),
+            Err(err) => Err(err.to_string()),
         }
     }
     async fn find(&self) -> Vec<Form> {
         let statement = "SELECT * FROM forms;";
         let res = self.pool.get().await.unwrap().query(statement, &[]).await;
         match res {
-            Ok(rows) => rows.iter().map(|row| Form::from_row(row)).collect(),
+            Ok(rows) => rows.iter().map(Form::from_row).collect(),
             Err(_err) => vec![],
         }
     }
@@ -87,12 +124,17 @@ impl TFormRepositories for FormRepository {
         id: &str,
         name: Option<String>,
         form_limit: Option<i32>,
-        start_date: Option<NaiveDateTime>,
-        end_date: Option<NaiveDateTime>,
+        start_date: Option<DateTime<Utc>>,
+        end_date: Option<DateTime<Utc>>,
         time_frame_duration: Option<i32>,
         status: Option<String>,
         exclude_form_ids: Option<Vec<String>>,
-    ) -> Result<(), String> {
+        auto_schedule: Option<bool>,
+        calendar: Option<WorkingCalendar>,
+        time_zone: Option<String>,
+        reflow: Option<&(dyn Fn(i32) -> DateTime<Utc> + Send + Sync)>,
+        user_id: &str,
+    ) -> Result<Vec<SubmissionMove>, String> {
         let mut set: Vec<String> = vec![];
         let mut fields: Vec<&(dyn ToSql + Sync)> = vec![&id];
 
@@ -129,38 +171,535 @@ impl TFormRepositories for FormRepository {
             set.push(format!("exclude_form_ids = ${}", fields.len()));
         }
 
-        if set.len() == 0 {
-            return Ok(());
+        if let Some(ref value) = auto_schedule {
+            fields.push(value);
+            set.push(format!("auto_schedule = ${}", fields.len()));
+        }
+
+        let calendar = calendar.map(Json);
+        if let Some(ref value) = calendar {
+            fields.push(value);
+            set.push(format!("calendar = ${}", fields.len()));
+        }
+
+        if let Some(ref value) = time_zone {
+            fields.push(value);
+            set.push(format!("time_zone = ${}", fields.len()));
+        }
+
+        if set.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let before = match snapshot(&tx, "forms", id).await {
+            Ok(before) => before,
+            Err(err) => return Err(err),
+        };
+
+        let res = tx
+            .execute(
+                &format!("UPDATE forms SET {} WHERE id = $1", set.join(",")),
+                &fields,
+            )
+            .await;
+        if let Err(err) = res {
+            return Err(err.to_string());
+        }
+
+        let after = match snapshot(&tx, "forms", id).await {
+            Ok(after) => after,
+            Err(err) => return Err(err),
+        };
+        match record(&tx, Some(user_id), "update", "form", id, before, after).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        let moves = match reflow {
+            Some(arrival_date) => match reflow_submissions(&tx, id, arrival_date, user_id).await {
+                Ok(moves) => moves,
+                Err(err) => return Err(err),
+            },
+            None => vec![],
+        };
+
+        // A raised limit frees places for the people on the waitlist.
+        if let (Some(_), Some(arrival_date)) = (form_limit, reflow) {
+            match promote_waitlist(&tx, id, user_id, arrival_date).await {
+                Ok(_) => (),
+                Err(err) => return Err(err),
+            };
+        }
+
+        match tx.commit().await {
+            Ok(_) => Ok(moves),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn reflow(
+        &self,
+        id: &str,
+        arrival_date: &(dyn Fn(i32) -> DateTime<Utc> + Send + Sync),
+        user_id: &str,
+    ) -> Result<Vec<SubmissionMove>, String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let moves = match reflow_submissions(&tx, id, arrival_date, user_id).await {
+            Ok(moves) => moves,
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(moves),
+            Err(err) => Err(err.to_string()),
         }
+    }
 
+    async fn find_scheduled(&self, now: DateTime<Utc>) -> Vec<Form> {
+        let statement = "
+            SELECT * FROM forms
+            WHERE auto_schedule
+                AND ((status = 'draft' AND scheduled_start_date <= $1)
+                    OR (status = 'open' AND scheduled_end_date <= $1))
+        ";
         let res = self
             .pool
             .get()
             .await
             .unwrap()
+            .query(statement, &[&now])
+            .await;
+        match res {
+            Ok(rows) => rows.iter().map(Form::from_row).collect(),
+            Err(_err) => vec![],
+        }
+    }
+
+    async fn update_status(
+        &self,
+        id: &str,
+        from: &str,
+        to: &str,
+        user_id: Option<&str>,
+    ) -> Result<bool, String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        // Only the caller that still sees the expected status wins, so concurrent
+        // schedulers never apply the same transition twice.
+        let res = tx
             .execute(
-                &format!("UPDATE forms SET {} WHERE id = $1", set.join(",")),
-                &fields,
+                "UPDATE forms SET status = $3 WHERE id = $1 AND status = $2",
+                &[&id, &from, &to],
             )
             .await;
         match res {
-            Ok(_) => Ok(()),
+            Ok(0) => return Ok(false),
+            Ok(_) => (),
+            Err(err) => return Err(err.to_string()),
+        }
+
+        let before = Some(json!({ "status": from }));
+        let after = Some(json!({ "status": to }));
+        match record(&tx, user_id, "status", "form", id, before, after).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(true),
             Err(err) => Err(err.to_string()),
         }
     }
 
-    async fn delete(&self, id: &str) -> Result<(), String> {
+    async fn find_slots(&self, form_id: &str) -> Vec<FormSlot> {
+        let statement = "
+            SELECT slot.*, form.time_zone,
+                (SELECT COUNT(*) FROM submissions AS sub
+                    WHERE sub.slot_id = slot.id AND sub.status <> 'cancelled') AS occupied
+            FROM form_slots AS slot
+            JOIN forms AS form ON form.id = slot.form_id
+            WHERE slot.form_id = $1
+            ORDER BY slot.starts_at
+        ";
         let res = self
             .pool
             .get()
             .await
             .unwrap()
-            .execute("DELETE FROM forms WHERE id = $1", &[&id])
+            .query(statement, &[&form_id])
             .await;
+        match res {
+            Ok(rows) => rows.iter().map(FormSlot::from_row).collect(),
+            Err(_err) => vec![],
+        }
+    }
+
+    async fn replace_slots(
+        &self,
+        form_id: &str,
+        slots: Vec<(DateTime<Utc>, DateTime<Utc>, i32)>,
+        user_id: &str,
+    ) -> Result<i32, String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let before = match snapshot_with_slots(&tx, form_id).await {
+            Ok(before) => before,
+            Err(err) => return Err(err),
+        };
 
+        if let Err(err) = tx
+            .execute("DELETE FROM form_slots WHERE form_id = $1", &[&form_id])
+            .await
+        {
+            return Err(err.to_string());
+        }
+
+        for (starts_at, ends_at, capacity) in slots.iter() {
+            let res = tx
+                .execute(
+                    "INSERT INTO form_slots (form_id, starts_at, ends_at, capacity) VALUES ($1, $2, $3, $4)",
+                    &[&form_id, starts_at, ends_at, capacity],
+                )
+                .await;
+            if let Err(err) = res {
+                return Err(err.to_string());
+            }
+        }
+
+        // With explicit slots the limit is simply the total capacity.
+        let form_limit: i32 = slots.iter().map(|(_, _, capacity)| capacity).sum();
+        if let Err(err) = tx
+            .execute(
+                "UPDATE forms SET form_limit = $2 WHERE id = $1",
+                &[&form_id, &form_limit],
+            )
+            .await
+        {
+            return Err(err.to_string());
+        }
+
+        let after = match snapshot_with_slots(&tx, form_id).await {
+            Ok(after) => after,
+            Err(err) => return Err(err),
+        };
+        match record(&tx, Some(user_id), "slots", "form", form_id, before, after).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(form_limit),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn shift(
+        &self,
+        id: &str,
+        seconds: i64,
+        reason: Option<String>,
+        check: &(dyn Fn(DateTime<Utc>, DateTime<Utc>, Vec<DateTime<Utc>>) -> Result<(), String>
+              + Send
+              + Sync),
+        user_id: &str,
+    ) -> Result<FormShift, String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let before = match snapshot_with_slots(&tx, id).await {
+            Ok(before) => before,
+            Err(err) => return Err(err),
+        };
+
+        // Dates move on the wall clock of the form, so a shift by whole days
+        // keeps the local times across a daylight saving change.
+        let statement = "
+            UPDATE forms SET
+                scheduled_start_date =
+                    (scheduled_start_date AT TIME ZONE time_zone + $2::BIGINT * INTERVAL '1 second')
+                    AT TIME ZONE time_zone,
+                scheduled_end_date =
+                    (scheduled_end_date AT TIME ZONE time_zone + $2::BIGINT * INTERVAL '1 second')
+                    AT TIME ZONE time_zone
+            WHERE id = $1 AND status <> 'close'
+            RETURNING scheduled_start_date, scheduled_end_date
+        ";
+        let (start_date, end_date) = match tx.query_opt(statement, &[&id, &seconds]).await {
+            Ok(Some(row)) => (
+                row.get::<&str, DateTime<Utc>>("scheduled_start_date"),
+                row.get::<&str, DateTime<Utc>>("scheduled_end_date"),
+            ),
+            Ok(None) => return Err("Form not found or closed".to_string()),
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let statement = "
+            UPDATE form_slots AS s SET
+                starts_at = (s.starts_at AT TIME ZONE f.time_zone + $2::BIGINT * INTERVAL '1 second')
+                    AT TIME ZONE f.time_zone,
+                ends_at = (s.ends_at AT TIME ZONE f.time_zone + $2::BIGINT * INTERVAL '1 second')
+                    AT TIME ZONE f.time_zone
+            FROM forms AS f
+            WHERE f.id = s.form_id AND s.form_id = $1
+        ";
+        if let Err(err) = tx.execute(statement, &[&id, &seconds]).await {
+            return Err(err.to_string());
+        }
+
+        // Cancelled submissions keep their date and aren't counted.
+        let statement = "
+            UPDATE submissions AS s SET
+                arrival_date = (s.arrival_date AT TIME ZONE f.time_zone + $2::BIGINT * INTERVAL '1 second')
+                    AT TIME ZONE f.time_zone
+            FROM forms AS f
+            WHERE f.id = s.form_id AND s.form_id = $1 AND s.status <> 'cancelled'
+            RETURNING s.arrival_date
+        ";
+        let arrival_dates: Vec<DateTime<Utc>> = match tx.query(statement, &[&id, &seconds]).await {
+            Ok(rows) => rows
+                .iter()
+                .map(|row| row.get::<&str, DateTime<Utc>>("arrival_date"))
+                .collect(),
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let submissions = arrival_dates.len() as i32;
+        match check(start_date, end_date, arrival_dates) {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        let statement = "
+            WITH shift AS (
+                INSERT INTO form_shifts (form_id, shift_seconds, reason, submissions, user_id)
+                VALUES ($1, $2, $3, $4, $5) RETURNING *
+            )
+            SELECT shift.*, u.email AS user_email FROM shift
+            LEFT JOIN users AS u ON u.id = shift.user_id
+        ";
+        let shift = match tx
+            .query_one(statement, &[&id, &seconds, &reason, &submissions, &user_id])
+            .await
+        {
+            Ok(row) => FormShift::from_row(&row),
+            Err(err) => return Err(err.to_string()),
+        };
+
+        // The moved submissions are counted in the shift itself.
+        let after = match snapshot_with_slots(&tx, id).await {
+            Ok(after) => after,
+            Err(err) => return Err(err),
+        };
+        match record(&tx, Some(user_id), "shift", "form", id, before, after).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(shift),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn find_shifts(&self, id: &str) -> Vec<FormShift> {
+        let statement = "
+            SELECT s.*, u.email AS user_email FROM form_shifts AS s
+            LEFT JOIN users AS u ON u.id = s.user_id
+            WHERE s.form_id = $1
+            ORDER BY s.created_at, s.id
+        ";
+        let res = self
+            .pool
+            .get()
+            .await
+            .unwrap()
+            .query(statement, &[&id])
+            .await;
         match res {
+            Ok(rows) => rows.iter().map(FormShift::from_row).collect(),
+            Err(_err) => vec![],
+        }
+    }
+
+    async fn delete(&self, id: &str, user_id: &str) -> Result<(), String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let before = match snapshot(&tx, "forms", id).await {
+            Ok(before) => before,
+            Err(err) => return Err(err),
+        };
+
+        if let Err(err) = tx.execute("DELETE FROM forms WHERE id = $1", &[&id]).await {
+            return Err(err.to_string());
+        }
+
+        match record(&tx, Some(user_id), "delete", "form", id, before, None).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
             Ok(_) => Ok(()),
             Err(err) => Err(err.to_string()),
         }
     }
 }
+
+// The form together with its slots, as the audit log shows slot changes.
+async fn snapshot_with_slots(tx: &Transaction<'_>, form_id: &str) -> Result<Option<Value>, String> {
+    let mut form = match snapshot(tx, "forms", form_id).await {
+        Ok(Some(form)) => form,
+        Ok(None) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let statement = "
+        SELECT COALESCE(
+            jsonb_agg(jsonb_build_object('starts_at', starts_at, 'ends_at', ends_at, 'capacity', capacity)
+                ORDER BY starts_at),
+            '[]'::JSONB
+        ) AS slots
+        FROM form_slots WHERE form_id = $1
+    ";
+    let slots = match tx.query_one(statement, &[&form_id]).await {
+        Ok(row) => row.get::<&str, Value>("slots"),
+        Err(err) => return Err(err.to_string()),
+    };
+    form["slots"] = slots;
+
+    Ok(Some(form))
+}
+
+// Renumbers the active submissions of the form without gaps, keeping their
+// relative order, and gives received and confirmed ones the arrival date of
+// their new place. Submissions booked into an explicit slot keep their time.
+async fn reflow_submissions(
+    tx: &Transaction<'_>,
+    form_id: &str,
+    arrival_date: &(dyn Fn(i32) -> DateTime<Utc> + Send + Sync),
+    user_id: &str,
+) -> Result<Vec<SubmissionMove>, String> {
+    let form = match tx
+        .query_opt(
+            "SELECT form_limit, time_zone FROM forms WHERE id = $1 FOR UPDATE",
+            &[&form_id],
+        )
+        .await
+    {
+        Ok(Some(row)) => row,
+        Ok(None) => return Err("Form not foound".to_string()),
+        Err(err) => return Err(err.to_string()),
+    };
+    let form_limit = form.get::<&str, i32>("form_limit");
+    let time_zone = parse_time_zone(form.get::<&str, String>("time_zone"));
+
+    let statement = "
+        SELECT sub.id, sub.sub_order, sub.arrival_date, sub.status, sub.slot_id,
+            res.id AS res_id, res.first_name, res.last_name, res.phone
+        FROM submissions AS sub
+        JOIN respondents AS res ON res.id = sub.respondent_id
+        WHERE sub.form_id = $1 AND sub.status <> 'cancelled'
+        ORDER BY sub.sub_order
+    ";
+    let rows = match tx.query(statement, &[&form_id]).await {
+        Ok(rows) => rows,
+        Err(err) => return Err(err.to_string()),
+    };
+
+    if rows.len() > form_limit as usize {
+        return Err("Form limit is less than the number of submissions".to_string());
+    }
+
+    let mut moves = vec![];
+    // Orders only ever go down here, so the active order index never collides.
+    for (index, row) in rows.iter().enumerate() {
+        let old_order = row.get::<&str, i32>("sub_order");
+        let new_order = index as i32 + 1;
+        let old_date: DateTime<Utc> = row.get::<&str, SystemTime>("arrival_date").into();
+        let status = SubmissionStatus::from_str(&row.get::<&str, String>("status")).unwrap();
+
+        let keep_date = row.get::<&str, Option<String>>("slot_id").is_some()
+            || status == SubmissionStatus::Completed
+            || status == SubmissionStatus::NoShow;
+        let new_date = if keep_date {
+            old_date
+        } else {
+            arrival_date(new_order)
+        };
+
+        if old_order == new_order && old_date == new_date {
+            continue;
+        }
+
+        let id = row.get::<&str, String>("id");
+        let res = tx
+            .execute(
+                "UPDATE submissions SET sub_order = $2, arrival_date = $3 WHERE id = $1",
+                &[&id, &new_order, &new_date],
+            )
+            .await;
+        if let Err(err) = res {
+            return Err(err.to_string());
+        }
+
+        let before = Some(json!({ "sub_order": old_order, "arrival_date": old_date }));
+        let after = Some(json!({ "sub_order": new_order, "arrival_date": new_date }));
+        match record(
+            tx,
+            Some(user_id),
+            "reflow",
+            "submission",
+            &id,
+            before,
+            after,
+        )
+        .await
+        {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        moves.push(SubmissionMove {
+            submission_id: id,
+            respondent_id: row.get::<&str, String>("res_id"),
+            first_name: row.get::<&str, String>("first_name"),
+            last_name: row.get::<&str, String>("last_name"),
+            phone: row.get::<&str, String>("phone"),
+            old_order: old_order as u32,
+            new_order: new_order as u32,
+            old_arrival_date: old_date,
+            new_arrival_date: new_date,
+            local_old_arrival_date: old_date.with_timezone(&time_zone).naive_local(),
+            local_new_arrival_date: new_date.with_timezone(&time_zone).naive_local(),
+            shift_seconds: (new_date - old_date).num_seconds(),
+        });
+    }
+
+    Ok(moves)
+}
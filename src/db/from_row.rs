@@ -1,20 +1,40 @@
 use std::{str::FromStr, time::SystemTime};
 
-use tokio_postgres::Row;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use tokio_postgres::{types::Json, Row};
 
 use crate::app::entities::{
-    form::{status::FormStatus, Form},
+    audit::AuditEntry,
+    form::{
+        calendar::WorkingCalendar, shift::FormShift, slot::FormSlot, status::FormStatus, Form,
+        DEFAULT_TIME_ZONE,
+    },
     respondent::Respondent,
-    submission::{status::SubmissionStatus, Submission},
+    submission::{
+        eligibility::EligibilityConflict, history::StatusChange, status::SubmissionStatus,
+        waitlist::WaitlistEntry, Submission,
+    },
 };
 
 impl Submission {
     pub fn from_row(row: &Row) -> Self {
+        let time_zone = parse_time_zone(row.get::<&str, String>("form_time_zone"));
+        let start_date: DateTime<Utc> = row
+            .get::<&str, SystemTime>("form_scheduled_start_date")
+            .into();
+        let end_date: DateTime<Utc> = row
+            .get::<&str, SystemTime>("form_scheduled_end_date")
+            .into();
+        let arrival_date: DateTime<Utc> = row.get::<&str, SystemTime>("arrival_date").into();
+
         Submission {
             id: row.get::<&str, String>("id"),
             sub_order: row.get::<&str, i32>("sub_order") as u32,
+            slot_id: row.get::<&str, Option<String>>("slot_id"),
             status: SubmissionStatus::from_str(row.get::<&str, String>("status").as_str()).unwrap(),
-            arrival_date: row.get::<&str, SystemTime>("arrival_date").into(),
+            arrival_date,
+            local_arrival_date: arrival_date.with_timezone(&time_zone).naive_local(),
             created_at: row.get::<&str, SystemTime>("created_at").into(),
             form: Form {
                 id: row.get::<&str, String>("form_id"),
@@ -22,15 +42,18 @@ impl Submission {
                 limit: row.get::<&str, i32>("form_limit") as u16,
                 status: FormStatus::from_str(row.get::<&str, String>("form_status").as_str())
                     .unwrap(),
-                start_date: row
-                    .get::<&str, SystemTime>("form_scheduled_start_date")
-                    .into(),
-                end_date: row
-                    .get::<&str, SystemTime>("form_scheduled_end_date")
-                    .into(),
+                start_date,
+                end_date,
                 created_at: row.get::<&str, SystemTime>("form_created_at").into(),
                 time_frame_duration: row.get::<&str, i32>("form_time_frame_duration") as u16,
                 exclude_form_ids: row.get::<&str, Vec<String>>("form_exclude_form_ids"),
+                auto_schedule: row.get::<&str, bool>("form_auto_schedule"),
+                calendar: row
+                    .get::<&str, Option<Json<WorkingCalendar>>>("form_calendar")
+                    .map(|v| v.0),
+                time_zone,
+                local_start_date: start_date.with_timezone(&time_zone).naive_local(),
+                local_end_date: end_date.with_timezone(&time_zone).naive_local(),
             },
             respondent: Respondent {
                 id: row.get::<&str, String>("res_id"),
@@ -65,16 +88,128 @@ impl Respondent {
 
 impl Form {
     pub fn from_row(row: &Row) -> Self {
+        let time_zone = parse_time_zone(row.get::<&str, String>("time_zone"));
+        let start_date: DateTime<Utc> = row.get::<&str, SystemTime>("scheduled_start_date").into();
+        let end_date: DateTime<Utc> = row.get::<&str, SystemTime>("scheduled_end_date").into();
+
         Form {
             id: row.get::<&str, String>("id"),
             name: row.get::<&str, String>("name"),
             limit: row.get::<&str, i32>("form_limit") as u16,
             status: FormStatus::from_str(row.get::<&str, String>("status").as_str()).unwrap(),
-            start_date: row.get::<&str, SystemTime>("scheduled_start_date").into(),
-            end_date: row.get::<&str, SystemTime>("scheduled_end_date").into(),
+            start_date,
+            end_date,
             created_at: row.get::<&str, SystemTime>("created_at").into(),
             time_frame_duration: row.get::<&str, i32>("time_frame_duration") as u16,
             exclude_form_ids: row.get::<&str, Vec<String>>("exclude_form_ids"),
+            auto_schedule: row.get::<&str, bool>("auto_schedule"),
+            calendar: row
+                .get::<&str, Option<Json<WorkingCalendar>>>("calendar")
+                .map(|v| v.0),
+            time_zone,
+            local_start_date: start_date.with_timezone(&time_zone).naive_local(),
+            local_end_date: end_date.with_timezone(&time_zone).naive_local(),
+        }
+    }
+}
+
+impl FormSlot {
+    pub fn from_row(row: &Row) -> Self {
+        let time_zone = parse_time_zone(row.get::<&str, String>("time_zone"));
+        let starts_at: DateTime<Utc> = row.get::<&str, SystemTime>("starts_at").into();
+        let ends_at: DateTime<Utc> = row.get::<&str, SystemTime>("ends_at").into();
+
+        FormSlot {
+            id: row.get::<&str, String>("id"),
+            starts_at,
+            ends_at,
+            local_starts_at: starts_at.with_timezone(&time_zone).naive_local(),
+            local_ends_at: ends_at.with_timezone(&time_zone).naive_local(),
+            capacity: row.get::<&str, i32>("capacity") as u16,
+            occupied: row.get::<&str, i64>("occupied") as u16,
+        }
+    }
+}
+
+impl WaitlistEntry {
+    pub fn from_row(row: &Row) -> Self {
+        WaitlistEntry {
+            id: row.get::<&str, String>("id"),
+            form_id: row.get::<&str, String>("form_id"),
+            position: row.get::<&str, i64>("position") as u32,
+            created_at: row.get::<&str, SystemTime>("created_at").into(),
+            respondent: Respondent {
+                id: row.get::<&str, String>("res_id"),
+                passport_id: row.get::<&str, String>("res_passport_id"),
+                first_name: row.get::<&str, String>("res_first_name"),
+                last_name: row.get::<&str, String>("res_last_name"),
+                phone: row.get::<&str, String>("res_phone"),
+                region: row.get::<&str, String>("res_region"),
+                children: row.get::<&str, i16>("res_children") as u8,
+                idp_code: row.get::<&str, Option<String>>("res_idp_code"),
+                created_at: row.get::<&str, SystemTime>("res_created_at").into(),
+            },
+        }
+    }
+}
+
+impl FormShift {
+    pub fn from_row(row: &Row) -> Self {
+        FormShift {
+            id: row.get::<&str, i32>("id"),
+            form_id: row.get::<&str, String>("form_id"),
+            shift_seconds: row.get::<&str, i64>("shift_seconds"),
+            reason: row.get::<&str, Option<String>>("reason"),
+            submissions: row.get::<&str, i32>("submissions") as u32,
+            user_id: row.get::<&str, Option<String>>("user_id"),
+            user_email: row.get::<&str, Option<String>>("user_email"),
+            created_at: row.get::<&str, SystemTime>("created_at").into(),
+        }
+    }
+}
+
+impl StatusChange {
+    pub fn from_row(row: &Row) -> Self {
+        StatusChange {
+            id: row.get::<&str, i32>("id"),
+            submission_id: row.get::<&str, String>("submission_id"),
+            old_status: row
+                .get::<&str, Option<String>>("old_status")
+                .map(|v| SubmissionStatus::from_str(v.as_str()).unwrap()),
+            new_status: SubmissionStatus::from_str(row.get::<&str, String>("new_status").as_str())
+                .unwrap(),
+            user_id: row.get::<&str, Option<String>>("user_id"),
+            user_email: row.get::<&str, Option<String>>("user_email"),
+            created_at: row.get::<&str, SystemTime>("created_at").into(),
+        }
+    }
+}
+
+impl AuditEntry {
+    pub fn from_row(row: &Row) -> Self {
+        AuditEntry {
+            id: row.get::<&str, i64>("id"),
+            actor_id: row.get::<&str, Option<String>>("actor_id"),
+            actor_email: row.get::<&str, Option<String>>("actor_email"),
+            action: row.get::<&str, String>("action"),
+            entity: row.get::<&str, String>("entity"),
+            entity_id: row.get::<&str, String>("entity_id"),
+            changes: row.get::<&str, serde_json::Value>("changes"),
+            created_at: row.get::<&str, SystemTime>("created_at").into(),
+        }
+    }
+}
+
+pub fn parse_time_zone(value: String) -> Tz {
+    Tz::from_str(&value).unwrap_or(DEFAULT_TIME_ZONE)
+}
+
+impl EligibilityConflict {
+    pub fn from_row(row: &Row) -> Self {
+        EligibilityConflict {
+            form_id: row.get::<&str, String>("form_id"),
+            form_name: row.get::<&str, String>("form_name"),
+            submission_id: row.get::<&str, String>("submission_id"),
         }
     }
 }
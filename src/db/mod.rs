@@ -4,17 +4,19 @@ use tokio_postgres::NoTls;
 
 use crate::app::{
     self,
+    entities::role::Role,
     services::auth::{AuthService, CreateInputData},
     traits::repositories::{
-        form::TFormRepositories, respondent::TRespondentRepositories,
+        audit::TAuditRepositories, form::TFormRepositories, respondent::TRespondentRepositories,
         submission::TSubmissionRepositories, user::TUserRepositories,
     },
 };
 
 use self::{
-    forms::FormRepository, respondent::RespondentRepository, submissions::SubmissionsRepository,
-    users::UserRepository,
+    audit::AuditRepository, forms::FormRepository, respondent::RespondentRepository,
+    submissions::SubmissionsRepository, users::UserRepository,
 };
+mod audit;
 mod forms;
 mod from_row;
 mod respondent;
@@ -26,17 +28,24 @@ pub struct DB {
     pub forms: Box<dyn TFormRepositories + Sync + Send>,
     pub respondents: Box<dyn TRespondentRepositories + Sync + Send>,
     pub submissions: Box<dyn TSubmissionRepositories + Sync + Send>,
+    pub audit: Box<dyn TAuditRepositories + Sync + Send>,
 }
 
 impl DB {
+    /// Panics when the schema can't be applied, the app doesn't work with a
+    /// partly migrated database.
     async fn run_migration(client: &Object) {
         if let Ok(path) = std::env::var("DATABASE_SCHEMA_FILE_PATH") {
             if let Ok(migration) = fs::read_to_string(path) {
                 if let Err(err) = client.batch_execute(&migration).await {
-                    eprintln!("Failed to initialize DB: {}", err);
+                    let message = match err.as_db_error() {
+                        Some(db_err) => db_err.message().to_string(),
+                        None => err.to_string(),
+                    };
+                    panic!("Failed to initialize DB: {}", message);
                 }
             } else {
-                eprintln!("Failed to read migration file");
+                panic!("Failed to read migration file");
             }
         } else {
             eprintln!("DATABASE_SCHEMA_FILE_PATH environment variable not set");
@@ -47,10 +56,15 @@ impl DB {
         if let Ok(email) = std::env::var("DEFAULT_USER_EMAIL") {
             if let Ok(password) = std::env::var("DEFAULT_USER_PASSWORD") {
                 match self.users.find_by_email(&email).await {
-                    Some(_) => return,
+                    Some(_) => (),
                     None => {
-                        let service = AuthService::new(&config, self.users.as_ref());
-                        let _ = service.create(CreateInputData { email, password }).await;
+                        let service = AuthService::new(config, self.users.as_ref());
+                        let data = CreateInputData {
+                            email,
+                            password,
+                            role: Role::Admin,
+                        };
+                        let _ = service.create(data, None).await;
                     }
                 }
             }
@@ -77,6 +91,7 @@ impl DB {
             forms: Box::new(FormRepository::new(pool.clone())),
             respondents: Box::new(RespondentRepository::new(pool.clone())),
             submissions: Box::new(SubmissionsRepository::new(pool.clone())),
+            audit: Box::new(AuditRepository::new(pool.clone())),
         }
     }
 }
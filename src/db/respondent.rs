@@ -1,9 +1,18 @@
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use deadpool_postgres::Pool;
-use tokio_postgres::types::ToSql;
+use serde_json::json;
+use std::str::FromStr;
+use tokio_postgres::{error::SqlState, types::ToSql, Row};
 
 use crate::app::{
-    entities::respondent::Respondent, traits::repositories::respondent::TRespondentRepositories,
+    entities::{form::Form, respondent::Respondent, submission::status::SubmissionStatus},
+    traits::repositories::respondent::TRespondentRepositories,
+};
+
+use super::{
+    audit::{record, snapshot},
+    submissions::promote_waitlist,
 };
 
 pub struct RespondentRepository {
@@ -27,16 +36,19 @@ impl TRespondentRepositories for RespondentRepository {
         region: &str,
         children: i16,
         idp_code: &Option<String>,
+        user_id: &str,
     ) -> Result<String, String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
         let statement ="
             INSERT INTO respondents (first_name, last_name, passport_id, phone, region, children, idp_code) 
             VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *
         ";
-        let res = self
-            .pool
-            .get()
-            .await
-            .unwrap()
+        let res = tx
             .query_one(
                 statement,
                 &[
@@ -51,12 +63,26 @@ impl TRespondentRepositories for RespondentRepository {
             )
             .await;
 
-        match res {
-            Ok(row) => Ok(row.get::<&str, String>("id")),
+        let id = match res {
+            Ok(row) => row.get::<&str, String>("id"),
             Err(err) => match err.as_db_error() {
-                Some(err) => Err(err.message().to_string()),
-                None => Err(err.to_string()),
+                Some(err) => return Err(err.message().to_string()),
+                None => return Err(err.to_string()),
             },
+        };
+
+        let after = match snapshot(&tx, "respondents", &id).await {
+            Ok(after) => after,
+            Err(err) => return Err(err),
+        };
+        match record(&tx, Some(user_id), "create", "respondent", &id, None, after).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(id),
+            Err(err) => Err(err.to_string()),
         }
     }
     async fn find(&self, by_name: Option<String>, by_passport: Option<String>) -> Vec<Respondent> {
@@ -76,7 +102,7 @@ impl TRespondentRepositories for RespondentRepository {
             conditions.push(format!("passport_id = ${}", fields.len()));
         }
 
-        if conditions.len() > 0 {
+        if !conditions.is_empty() {
             r#where = format!("WHERE {}", conditions.join(" AND "))
         }
 
@@ -89,7 +115,7 @@ impl TRespondentRepositories for RespondentRepository {
             .query(&statement, &fields)
             .await;
         match res {
-            Ok(rows) => rows.iter().map(|row| Respondent::from_row(row)).collect(),
+            Ok(rows) => rows.iter().map(Respondent::from_row).collect(),
             Err(_err) => vec![],
         }
     }
@@ -119,6 +145,7 @@ impl TRespondentRepositories for RespondentRepository {
         region: &Option<String>,
         children: &Option<i16>,
         idp_code: &Option<String>,
+        user_id: &str,
     ) -> Result<(), String> {
         let mut set: Vec<String> = vec![];
         let mut fields: Vec<&(dyn ToSql + Sync)> = vec![&id];
@@ -156,38 +183,321 @@ impl TRespondentRepositories for RespondentRepository {
             set.push(format!("children = ${}", fields.len()));
         }
 
-        if set.len() == 0 {
+        if set.is_empty() {
             return Ok(());
         }
 
-        let res = self
-            .pool
-            .get()
-            .await
-            .unwrap()
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let before = match snapshot(&tx, "respondents", id).await {
+            Ok(before) => before,
+            Err(err) => return Err(err),
+        };
+
+        let res = tx
             .execute(
                 &format!("UPDATE respondents SET {} WHERE id = $1", set.join(",")),
                 &fields,
             )
             .await;
-        match res {
+        if let Err(err) = res {
+            return Err(err.to_string());
+        }
+
+        let after = match snapshot(&tx, "respondents", id).await {
+            Ok(after) => after,
+            Err(err) => return Err(err),
+        };
+        match record(
+            &tx,
+            Some(user_id),
+            "update",
+            "respondent",
+            id,
+            before,
+            after,
+        )
+        .await
+        {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
             Ok(_) => Ok(()),
             Err(err) => Err(err.to_string()),
         }
     }
 
-    async fn delete(&self, id: &str) -> Result<(), String> {
-        let res = self
-            .pool
-            .get()
-            .await
-            .unwrap()
+    async fn delete(&self, id: &str, user_id: &str) -> Result<(), String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let before = match snapshot(&tx, "respondents", id).await {
+            Ok(before) => before,
+            Err(err) => return Err(err),
+        };
+
+        if let Err(err) = tx
             .execute("DELETE FROM respondents WHERE id = $1", &[&id])
-            .await;
+            .await
+        {
+            return Err(err.to_string());
+        }
 
-        match res {
+        match record(&tx, Some(user_id), "delete", "respondent", id, before, None).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
             Ok(_) => Ok(()),
             Err(err) => Err(err.to_string()),
         }
     }
+
+    async fn merge(
+        &self,
+        target_id: &str,
+        source_id: &str,
+        arrival_date: &(dyn for<'f> Fn(&'f Form, i32) -> DateTime<Utc> + Send + Sync),
+        phone: &Option<String>,
+        region: &Option<String>,
+        children: &Option<i16>,
+        idp_code: &Option<String>,
+        user_id: &str,
+    ) -> Result<(Vec<String>, Vec<String>), String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let ids = vec![target_id, source_id];
+        match tx
+            .query(
+                "SELECT id FROM respondents WHERE id = any($1) FOR UPDATE",
+                &[&ids],
+            )
+            .await
+        {
+            Ok(rows) if rows.len() == 2 => (),
+            Ok(_) => return Err("Respondent not found".to_string()),
+            Err(err) => return Err(err.to_string()),
+        }
+
+        let target_before = match snapshot(&tx, "respondents", target_id).await {
+            Ok(before) => before,
+            Err(err) => return Err(err),
+        };
+        let source_before = match snapshot(&tx, "respondents", source_id).await {
+            Ok(before) => before,
+            Err(err) => return Err(err),
+        };
+
+        // Forms where both hold a place are locked like for a booking, since
+        // dropping one of the places frees it for the waitlist.
+        let statement = "
+            SELECT * FROM forms WHERE id IN (
+                SELECT form_id FROM submissions
+                WHERE respondent_id = any($1) AND status <> 'cancelled'
+                GROUP BY form_id HAVING COUNT(*) > 1
+            )
+            ORDER BY id
+            FOR UPDATE
+        ";
+        let forms: Vec<Form> = match tx.query(statement, &[&ids]).await {
+            Ok(rows) => rows.iter().map(Form::from_row).collect(),
+            Err(err) => return Err(err.to_string()),
+        };
+        let form_ids: Vec<&str> = forms.iter().map(|form| form.id.as_str()).collect();
+
+        let statement = "
+            SELECT id, form_id, respondent_id, status, sub_order FROM submissions
+            WHERE respondent_id = any($1) AND form_id = any($2) AND status <> 'cancelled'
+            FOR UPDATE
+        ";
+        let booked = match tx.query(statement, &[&ids, &form_ids]).await {
+            Ok(rows) => rows,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let mut drop_submission_ids: Vec<String> = vec![];
+        for form_id in form_ids.iter() {
+            let of_form = |respondent_id: &str| {
+                booked.iter().find(|row| {
+                    row.get::<&str, String>("form_id") == *form_id
+                        && row.get::<&str, String>("respondent_id") == respondent_id
+                })
+            };
+            if let (Some(target), Some(source)) = (of_form(target_id), of_form(source_id)) {
+                let dropped = match keep_target_submission(target, source) {
+                    true => source,
+                    false => target,
+                };
+                drop_submission_ids.push(dropped.get::<&str, String>("id"));
+            }
+        }
+
+        let dropped = match tx
+            .query(
+                "
+                DELETE FROM submissions WHERE id = any($1) AND respondent_id = any($2)
+                RETURNING id, to_jsonb(submissions) AS data
+                ",
+                &[&drop_submission_ids, &ids],
+            )
+            .await
+        {
+            Ok(rows) => rows,
+            Err(err) => return Err(err.to_string()),
+        };
+        for row in dropped.iter() {
+            let id = row.get::<&str, String>("id");
+            let before = Some(row.get::<&str, serde_json::Value>("data"));
+            match record(&tx, Some(user_id), "merge", "submission", &id, before, None).await {
+                Ok(_) => (),
+                Err(err) => return Err(err),
+            };
+        }
+
+        let moved = tx
+            .query(
+                "UPDATE submissions SET respondent_id = $1 WHERE respondent_id = $2 RETURNING id",
+                &[&target_id, &source_id],
+            )
+            .await;
+        let moved_ids: Vec<String> = match moved {
+            Ok(rows) => rows
+                .iter()
+                .map(|row| row.get::<&str, String>("id"))
+                .collect(),
+            Err(err) => match err.as_db_error() {
+                Some(db_err) if db_err.code() == &SqlState::UNIQUE_VIOLATION => {
+                    return Err("Both respondents have a submission in the same form".to_string())
+                }
+                Some(db_err) => return Err(db_err.message().to_string()),
+                None => return Err(err.to_string()),
+            },
+        };
+        for id in moved_ids.iter() {
+            let before = Some(json!({ "respondent_id": source_id }));
+            let after = Some(json!({ "respondent_id": target_id }));
+            match record(&tx, Some(user_id), "merge", "submission", id, before, after).await {
+                Ok(_) => (),
+                Err(err) => return Err(err),
+            };
+        }
+
+        // Waitlist entries follow the submissions, unless the target already
+        // waits for or holds a place in the same form.
+        let statement = "
+            UPDATE form_waitlist AS w SET respondent_id = $1
+            WHERE w.respondent_id = $2
+                AND NOT EXISTS (
+                    SELECT 1 FROM form_waitlist WHERE form_id = w.form_id AND respondent_id = $1
+                )
+                AND NOT EXISTS (
+                    SELECT 1 FROM submissions
+                    WHERE form_id = w.form_id AND respondent_id = $1 AND status <> 'cancelled'
+                )
+        ";
+        if let Err(err) = tx.execute(statement, &[&target_id, &source_id]).await {
+            return Err(err.to_string());
+        }
+
+        for form in forms.iter() {
+            let arrival_date = |order: i32| arrival_date(form, order);
+            match promote_waitlist(&tx, &form.id, user_id, &arrival_date).await {
+                Ok(_) => (),
+                Err(err) => return Err(err),
+            };
+        }
+
+        let mut set: Vec<String> = vec![];
+        let mut fields: Vec<&(dyn ToSql + Sync)> = vec![&target_id];
+
+        if let Some(ref value) = phone {
+            fields.push(value);
+            set.push(format!("phone = ${}", fields.len()));
+        }
+        if let Some(ref value) = region {
+            fields.push(value);
+            set.push(format!("region = ${}", fields.len()));
+        }
+        if let Some(ref value) = children {
+            fields.push(value);
+            set.push(format!("children = ${}", fields.len()));
+        }
+        if let Some(ref value) = idp_code {
+            fields.push(value);
+            set.push(format!("idp_code = ${}", fields.len()));
+        }
+
+        if !set.is_empty() {
+            if let Err(err) = tx
+                .execute(
+                    &format!("UPDATE respondents SET {} WHERE id = $1", set.join(",")),
+                    &fields,
+                )
+                .await
+            {
+                return Err(err.to_string());
+            }
+        }
+
+        if let Err(err) = tx
+            .execute("DELETE FROM respondents WHERE id = $1", &[&source_id])
+            .await
+        {
+            return Err(err.to_string());
+        }
+
+        let target_after = match snapshot(&tx, "respondents", target_id).await {
+            Ok(after) => after,
+            Err(err) => return Err(err),
+        };
+        let entries = [
+            (target_id, target_before, target_after),
+            (source_id, source_before, None),
+        ];
+        for (id, before, after) in entries {
+            match record(&tx, Some(user_id), "merge", "respondent", id, before, after).await {
+                Ok(_) => (),
+                Err(err) => return Err(err),
+            };
+        }
+
+        match tx.commit().await {
+            Ok(_) => Ok((moved_ids, drop_submission_ids)),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+}
+
+// When both respondents are booked in the same form, the submission that went
+// further through the process wins; on a tie the earlier slot is kept.
+fn keep_target_submission(target: &Row, source: &Row) -> bool {
+    let rank = |row: &Row| match SubmissionStatus::from_str(&row.get::<&str, String>("status")) {
+        Ok(SubmissionStatus::Completed) => 4,
+        Ok(SubmissionStatus::Confirmed) => 3,
+        Ok(SubmissionStatus::Received) => 2,
+        Ok(SubmissionStatus::NoShow) => 1,
+        _ => 0,
+    };
+    let order = |row: &Row| row.get::<&str, i32>("sub_order");
+
+    let (target_rank, source_rank) = (rank(target), rank(source));
+    if target_rank != source_rank {
+        target_rank > source_rank
+    } else {
+        order(target) <= order(source)
+    }
 }
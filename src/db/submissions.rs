@@ -1,12 +1,33 @@
 use async_trait::async_trait;
-use chrono::NaiveDateTime;
-use deadpool_postgres::Pool;
-use tokio_postgres::types::ToSql;
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{Pool, Transaction};
+use serde_json::json;
+use std::time::SystemTime;
+use tokio_postgres::{error::SqlState, types::ToSql};
 
 use crate::app::{
-    entities::submission::Submission, traits::repositories::submission::TSubmissionRepositories,
+    entities::submission::{
+        eligibility::EligibilityConflict,
+        history::StatusChange,
+        status::SubmissionStatus,
+        waitlist::{Booking, BookingOutcome, WaitlistEntry},
+        Submission,
+    },
+    traits::repositories::submission::TSubmissionRepositories,
 };
 
+use super::audit::{record, snapshot};
+
+/// Active submissions of the respondent ($2) in forms the form ($1) excludes.
+const CONFLICTS: &str = "
+    SELECT sub.id AS submission_id, excluded.id AS form_id, excluded.name AS form_name
+    FROM forms AS form
+    JOIN submissions AS sub ON sub.form_id = ANY(form.exclude_form_ids)
+    JOIN forms AS excluded ON excluded.id = sub.form_id
+    WHERE form.id = $1 AND sub.respondent_id = $2 AND sub.status NOT IN ('cancelled', 'no_show')
+    ORDER BY sub.created_at
+";
+
 pub struct SubmissionsRepository {
     pool: Pool,
 }
@@ -23,33 +44,75 @@ impl TSubmissionRepositories for SubmissionsRepository {
         &self,
         form_id: &str,
         respondent_id: &str,
-        arrival_date: NaiveDateTime,
-        sub_order: i32,
         status: &str,
-    ) -> Result<String, String> {
-        let statement = "
-            INSERT INTO submissions (form_id, respondent_id, arrival_date, sub_order, status) 
-            VALUES ($1, $2, $3, $4, $5) RETURNING *
-        ";
+        user_id: &str,
+        arrival_date: &(dyn Fn(i32) -> DateTime<Utc> + Send + Sync),
+    ) -> Result<BookingOutcome, String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let booked = book(&tx, form_id, respondent_id, status, user_id, arrival_date).await;
+        let booking = match booked {
+            Ok(Booked::Place(id)) => Booking::Submission(id),
+            Ok(Booked::Excluded(conflicts)) => return Ok(BookingOutcome::Excluded(conflicts)),
+            Ok(Booked::Full) => {
+                let statement = "
+                    INSERT INTO form_waitlist (form_id, respondent_id, position)
+                    SELECT $1::VARCHAR, $2::VARCHAR, COALESCE(MAX(position), 0) + 1
+                    FROM form_waitlist WHERE form_id = $1
+                    RETURNING id
+                ";
+                let id = match tx.query_one(statement, &[&form_id, &respondent_id]).await {
+                    Ok(row) => row.get::<&str, String>("id"),
+                    Err(err) => return Err(conflict_message(&err)),
+                };
+                let after = match snapshot(&tx, "form_waitlist", &id).await {
+                    Ok(after) => after,
+                    Err(err) => return Err(err),
+                };
+                match record(
+                    &tx,
+                    Some(user_id),
+                    "create",
+                    "waitlist_entry",
+                    &id,
+                    None,
+                    after,
+                )
+                .await
+                {
+                    Ok(_) => (),
+                    Err(err) => return Err(err),
+                };
+                Booking::Waitlist(id)
+            }
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(BookingOutcome::Booked(booking)),
+            Err(err) => Err(conflict_message(&err)),
+        }
+    }
+
+    async fn find_conflicts(&self, form_id: &str, respondent_id: &str) -> Vec<EligibilityConflict> {
         let res = self
             .pool
             .get()
             .await
             .unwrap()
-            .query_one(
-                statement,
-                &[&form_id, &respondent_id, &arrival_date, &sub_order, &status],
-            )
+            .query(CONFLICTS, &[&form_id, &respondent_id])
             .await;
 
         match res {
-            Ok(row) => Ok(row.get::<&str, String>("id")),
-            Err(err) => match err.as_db_error() {
-                Some(err) => Err(err.message().to_string()),
-                None => Err(err.to_string()),
-            },
+            Ok(rows) => rows.iter().map(EligibilityConflict::from_row).collect(),
+            Err(_) => vec![],
         }
     }
+
     async fn find(
         &self,
         by_form: Option<String>,
@@ -69,7 +132,7 @@ impl TSubmissionRepositories for SubmissionsRepository {
             conditions.push(format!("sub.respondent_id = ${}", fields.len()));
         }
 
-        if conditions.len() > 0 {
+        if !conditions.is_empty() {
             r#where = format!("WHERE {}", conditions.join(" AND "))
         }
 
@@ -86,6 +149,9 @@ impl TSubmissionRepositories for SubmissionsRepository {
                 form.time_frame_duration AS form_time_frame_duration,
                 form.created_at AS form_created_at,
                 form.exclude_form_ids AS form_exclude_form_ids,
+                form.auto_schedule AS form_auto_schedule,
+                form.calendar AS form_calendar,
+                form.time_zone AS form_time_zone,
                 res.id AS res_id,
                 res.created_at AS res_created_at,
                 res.id AS res_id,
@@ -113,7 +179,7 @@ impl TSubmissionRepositories for SubmissionsRepository {
             .query(&statement, &fields)
             .await;
         match res {
-            Ok(rows) => rows.iter().map(|row| Submission::from_row(row)).collect(),
+            Ok(rows) => rows.iter().map(Submission::from_row).collect(),
             Err(_err) => vec![],
         }
     }
@@ -130,6 +196,9 @@ impl TSubmissionRepositories for SubmissionsRepository {
                 form.time_frame_duration AS form_time_frame_duration,
                 form.created_at AS form_created_at,
                 form.exclude_form_ids AS form_exclude_form_ids,
+                form.auto_schedule AS form_auto_schedule,
+                form.calendar AS form_calendar,
+                form.time_zone AS form_time_zone,
                 res.id AS res_id,
                 res.passport_id AS res_passport_id,
                 res.first_name AS res_first_name,
@@ -157,7 +226,13 @@ impl TSubmissionRepositories for SubmissionsRepository {
         }
     }
 
-    async fn update(&self, id: &str, status: &Option<String>) -> Result<(), String> {
+    async fn update(
+        &self,
+        id: &str,
+        status: &Option<String>,
+        user_id: &str,
+        arrival_date: &(dyn Fn(i32) -> DateTime<Utc> + Send + Sync),
+    ) -> Result<(), String> {
         let mut set: Vec<String> = vec![];
         let mut fields: Vec<&(dyn ToSql + Sync)> = vec![&id];
 
@@ -166,38 +241,590 @@ impl TSubmissionRepositories for SubmissionsRepository {
             set.push(format!("status = ${}", fields.len()));
         }
 
-        if set.len() == 0 {
+        if set.is_empty() {
             return Ok(());
         }
 
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let form_id = match lock_form_of(&tx, id).await {
+            Ok(form_id) => form_id,
+            Err(err) => return Err(err),
+        };
+
+        let before = match snapshot(&tx, "submissions", id).await {
+            Ok(Some(before)) => before,
+            Ok(None) => return Err("Submission not found".to_string()),
+            Err(err) => return Err(err),
+        };
+        let old_status = before["status"].as_str().unwrap_or_default().to_string();
+
+        let res = tx
+            .execute(
+                &format!("UPDATE submissions SET {} WHERE id = $1", set.join(",")),
+                &fields,
+            )
+            .await;
+        if let Err(err) = res {
+            return Err(conflict_message(&err));
+        }
+
+        if let Some(ref value) = status {
+            if value != &old_status {
+                let res = tx
+                    .execute(
+                        "
+                        INSERT INTO submission_status_history (submission_id, old_status, new_status, user_id)
+                        VALUES ($1, $2, $3, $4)
+                        ",
+                        &[&id, &old_status, value, &user_id],
+                    )
+                    .await;
+                if let Err(err) = res {
+                    return Err(err.to_string());
+                }
+            }
+
+            let cancelled = SubmissionStatus::Cancelled.to_string();
+            if value == &cancelled && old_status != cancelled {
+                match promote_waitlist(&tx, &form_id, user_id, arrival_date).await {
+                    Ok(_) => (),
+                    Err(err) => return Err(err),
+                };
+            }
+        }
+
+        let after = match snapshot(&tx, "submissions", id).await {
+            Ok(after) => after,
+            Err(err) => return Err(err),
+        };
+        match record(
+            &tx,
+            Some(user_id),
+            "status",
+            "submission",
+            id,
+            Some(before),
+            after,
+        )
+        .await
+        {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn find_history(&self, id: &str) -> Vec<StatusChange> {
+        let statement = "
+            SELECT h.*, u.email AS user_email FROM submission_status_history AS h
+            LEFT JOIN users AS u ON u.id = h.user_id
+            WHERE h.submission_id = $1
+            ORDER BY h.created_at, h.id
+        ";
         let res = self
             .pool
             .get()
             .await
             .unwrap()
-            .execute(
-                &format!("UPDATE submissions SET {} WHERE id = $1", set.join(",")),
-                &fields,
-            )
+            .query(statement, &[&id])
             .await;
         match res {
+            Ok(rows) => rows.iter().map(StatusChange::from_row).collect(),
+            Err(_err) => vec![],
+        }
+    }
+
+    async fn delete(
+        &self,
+        id: &str,
+        user_id: &str,
+        arrival_date: &(dyn Fn(i32) -> DateTime<Utc> + Send + Sync),
+    ) -> Result<(), String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let form_id = match lock_form_of(&tx, id).await {
+            Ok(form_id) => form_id,
+            Err(err) => return Err(err),
+        };
+
+        let before = match snapshot(&tx, "submissions", id).await {
+            Ok(before) => before,
+            Err(err) => return Err(err),
+        };
+
+        let res = tx
+            .query_one(
+                "DELETE FROM submissions WHERE id = $1 RETURNING status",
+                &[&id],
+            )
+            .await;
+        let status = match res {
+            Ok(row) => row.get::<&str, String>("status"),
+            Err(err) => return Err(err.to_string()),
+        };
+
+        match record(&tx, Some(user_id), "delete", "submission", id, before, None).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        if status != SubmissionStatus::Cancelled.to_string() {
+            match promote_waitlist(&tx, &form_id, user_id, arrival_date).await {
+                Ok(_) => (),
+                Err(err) => return Err(err),
+            };
+        }
+
+        match tx.commit().await {
             Ok(_) => Ok(()),
             Err(err) => Err(err.to_string()),
         }
     }
 
-    async fn delete(&self, id: &str) -> Result<(), String> {
+    async fn find_waitlist(&self, form_id: &str) -> Vec<WaitlistEntry> {
+        let statement = "
+            SELECT w.id, w.form_id, w.created_at,
+                ROW_NUMBER() OVER (ORDER BY w.position, w.created_at) AS position,
+                res.id AS res_id,
+                res.passport_id AS res_passport_id,
+                res.first_name AS res_first_name,
+                res.last_name AS res_last_name,
+                res.phone AS res_phone,
+                res.region AS res_region,
+                res.children AS res_children,
+                res.idp_code AS res_idp_code,
+                res.created_at AS res_created_at
+            FROM form_waitlist AS w
+            JOIN respondents AS res ON res.id = w.respondent_id
+            WHERE w.form_id = $1
+            ORDER BY position
+        ";
         let res = self
             .pool
             .get()
             .await
             .unwrap()
-            .execute("DELETE FROM submissions WHERE id = $1", &[&id])
+            .query(statement, &[&form_id])
             .await;
-
         match res {
+            Ok(rows) => rows.iter().map(WaitlistEntry::from_row).collect(),
+            Err(_err) => vec![],
+        }
+    }
+
+    async fn move_waitlist_entry(
+        &self,
+        form_id: &str,
+        id: &str,
+        position: usize,
+        user_id: &str,
+    ) -> Result<(), String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        if let Err(err) = tx
+            .execute("SELECT id FROM forms WHERE id = $1 FOR UPDATE", &[&form_id])
+            .await
+        {
+            return Err(err.to_string());
+        }
+
+        let rows = tx
+            .query(
+                "SELECT id FROM form_waitlist WHERE form_id = $1 ORDER BY position, created_at",
+                &[&form_id],
+            )
+            .await;
+        let mut ids: Vec<String> = match rows {
+            Ok(rows) => rows
+                .iter()
+                .map(|row| row.get::<&str, String>("id"))
+                .collect(),
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let index = match ids.iter().position(|v| v == id) {
+            Some(index) => index,
+            None => return Err("Waitlist entry not found".to_string()),
+        };
+        let entry = ids.remove(index);
+        let new_index = position.clamp(1, ids.len() + 1) - 1;
+        ids.insert(new_index, entry);
+
+        let statement = "
+            UPDATE form_waitlist SET position = t.position
+            FROM unnest($1::VARCHAR[]) WITH ORDINALITY AS t(id, position)
+            WHERE form_waitlist.id = t.id
+        ";
+        if let Err(err) = tx.execute(statement, &[&ids]).await {
+            return Err(err.to_string());
+        }
+
+        let before = Some(json!({ "position": index + 1 }));
+        let after = Some(json!({ "position": new_index + 1 }));
+        match record(
+            &tx,
+            Some(user_id),
+            "move",
+            "waitlist_entry",
+            id,
+            before,
+            after,
+        )
+        .await
+        {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn delete_waitlist_entry(
+        &self,
+        form_id: &str,
+        id: &str,
+        user_id: &str,
+    ) -> Result<(), String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let res = tx
+            .query_opt(
+                "
+                DELETE FROM form_waitlist WHERE id = $1 AND form_id = $2
+                RETURNING to_jsonb(form_waitlist) AS data
+                ",
+                &[&id, &form_id],
+            )
+            .await;
+        let before = match res {
+            Ok(Some(row)) => row.get::<&str, serde_json::Value>("data"),
+            Ok(None) => return Err("Waitlist entry not found".to_string()),
+            Err(err) => return Err(err.to_string()),
+        };
+
+        match record(
+            &tx,
+            Some(user_id),
+            "delete",
+            "waitlist_entry",
+            id,
+            Some(before),
+            None,
+        )
+        .await
+        {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
             Ok(_) => Ok(()),
             Err(err) => Err(err.to_string()),
         }
     }
 }
+
+// Locks the form a submission belongs to before the submission itself, the
+// same order `book` takes them in.
+async fn lock_form_of(tx: &Transaction<'_>, submission_id: &str) -> Result<String, String> {
+    let statement = "
+        SELECT form.id FROM forms AS form
+        JOIN submissions AS sub ON sub.form_id = form.id
+        WHERE sub.id = $1
+        FOR UPDATE OF form
+    ";
+    match tx.query_opt(statement, &[&submission_id]).await {
+        Ok(Some(row)) => Ok(row.get::<&str, String>("id")),
+        Ok(None) => Err("Submission not found".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+enum Booked {
+    Place(String),
+    Full,
+    Excluded(Vec<EligibilityConflict>),
+}
+
+// Books the first free place of the form for the respondent. The respondent
+// and form rows stay locked until the transaction ends, which serializes
+// concurrent bookings for the same form and the exclusion check for the same
+// respondent. The respondent is locked first, in the order merges lock them.
+async fn book(
+    tx: &Transaction<'_>,
+    form_id: &str,
+    respondent_id: &str,
+    status: &str,
+    user_id: &str,
+    arrival_date: &(dyn Fn(i32) -> DateTime<Utc> + Send + Sync),
+) -> Result<Booked, String> {
+    match tx
+        .query_opt(
+            "SELECT id FROM respondents WHERE id = $1 FOR UPDATE",
+            &[&respondent_id],
+        )
+        .await
+    {
+        Ok(Some(_)) => (),
+        Ok(None) => return Err("Respondent not found".to_string()),
+        Err(err) => return Err(err.to_string()),
+    };
+
+    let form_limit = match tx
+        .query_opt(
+            "SELECT form_limit FROM forms WHERE id = $1 FOR UPDATE",
+            &[&form_id],
+        )
+        .await
+    {
+        Ok(Some(row)) => row.get::<&str, i32>("form_limit"),
+        Ok(None) => return Err("Form not foound".to_string()),
+        Err(err) => return Err(err.to_string()),
+    };
+
+    let exists = tx
+        .query_opt(
+            "SELECT id FROM submissions WHERE form_id = $1 AND respondent_id = $2 AND status <> 'cancelled'",
+            &[&form_id, &respondent_id],
+        )
+        .await;
+    match exists {
+        Ok(Some(_)) => return Err("This respondent already have submission".to_string()),
+        Ok(None) => (),
+        Err(err) => return Err(err.to_string()),
+    }
+
+    let conflicts = match tx.query(CONFLICTS, &[&form_id, &respondent_id]).await {
+        Ok(rows) => rows
+            .iter()
+            .map(EligibilityConflict::from_row)
+            .collect::<Vec<_>>(),
+        Err(err) => return Err(err.to_string()),
+    };
+    if !conflicts.is_empty() {
+        return Ok(Booked::Excluded(conflicts));
+    }
+
+    let statement = "
+        SELECT n AS sub_order FROM generate_series(1, $2::INT) AS n
+        WHERE NOT EXISTS (
+            SELECT 1 FROM submissions WHERE form_id = $1 AND sub_order = n AND status <> 'cancelled'
+        )
+        ORDER BY n
+    ";
+    let free_orders: Vec<i32> = match tx.query(statement, &[&form_id, &form_limit]).await {
+        Ok(rows) => rows
+            .iter()
+            .map(|row| row.get::<&str, i32>("sub_order"))
+            .collect(),
+        Err(err) => return Err(err.to_string()),
+    };
+    if free_orders.is_empty() {
+        return Ok(Booked::Full);
+    }
+
+    // Forms with explicit slots get the earliest upcoming slot that still has
+    // room, the rest fall back to the computed arrival date.
+    let statement = "
+        SELECT s.id, s.starts_at FROM form_slots AS s
+        WHERE s.form_id = $1 AND s.starts_at > NOW() AND s.capacity > (
+            SELECT COUNT(*) FROM submissions AS sub
+            WHERE sub.slot_id = s.id AND sub.status <> 'cancelled'
+        )
+        ORDER BY s.starts_at
+        LIMIT 1
+    ";
+    let free_slot = match tx.query_opt(statement, &[&form_id]).await {
+        Ok(row) => row,
+        Err(err) => return Err(err.to_string()),
+    };
+    let (slot_id, arrival_date, sub_order) = match free_slot {
+        Some(row) => (
+            Some(row.get::<&str, String>("id")),
+            row.get::<&str, SystemTime>("starts_at").into(),
+            free_orders[0],
+        ),
+        None => {
+            let has_slots = tx
+                .query_opt(
+                    "SELECT id FROM form_slots WHERE form_id = $1 LIMIT 1",
+                    &[&form_id],
+                )
+                .await;
+            match has_slots {
+                Ok(Some(_)) => return Ok(Booked::Full),
+                Ok(None) => (),
+                Err(err) => return Err(err.to_string()),
+            }
+
+            // Places whose time has already passed can't be given out any more.
+            let now = Utc::now();
+            let upcoming = free_orders
+                .iter()
+                .map(|order| (*order, arrival_date(*order)))
+                .find(|(_, date)| *date > now);
+            match upcoming {
+                Some((order, date)) => (None, date, order),
+                None => return Ok(Booked::Full),
+            }
+        }
+    };
+
+    let statement = "
+        INSERT INTO submissions (form_id, respondent_id, arrival_date, sub_order, status, slot_id) 
+        VALUES ($1, $2, $3, $4, $5, $6) RETURNING id
+    ";
+    let res = tx
+        .query_one(
+            statement,
+            &[
+                &form_id,
+                &respondent_id,
+                &arrival_date,
+                &sub_order,
+                &status,
+                &slot_id,
+            ],
+        )
+        .await;
+
+    let id = match res {
+        Ok(row) => row.get::<&str, String>("id"),
+        Err(err) => return Err(conflict_message(&err)),
+    };
+
+    let res = tx
+        .execute(
+            "INSERT INTO submission_status_history (submission_id, new_status, user_id) VALUES ($1, $2, $3)",
+            &[&id, &status, &user_id],
+        )
+        .await;
+    if let Err(err) = res {
+        return Err(err.to_string());
+    }
+
+    let after = match snapshot(tx, "submissions", &id).await {
+        Ok(after) => after,
+        Err(err) => return Err(err),
+    };
+    match record(tx, Some(user_id), "create", "submission", &id, None, after).await {
+        Ok(_) => (),
+        Err(err) => return Err(err),
+    };
+
+    Ok(Booked::Place(id))
+}
+
+// Moves people from the head of the waitlist into freed places, each with a
+// freshly calculated arrival date. Entries of respondents who got a place in
+// some other way meanwhile, or a place in a form this one excludes, are
+// simply dropped.
+pub async fn promote_waitlist(
+    tx: &Transaction<'_>,
+    form_id: &str,
+    user_id: &str,
+    arrival_date: &(dyn Fn(i32) -> DateTime<Utc> + Send + Sync),
+) -> Result<(), String> {
+    let status = SubmissionStatus::Received.to_string();
+    loop {
+        let statement = "
+            SELECT w.id, w.respondent_id,
+                EXISTS (
+                    SELECT 1 FROM submissions AS sub
+                    WHERE sub.form_id = w.form_id AND sub.respondent_id = w.respondent_id
+                        AND sub.status <> 'cancelled'
+                ) AS booked
+            FROM form_waitlist AS w
+            WHERE w.form_id = $1
+            ORDER BY w.position, w.created_at
+            LIMIT 1
+        ";
+        let entry = match tx.query_opt(statement, &[&form_id]).await {
+            Ok(Some(row)) => row,
+            Ok(None) => return Ok(()),
+            Err(err) => return Err(err.to_string()),
+        };
+        let entry_id = entry.get::<&str, String>("id");
+        let respondent_id = entry.get::<&str, String>("respondent_id");
+
+        let action = match entry.get::<&str, bool>("booked") {
+            true => "delete",
+            false => {
+                match book(tx, form_id, &respondent_id, &status, user_id, arrival_date).await {
+                    Ok(Booked::Place(_)) => "promote",
+                    Ok(Booked::Excluded(_)) => "delete",
+                    Ok(Booked::Full) => return Ok(()),
+                    Err(err) => return Err(err),
+                }
+            }
+        };
+
+        let res = tx
+            .query_one(
+                "DELETE FROM form_waitlist WHERE id = $1 RETURNING to_jsonb(form_waitlist) AS data",
+                &[&entry_id],
+            )
+            .await;
+        let before = match res {
+            Ok(row) => row.get::<&str, serde_json::Value>("data"),
+            Err(err) => return Err(err.to_string()),
+        };
+        match record(
+            tx,
+            Some(user_id),
+            action,
+            "waitlist_entry",
+            &entry_id,
+            Some(before),
+            None,
+        )
+        .await
+        {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+    }
+}
+
+fn conflict_message(err: &tokio_postgres::Error) -> String {
+    match err.as_db_error() {
+        Some(db_err) if db_err.code() == &SqlState::UNIQUE_VIOLATION => match db_err.constraint() {
+            Some("uq_submissions_active_form_respondent") => {
+                "This respondent already have submission".to_string()
+            }
+            Some("uq_submissions_active_form_order") => {
+                "This place is already taken, try again".to_string()
+            }
+            Some("uq_form_waitlist_form_respondent") => {
+                "This respondent is already on the waitlist".to_string()
+            }
+            _ => db_err.message().to_string(),
+        },
+        Some(db_err) => db_err.message().to_string(),
+        None => err.to_string(),
+    }
+}
@@ -1,11 +1,21 @@
 use crate::app::{
-    entities::user::{User, UserToken},
+    entities::{
+        api_key::ApiKey,
+        oidc::OidcLogin,
+        role::{Permission, Role},
+        session::Session,
+        user::{User, UserToken},
+    },
     traits::repositories::user::TUserRepositories,
 };
 use async_trait::async_trait;
-use deadpool_postgres::Pool;
-use std::time::SystemTime;
-use tokio_postgres::Row;
+use chrono::{DateTime, Duration, Utc};
+use deadpool_postgres::{Pool, Transaction};
+use serde_json::{json, Value};
+use std::{str::FromStr, time::SystemTime};
+use tokio_postgres::{types::ToSql, Row};
+
+use super::audit::{record, snapshot};
 
 pub struct UserRepository {
     pool: Pool,
@@ -16,8 +26,16 @@ impl User {
         User {
             id: row.get::<&str, String>("id"),
             email: row.get::<&str, String>("email"),
+            role: Role::from_str(row.get::<&str, String>("role").as_str()).unwrap_or(Role::Viewer),
             password_alg: row.get::<&str, String>("password_alg"),
             password_hash: row.get::<&str, String>("password_hash"),
+            disabled_at: row
+                .get::<&str, Option<SystemTime>>("disabled_at")
+                .map(|v| v.into()),
+            totp_secret: row.get::<&str, Option<String>>("totp_secret"),
+            totp_enabled_at: row
+                .get::<&str, Option<SystemTime>>("totp_enabled_at")
+                .map(|v| v.into()),
             created_at: row.get::<&str, SystemTime>("created_at").into(),
         }
     }
@@ -26,8 +44,50 @@ impl User {
 impl UserToken {
     fn from_row(row: &Row) -> Self {
         UserToken {
-            token: row.get::<&str, String>("token"),
             used_for: row.get::<&str, String>("type"),
+            family: row.get::<&str, Option<String>>("family"),
+            used_at: row
+                .get::<&str, Option<SystemTime>>("used_at")
+                .map(|v| v.into()),
+        }
+    }
+}
+
+impl ApiKey {
+    fn from_row(row: &Row) -> Self {
+        ApiKey {
+            id: row.get::<&str, String>("id"),
+            name: row.get::<&str, String>("name"),
+            prefix: row.get::<&str, String>("prefix"),
+            permissions: row
+                .get::<&str, Vec<String>>("permissions")
+                .iter()
+                .filter_map(|p| Permission::from_str(p).ok())
+                .collect(),
+            user_id: row.get::<&str, String>("user_id"),
+            user_email: row.get::<&str, String>("user_email"),
+            created_at: row.get::<&str, SystemTime>("created_at").into(),
+            last_used_at: row
+                .get::<&str, Option<SystemTime>>("last_used_at")
+                .map(|v| v.into()),
+            revoked_at: row
+                .get::<&str, Option<SystemTime>>("revoked_at")
+                .map(|v| v.into()),
+        }
+    }
+}
+
+impl Session {
+    fn from_row(row: &Row) -> Self {
+        Session {
+            id: row.get::<&str, i32>("id"),
+            device: row.get::<&str, Option<String>>("device"),
+            ip: row.get::<&str, Option<String>>("ip"),
+            created_at: row.get::<&str, SystemTime>("created_at").into(),
+            last_seen_at: row
+                .get::<&str, Option<SystemTime>>("last_seen_at")
+                .map(|v| v.into()),
+            current: row.get::<&str, bool>("current"),
         }
     }
 }
@@ -40,25 +100,48 @@ impl UserRepository {
 
 #[async_trait]
 impl TUserRepositories for UserRepository {
-    async fn insert(&self, email: &str, p_hash: &str, p_alg: &str) -> Result<String, String> {
+    async fn insert(
+        &self,
+        email: &str,
+        p_hash: &str,
+        p_alg: &str,
+        role: &str,
+        user_id: Option<&str>,
+    ) -> Result<String, String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
         let statement = "
-            INSERT INTO users (password_alg, password_hash, email) 
-            VALUES ($1, $2, $3) RETURNING *
+            INSERT INTO users (password_alg, password_hash, email, role) 
+            VALUES ($1, $2, $3, $4) RETURNING *
         ";
-        let res = self
-            .pool
-            .get()
-            .await
-            .unwrap()
-            .query_one(statement, &[&p_alg, &p_hash, &email])
+        let res = tx
+            .query_one(statement, &[&p_alg, &p_hash, &email, &role])
             .await;
 
-        match res {
-            Ok(row) => Ok(row.get::<&str, String>("id")),
+        let id = match res {
+            Ok(row) => row.get::<&str, String>("id"),
             Err(err) => match err.as_db_error() {
-                Some(err) => Err(err.message().to_string()),
-                None => Err(err.to_string()),
+                Some(err) => return Err(err.message().to_string()),
+                None => return Err(err.to_string()),
             },
+        };
+
+        let after = match snapshot(&tx, "users", &id).await {
+            Ok(after) => after,
+            Err(err) => return Err(err),
+        };
+        match record(&tx, user_id, "create", "user", &id, None, after).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(id),
+            Err(err) => Err(err.to_string()),
         }
     }
 
@@ -92,72 +175,1284 @@ impl TUserRepositories for UserRepository {
         }
     }
 
-    async fn upsert_user_token(
+    async fn find(&self) -> Vec<User> {
+        let statement = "SELECT * FROM users ORDER BY created_at";
+        let res = self.pool.get().await.unwrap().query(statement, &[]).await;
+        match res {
+            Ok(rows) => rows.into_iter().map(User::from_row).collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    async fn update(
         &self,
-        user_id: &str,
-        token: &str,
-        used_for: &str,
-    ) -> Result<bool, String> {
+        id: &str,
+        email: Option<String>,
+        role: Option<String>,
+        actor_id: &str,
+    ) -> Result<(), String> {
+        let mut set: Vec<String> = vec![];
+        let mut fields: Vec<&(dyn ToSql + Sync)> = vec![&id];
+
+        if let Some(ref value) = email {
+            fields.push(value);
+            set.push(format!("email = ${}", fields.len()));
+        }
+        if let Some(ref value) = role {
+            fields.push(value);
+            set.push(format!("role = ${}", fields.len()));
+        }
+
+        if set.is_empty() {
+            return Ok(());
+        }
+
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let before = match snapshot(&tx, "users", id).await {
+            Ok(before) => before,
+            Err(err) => return Err(err),
+        };
+
+        let res = tx
+            .execute(
+                &format!("UPDATE users SET {} WHERE id = $1", set.join(",")),
+                &fields,
+            )
+            .await;
+
+        if let Err(err) = res {
+            return match err.as_db_error() {
+                Some(err) => Err(err.message().to_string()),
+                None => Err(err.to_string()),
+            };
+        }
+
+        let after = match snapshot(&tx, "users", id).await {
+            Ok(after) => after,
+            Err(err) => return Err(err),
+        };
+        match record(&tx, Some(actor_id), "update", "user", id, before, after).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn set_disabled(&self, id: &str, disabled: bool, actor_id: &str) -> Result<(), String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let before = match snapshot(&tx, "users", id).await {
+            Ok(before) => before,
+            Err(err) => return Err(err),
+        };
+
+        let statement = "
+            UPDATE users SET disabled_at = CASE WHEN $2 THEN COALESCE(disabled_at, NOW()) END
+            WHERE id = $1
+        ";
+        if let Err(err) = tx.execute(statement, &[&id, &disabled]).await {
+            return Err(err.to_string());
+        }
+
+        let after = match snapshot(&tx, "users", id).await {
+            Ok(after) => after,
+            Err(err) => return Err(err),
+        };
+        let action = if disabled { "disable" } else { "enable" };
+        match record(&tx, Some(actor_id), action, "user", id, before, after).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        if disabled {
+            let res = tx
+                .query(
+                    "DELETE FROM user_tokens WHERE user_id = $1 RETURNING id, type, user_id, device, ip",
+                    &[&id],
+                )
+                .await;
+            let rows = match res {
+                Ok(rows) => rows,
+                Err(err) => return Err(err.to_string()),
+            };
+
+            match record_sign_outs(&tx, Some(actor_id), &rows).await {
+                Ok(_) => (),
+                Err(err) => return Err(err),
+            };
+        }
+
+        match tx.commit().await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn update_password(
+        &self,
+        id: &str,
+        p_hash: &str,
+        p_alg: &str,
+        keep_family: &Option<String>,
+        actor_id: &str,
+    ) -> Result<(), String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        match set_password(&tx, id, p_hash, p_alg, keep_family, actor_id).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn rehash_password(
+        &self,
+        id: &str,
+        old_hash: &str,
+        p_hash: &str,
+        p_alg: &str,
+    ) -> Result<(), String> {
+        // Skipped when the password was changed since it was read.
+        let statement = "
+            UPDATE users SET password_hash = $3, password_alg = $4
+            WHERE id = $1 AND password_hash = $2
+        ";
         let res = self
             .pool
             .get()
             .await
             .unwrap()
-            .execute("
-                    WITH upsert AS (UPDATE user_tokens SET token = $2 WHERE user_id = $1 AND type = $3 RETURNING *)
-                    INSERT INTO user_tokens (user_id, token, type) SELECT $1,$2,$3 WHERE NOT EXISTS (SELECT * FROM upsert);
-            ", &[&user_id, &token, &used_for],
+            .execute(statement, &[&id, &old_hash, &p_hash, &p_alg])
+            .await;
+
+        match res {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn insert_reset_token(&self, user_id: &str, token: &str) -> Result<(), String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        // Only the latest reset token of a user stays valid.
+        if let Err(err) = tx
+            .execute(
+                "DELETE FROM user_tokens WHERE user_id = $1 AND type = 'RESET'",
+                &[&user_id],
             )
+            .await
+        {
+            return Err(err.to_string());
+        }
+
+        if let Err(err) = tx
+            .execute(
+                "INSERT INTO user_tokens (user_id, token, type) VALUES ($1, $2, 'RESET')",
+                &[&user_id, &token],
+            )
+            .await
+        {
+            return Err(err.to_string());
+        }
+
+        match tx.commit().await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn reset_password(&self, token: &str, p_hash: &str, p_alg: &str) -> Result<bool, String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        // Removing the token first makes sure it can't be used twice.
+        let statement = "
+            DELETE FROM user_tokens WHERE token = $1 AND type = 'RESET' RETURNING user_id
+        ";
+        let user_id = match tx.query_opt(statement, &[&token]).await {
+            Ok(Some(row)) => row.get::<&str, String>("user_id"),
+            Ok(None) => return Ok(false),
+            Err(err) => return Err(err.to_string()),
+        };
+
+        match set_password(&tx, &user_id, p_hash, p_alg, &None, &user_id).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(true),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn delete(&self, id: &str, actor_id: &str) -> Result<(), String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let before = match snapshot(&tx, "users", id).await {
+            Ok(before) => before,
+            Err(err) => return Err(err),
+        };
+
+        if let Err(err) = tx.execute("DELETE FROM users WHERE id = $1", &[&id]).await {
+            return Err(err.to_string());
+        }
+
+        match record(&tx, Some(actor_id), "delete", "user", id, before, None).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn find_login_lock(&self, email: &str, ip: &Option<String>) -> Option<DateTime<Utc>> {
+        let statement = "
+            SELECT MAX(locked_until) AS locked_until FROM login_attempts
+            WHERE locked_until > NOW()
+              AND ((kind = 'EMAIL' AND value = LOWER($1)) OR (kind = 'IP' AND value = $2))
+        ";
+        let res = self
+            .pool
+            .get()
+            .await
+            .unwrap()
+            .query_one(statement, &[&email, ip])
             .await;
 
         match res {
-            Ok(row) => Ok(row != 0),
-            Err(err) => match err.as_db_error() {
-                Some(err) => Err(err.message().to_string()),
-                None => Err(err.to_string()),
-            },
+            Ok(row) => row
+                .get::<&str, Option<SystemTime>>("locked_until")
+                .map(|v| v.into()),
+            Err(_) => None,
         }
     }
 
-    async fn remove_user_tokens(&self, user_id: &str, tokens: Vec<&str>) -> Result<(), String> {
+    async fn record_login_failure(
+        &self,
+        email: &str,
+        ip: &Option<String>,
+        window: Duration,
+        lockout: &(dyn Fn(i32) -> Option<Duration> + Send + Sync),
+    ) -> Result<(), String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let email = email.to_lowercase();
+        let mut keys = vec![("EMAIL", email.as_str())];
+        if let Some(ip) = ip {
+            keys.push(("IP", ip.as_str()));
+        }
+
+        // Failures older than the window are forgotten.
+        let statement = "
+            INSERT INTO login_attempts (kind, value, failures, last_failed_at)
+            VALUES ($1, $2, 1, NOW())
+            ON CONFLICT (kind, value) DO UPDATE SET
+              failures = CASE
+                WHEN login_attempts.last_failed_at < NOW() - $3::BIGINT * INTERVAL '1 second' THEN 1
+                ELSE login_attempts.failures + 1
+              END,
+              last_failed_at = NOW()
+            RETURNING failures
+        ";
+        let window = window.num_seconds();
+
+        for (kind, value) in keys {
+            let failures = match tx.query_one(statement, &[&kind, &value, &window]).await {
+                Ok(row) => row.get::<&str, i32>("failures"),
+                Err(err) => return Err(err.to_string()),
+            };
+
+            let locked_until = lockout(failures).map(|d| SystemTime::from(Utc::now() + d));
+            if let Err(err) = tx
+                .execute(
+                    "UPDATE login_attempts SET locked_until = $3 WHERE kind = $1 AND value = $2",
+                    &[&kind, &value, &locked_until],
+                )
+                .await
+            {
+                return Err(err.to_string());
+            }
+        }
+
+        match tx.commit().await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn clear_login_failures(&self, email: &str) -> Result<(), String> {
         let res = self
             .pool
             .get()
             .await
             .unwrap()
             .execute(
-                "DELETE FROM user_tokens WHERE user_id = $1 AND token = any($2);",
-                &[&user_id, &tokens],
+                "DELETE FROM login_attempts WHERE kind = 'EMAIL' AND value = LOWER($1)",
+                &[&email],
             )
             .await;
 
         match res {
             Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn count_reset_request(
+        &self,
+        kind: &str,
+        value: &str,
+        window: Duration,
+    ) -> Result<i32, String> {
+        // Requests are counted in fixed windows that start with the first one.
+        let statement = "
+            INSERT INTO reset_requests (kind, value, requests, window_started_at)
+            VALUES ($1, $2, 1, NOW())
+            ON CONFLICT (kind, value) DO UPDATE SET
+              requests = CASE
+                WHEN reset_requests.window_started_at < NOW() - $3::BIGINT * INTERVAL '1 second' THEN 1
+                ELSE reset_requests.requests + 1
+              END,
+              window_started_at = CASE
+                WHEN reset_requests.window_started_at < NOW() - $3::BIGINT * INTERVAL '1 second' THEN NOW()
+                ELSE reset_requests.window_started_at
+              END
+            RETURNING requests
+        ";
+        let res = self
+            .pool
+            .get()
+            .await
+            .unwrap()
+            .query_one(statement, &[&kind, &value, &window.num_seconds()])
+            .await;
+
+        match res {
+            Ok(row) => Ok(row.get::<&str, i32>("requests")),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn unlock(&self, id: &str, actor_id: &str) -> Result<(), String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let statement = "
+            DELETE FROM login_attempts
+            WHERE kind = 'EMAIL' AND value = (SELECT LOWER(email) FROM users WHERE id = $1)
+            RETURNING jsonb_build_object('failures', failures, 'locked_until', locked_until) AS data
+        ";
+        let before = match tx.query_opt(statement, &[&id]).await {
+            Ok(row) => row.map(|row| row.get::<&str, Value>("data")),
+            Err(err) => return Err(err.to_string()),
+        };
+
+        match record(&tx, Some(actor_id), "unlock", "user", id, before, None).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn set_totp_secret(&self, id: &str, secret: &str) -> Result<(), String> {
+        let statement = "
+            UPDATE users SET totp_secret = $2, totp_enabled_at = NULL, totp_last_step = NULL
+            WHERE id = $1
+        ";
+        let res = self
+            .pool
+            .get()
+            .await
+            .unwrap()
+            .execute(statement, &[&id, &secret])
+            .await;
+
+        match res {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn enable_totp(&self, id: &str, step: i64, codes: Vec<String>) -> Result<(), String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let before = match snapshot(&tx, "users", id).await {
+            Ok(before) => before,
+            Err(err) => return Err(err),
+        };
+
+        let statement = "
+            UPDATE users SET totp_enabled_at = NOW(), totp_last_step = $2
+            WHERE id = $1 AND totp_secret IS NOT NULL
+        ";
+        if let Err(err) = tx.execute(statement, &[&id, &step]).await {
+            return Err(err.to_string());
+        }
+
+        match insert_recovery_codes(&tx, id, &codes).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        let after = match snapshot(&tx, "users", id).await {
+            Ok(after) => after,
+            Err(err) => return Err(err),
+        };
+        match record(&tx, Some(id), "enable_totp", "user", id, before, after).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn disable_totp(&self, id: &str, actor_id: &str) -> Result<(), String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let before = match snapshot(&tx, "users", id).await {
+            Ok(before) => before,
+            Err(err) => return Err(err),
+        };
+
+        let statement = "
+            UPDATE users SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_step = NULL
+            WHERE id = $1
+        ";
+        if let Err(err) = tx.execute(statement, &[&id]).await {
+            return Err(err.to_string());
+        }
+
+        if let Err(err) = tx
+            .execute("DELETE FROM user_recovery_codes WHERE user_id = $1", &[&id])
+            .await
+        {
+            return Err(err.to_string());
+        }
+
+        let after = match snapshot(&tx, "users", id).await {
+            Ok(after) => after,
+            Err(err) => return Err(err),
+        };
+        match record(
+            &tx,
+            Some(actor_id),
+            "disable_totp",
+            "user",
+            id,
+            before,
+            after,
+        )
+        .await
+        {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn use_totp_step(&self, id: &str, step: i64) -> Result<bool, String> {
+        let statement = "
+            UPDATE users SET totp_last_step = $2
+            WHERE id = $1 AND (totp_last_step IS NULL OR totp_last_step < $2)
+        ";
+        let res = self
+            .pool
+            .get()
+            .await
+            .unwrap()
+            .execute(statement, &[&id, &step])
+            .await;
+
+        match res {
+            Ok(count) => Ok(count == 1),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    /// Ids and hashes of the recovery codes the user hasn't used yet.
+    async fn find_recovery_codes(&self, id: &str) -> Vec<(i32, String)> {
+        let statement = "
+            SELECT id, code_hash FROM user_recovery_codes
+            WHERE user_id = $1 AND used_at IS NULL
+        ";
+        let res = self
+            .pool
+            .get()
+            .await
+            .unwrap()
+            .query(statement, &[&id])
+            .await;
+
+        match res {
+            Ok(rows) => rows
+                .iter()
+                .map(|row| (row.get("id"), row.get("code_hash")))
+                .collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    async fn use_recovery_code(&self, id: &str, code_id: i32) -> Result<bool, String> {
+        let statement = "
+            UPDATE user_recovery_codes SET used_at = NOW()
+            WHERE user_id = $1 AND id = $2 AND used_at IS NULL
+        ";
+        let res = self
+            .pool
+            .get()
+            .await
+            .unwrap()
+            .execute(statement, &[&id, &code_id])
+            .await;
+
+        match res {
+            Ok(count) => Ok(count > 0),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn replace_recovery_codes(&self, id: &str, codes: Vec<String>) -> Result<(), String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        match insert_recovery_codes(&tx, id, &codes).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        // The codes themselves stay out of the log, only that they were renewed.
+        let after = Some(json!({ "recovery_codes": codes.len() }));
+        match record(&tx, Some(id), "recovery_codes", "user", id, None, after).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn find_setting(&self, key: &str) -> Option<String> {
+        let res = self
+            .pool
+            .get()
+            .await
+            .unwrap()
+            .query_opt("SELECT value FROM settings WHERE key = $1", &[&key])
+            .await;
+
+        match res {
+            Ok(Some(row)) => Some(row.get::<&str, String>("value")),
+            _ => None,
+        }
+    }
+
+    async fn set_setting(&self, key: &str, value: &str, actor_id: &str) -> Result<(), String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let res = tx
+            .query_opt(
+                "SELECT value FROM settings WHERE key = $1 FOR UPDATE",
+                &[&key],
+            )
+            .await;
+        let before = match res {
+            Ok(row) => row.map(|row| json!({ "value": row.get::<&str, String>("value") })),
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let statement = "
+            INSERT INTO settings (key, value) VALUES ($1, $2)
+            ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value
+        ";
+        if let Err(err) = tx.execute(statement, &[&key, &value]).await {
+            return Err(err.to_string());
+        }
+
+        let after = Some(json!({ "value": value }));
+        match record(&tx, Some(actor_id), "update", "setting", key, before, after).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn insert_api_key(
+        &self,
+        user_id: &str,
+        name: &str,
+        prefix: &str,
+        key_hash: &str,
+        permissions: Vec<String>,
+    ) -> Result<String, String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let statement = "
+            INSERT INTO api_keys (user_id, name, prefix, key_hash, permissions)
+            VALUES ($1, $2, $3, $4, $5) RETURNING id
+        ";
+        let res = tx
+            .query_one(
+                statement,
+                &[&user_id, &name, &prefix, &key_hash, &permissions],
+            )
+            .await;
+        let id = match res {
+            Ok(row) => row.get::<&str, String>("id"),
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let after = match snapshot(&tx, "api_keys", &id).await {
+            Ok(after) => after,
+            Err(err) => return Err(err),
+        };
+        match record(&tx, Some(user_id), "create", "api_key", &id, None, after).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(id),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn find_api_keys(&self) -> Vec<ApiKey> {
+        let statement = "
+            SELECT k.*, u.email AS user_email FROM api_keys AS k
+            INNER JOIN users AS u ON u.id = k.user_id
+            ORDER BY k.created_at DESC
+        ";
+        let res = self.pool.get().await.unwrap().query(statement, &[]).await;
+
+        match res {
+            Ok(rows) => rows.iter().map(ApiKey::from_row).collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    async fn find_api_key_by_hash(&self, key_hash: &str) -> Option<ApiKey> {
+        let statement = "
+            SELECT k.*, u.email AS user_email FROM api_keys AS k
+            INNER JOIN users AS u ON u.id = k.user_id
+            WHERE k.key_hash = $1 AND k.revoked_at IS NULL
+        ";
+        let res = self
+            .pool
+            .get()
+            .await
+            .unwrap()
+            .query_opt(statement, &[&key_hash])
+            .await;
+
+        match res {
+            Ok(row) => row.as_ref().map(ApiKey::from_row),
+            Err(_) => None,
+        }
+    }
+
+    async fn revoke_api_key(&self, id: &str, actor_id: &str) -> Result<bool, String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let before = match snapshot(&tx, "api_keys", id).await {
+            Ok(before) => before,
+            Err(err) => return Err(err),
+        };
+
+        let statement = "
+            UPDATE api_keys SET revoked_at = NOW() WHERE id = $1 AND revoked_at IS NULL
+        ";
+        match tx.execute(statement, &[&id]).await {
+            Ok(0) => return Ok(false),
+            Ok(_) => (),
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let after = match snapshot(&tx, "api_keys", id).await {
+            Ok(after) => after,
+            Err(err) => return Err(err),
+        };
+        match record(&tx, Some(actor_id), "revoke", "api_key", id, before, after).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(true),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn touch_api_key(&self, id: &str) {
+        let statement = "
+            UPDATE api_keys SET last_used_at = NOW()
+            WHERE id = $1 AND (last_used_at IS NULL OR last_used_at < NOW() - INTERVAL '1 minute')
+        ";
+        let _ = self
+            .pool
+            .get()
+            .await
+            .unwrap()
+            .execute(statement, &[&id])
+            .await;
+    }
+
+    async fn insert_oidc_login(
+        &self,
+        state: &str,
+        nonce: &str,
+        code_verifier: &str,
+    ) -> Result<(), String> {
+        let client = self.pool.get().await.unwrap();
+
+        // Attempts abandoned at the identity provider are never taken.
+        let _ = client
+            .execute(
+                "DELETE FROM oidc_logins WHERE created_at < NOW() - INTERVAL '1 day'",
+                &[],
+            )
+            .await;
+
+        let res = client
+            .execute(
+                "INSERT INTO oidc_logins (state, nonce, code_verifier) VALUES ($1, $2, $3)",
+                &[&state, &nonce, &code_verifier],
+            )
+            .await;
+
+        match res {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn take_oidc_login(&self, state: &str, max_age: Duration) -> Option<OidcLogin> {
+        let statement = "
+            DELETE FROM oidc_logins WHERE state = $1 RETURNING nonce, code_verifier, created_at
+        ";
+        let res = self
+            .pool
+            .get()
+            .await
+            .unwrap()
+            .query_opt(statement, &[&state])
+            .await;
+
+        match res {
+            Ok(Some(row))
+                if row.get::<&str, DateTime<Utc>>("created_at") > Utc::now() - max_age =>
+            {
+                Some(OidcLogin {
+                    nonce: row.get("nonce"),
+                    code_verifier: row.get("code_verifier"),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    async fn insert_token_family(
+        &self,
+        user_id: &str,
+        access_token: &str,
+        refresh_token: &str,
+        device: &Option<String>,
+        ip: &Option<String>,
+        ttl: Duration,
+    ) -> Result<(), String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        // Sessions whose last refresh token has expired can't be resumed.
+        let statement = "
+            DELETE FROM user_tokens WHERE family IN (
+                SELECT family FROM user_tokens WHERE type = 'REFRESH' AND family IS NOT NULL
+                GROUP BY family
+                HAVING MAX(created_at) < NOW() - $1::BIGINT * INTERVAL '1 second'
+            )
+        ";
+        if let Err(err) = tx.execute(statement, &[&ttl.num_seconds()]).await {
+            return Err(err.to_string());
+        }
+
+        let statement = "
+            WITH f AS (SELECT uuid_generate_v4()::VARCHAR AS family)
+            INSERT INTO user_tokens (user_id, token, type, family, device, ip, last_seen_at)
+            SELECT $1, t.token, t.type, f.family, $4, $5, NOW() FROM f,
+                (VALUES ($2::VARCHAR, 'WEB'), ($3::VARCHAR, 'REFRESH')) AS t(token, type)
+            RETURNING id, type
+        ";
+        let res = tx
+            .query(
+                statement,
+                &[&user_id, &access_token, &refresh_token, device, ip],
+            )
+            .await;
+        let rows = match res {
+            Ok(rows) => rows,
             Err(err) => match err.as_db_error() {
-                Some(err) => Err(err.message().to_string()),
-                None => Err(err.to_string()),
+                Some(err) => return Err(err.message().to_string()),
+                None => return Err(err.to_string()),
             },
+        };
+
+        // A session is known by the id of its access token row.
+        for row in rows
+            .iter()
+            .filter(|row| row.get::<&str, String>("type") == "WEB")
+        {
+            let id = row.get::<&str, i32>("id").to_string();
+            let after = Some(json!({ "user_id": user_id, "device": device, "ip": ip }));
+            match record(&tx, Some(user_id), "sign_in", "session", &id, None, after).await {
+                Ok(_) => (),
+                Err(err) => return Err(err),
+            };
+        }
+
+        match tx.commit().await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
         }
     }
 
-    async fn find_tokens(&self, user_id: &str) -> Vec<UserToken> {
+    async fn rotate_refresh_token(
+        &self,
+        refresh_token: &str,
+        access_token: &str,
+        new_refresh_token: &str,
+        ttl: Duration,
+    ) -> Result<bool, String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        // Only one caller can mark the token as used, a second one is a reuse.
         let statement = "
-            SELECT t.* FROM users AS u 
-            INNER JOIN user_tokens AS t ON u.id = t.user_id 
-            WHERE u.id = $1;
+            UPDATE user_tokens SET used_at = NOW()
+            WHERE token = $1 AND type = 'REFRESH' AND used_at IS NULL
+            RETURNING user_id, family
         ";
+        let (user_id, family) = match tx.query_opt(statement, &[&refresh_token]).await {
+            Ok(Some(row)) => (
+                row.get::<&str, String>("user_id"),
+                row.get::<&str, Option<String>>("family"),
+            ),
+            Ok(None) => return Ok(false),
+            Err(err) => return Err(err.to_string()),
+        };
 
+        // The access token row is the session itself, so it is updated in place.
+        if let Err(err) = tx
+            .execute(
+                "UPDATE user_tokens SET token = $2, last_seen_at = NOW() WHERE family = $1 AND type = 'WEB'",
+                &[&family, &access_token],
+            )
+            .await
+        {
+            return Err(err.to_string());
+        }
+
+        let statement = "
+            INSERT INTO user_tokens (user_id, token, type, family)
+            VALUES ($1, $2, 'REFRESH', $3)
+        ";
+        if let Err(err) = tx
+            .execute(statement, &[&user_id, &new_refresh_token, &family])
+            .await
+        {
+            return Err(err.to_string());
+        }
+
+        // Used tokens are kept to catch a reuse only while they haven't expired.
+        let statement = "
+            DELETE FROM user_tokens
+            WHERE family = $1 AND type = 'REFRESH' AND used_at IS NOT NULL
+                AND created_at < NOW() - $2::BIGINT * INTERVAL '1 second'
+        ";
+        if let Err(err) = tx.execute(statement, &[&family, &ttl.num_seconds()]).await {
+            return Err(err.to_string());
+        }
+
+        match tx.commit().await {
+            Ok(_) => Ok(true),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn find_token(&self, token: &str) -> Option<UserToken> {
         let res = self
             .pool
             .get()
             .await
             .unwrap()
-            .query(statement, &[&user_id])
+            .query_opt("SELECT * FROM user_tokens WHERE token = $1", &[&token])
             .await;
 
         match res {
-            Ok(rows) => rows.iter().map(|data| UserToken::from_row(data)).collect(),
+            Ok(Some(row)) => Some(UserToken::from_row(&row)),
+            _ => None,
+        }
+    }
+
+    async fn remove_token_family(&self, family: &str) -> Result<(), String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let res = tx
+            .query(
+                "DELETE FROM user_tokens WHERE family = $1 RETURNING id, type, user_id, device, ip",
+                &[&family],
+            )
+            .await;
+        let rows = match res {
+            Ok(rows) => rows,
+            Err(err) => match err.as_db_error() {
+                Some(err) => return Err(err.message().to_string()),
+                None => return Err(err.to_string()),
+            },
+        };
+
+        match record_sign_outs(&tx, None, &rows).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn touch_token(&self, token: &str) {
+        // Written at most once a minute to keep reads cheap.
+        let statement = "
+            UPDATE user_tokens SET last_seen_at = NOW()
+            WHERE token = $1 AND (last_seen_at IS NULL OR last_seen_at < NOW() - INTERVAL '1 minute')
+        ";
+        let _ = self
+            .pool
+            .get()
+            .await
+            .unwrap()
+            .execute(statement, &[&token])
+            .await;
+    }
+
+    async fn find_sessions(
+        &self,
+        user_id: &str,
+        current_token: &str,
+        ttl: Duration,
+    ) -> Vec<Session> {
+        // A session is gone once its latest refresh token has expired.
+        let statement = "
+            SELECT t.id, t.device, t.ip, t.created_at, t.last_seen_at, t.token = $2 AS current
+            FROM user_tokens AS t
+            WHERE t.user_id = $1 AND t.type = 'WEB' AND EXISTS (
+                SELECT 1 FROM user_tokens AS r
+                WHERE r.family = t.family AND r.type = 'REFRESH' AND r.used_at IS NULL
+                    AND r.created_at >= NOW() - $3::BIGINT * INTERVAL '1 second'
+            )
+            ORDER BY t.last_seen_at DESC NULLS LAST, t.id DESC
+        ";
+        let res = self
+            .pool
+            .get()
+            .await
+            .unwrap()
+            .query(statement, &[&user_id, &current_token, &ttl.num_seconds()])
+            .await;
+
+        match res {
+            Ok(rows) => rows.iter().map(Session::from_row).collect(),
             Err(_err) => vec![],
         }
     }
+
+    async fn remove_session(&self, user_id: &str, id: i32) -> Result<bool, String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let statement = "
+            DELETE FROM user_tokens WHERE user_id = $1 AND ((id = $2 AND type = 'WEB') OR family = (
+                SELECT family FROM user_tokens WHERE id = $2 AND user_id = $1 AND type = 'WEB'
+            ))
+            RETURNING id, type, user_id, device, ip
+        ";
+        let rows = match tx.query(statement, &[&user_id, &id]).await {
+            Ok(rows) => rows,
+            Err(err) => match err.as_db_error() {
+                Some(err) => return Err(err.message().to_string()),
+                None => return Err(err.to_string()),
+            },
+        };
+
+        match record_sign_outs(&tx, None, &rows).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(!rows.is_empty()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn remove_all_tokens(&self, user_id: &str) -> Result<(), String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let res = tx
+            .query(
+                "DELETE FROM user_tokens WHERE user_id = $1 RETURNING id, type, user_id, device, ip",
+                &[&user_id],
+            )
+            .await;
+        let rows = match res {
+            Ok(rows) => rows,
+            Err(err) => match err.as_db_error() {
+                Some(err) => return Err(err.message().to_string()),
+                None => return Err(err.to_string()),
+            },
+        };
+
+        match record_sign_outs(&tx, None, &rows).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn remove_user_tokens(&self, user_id: &str, tokens: Vec<&str>) -> Result<(), String> {
+        let mut client = self.pool.get().await.unwrap();
+        let tx = match client.transaction().await {
+            Ok(tx) => tx,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let res = tx
+            .query(
+                "
+                DELETE FROM user_tokens WHERE user_id = $1 AND token = any($2)
+                RETURNING id, type, user_id, device, ip
+                ",
+                &[&user_id, &tokens],
+            )
+            .await;
+        let rows = match res {
+            Ok(rows) => rows,
+            Err(err) => match err.as_db_error() {
+                Some(err) => return Err(err.message().to_string()),
+                None => return Err(err.to_string()),
+            },
+        };
+
+        match record_sign_outs(&tx, None, &rows).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+}
+
+/// Logs the end of every session among the removed token rows, on behalf of
+/// `actor_id` or else the user who owned it.
+async fn record_sign_outs(
+    tx: &Transaction<'_>,
+    actor_id: Option<&str>,
+    rows: &[Row],
+) -> Result<(), String> {
+    for row in rows
+        .iter()
+        .filter(|row| row.get::<&str, String>("type") == "WEB")
+    {
+        let id = row.get::<&str, i32>("id").to_string();
+        let user_id = row.get::<&str, String>("user_id");
+        let before = Some(json!({
+            "user_id": user_id,
+            "device": row.get::<&str, Option<String>>("device"),
+            "ip": row.get::<&str, Option<String>>("ip"),
+        }));
+        let actor_id = actor_id.unwrap_or(&user_id);
+        match record(tx, Some(actor_id), "sign_out", "session", &id, before, None).await {
+            Ok(_) => (),
+            Err(err) => return Err(err),
+        };
+    }
+    Ok(())
+}
+
+/// Sets the password and signs the user out of every session except the
+/// `keep_family` one.
+async fn set_password(
+    tx: &Transaction<'_>,
+    id: &str,
+    p_hash: &str,
+    p_alg: &str,
+    keep_family: &Option<String>,
+    actor_id: &str,
+) -> Result<(), String> {
+    if let Err(err) = tx
+        .execute(
+            "UPDATE users SET password_hash = $2, password_alg = $3 WHERE id = $1",
+            &[&id, &p_hash, &p_alg],
+        )
+        .await
+    {
+        return Err(err.to_string());
+    }
+
+    // The hash is hidden from snapshots, so only the fact of the change is kept.
+    let after = Some(json!({ "password": "changed" }));
+    match record(tx, Some(actor_id), "password", "user", id, None, after).await {
+        Ok(_) => (),
+        Err(err) => return Err(err),
+    };
+
+    let statement = "
+        DELETE FROM user_tokens
+        WHERE user_id = $1 AND ($2::VARCHAR IS NULL OR family IS DISTINCT FROM $2)
+        RETURNING id, type, user_id, device, ip
+    ";
+    let rows = match tx.query(statement, &[&id, keep_family]).await {
+        Ok(rows) => rows,
+        Err(err) => return Err(err.to_string()),
+    };
+
+    record_sign_outs(tx, Some(actor_id), &rows).await
+}
+
+/// Replaces the recovery codes of the user with the given hashes.
+async fn insert_recovery_codes(
+    tx: &Transaction<'_>,
+    user_id: &str,
+    codes: &[String],
+) -> Result<(), String> {
+    if let Err(err) = tx
+        .execute(
+            "DELETE FROM user_recovery_codes WHERE user_id = $1",
+            &[&user_id],
+        )
+        .await
+    {
+        return Err(err.to_string());
+    }
+
+    for code in codes {
+        if let Err(err) = tx
+            .execute(
+                "INSERT INTO user_recovery_codes (user_id, code_hash) VALUES ($1, $2)",
+                &[&user_id, code],
+            )
+            .await
+        {
+            return Err(err.to_string());
+        }
+    }
+
+    Ok(())
 }
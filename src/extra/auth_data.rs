@@ -30,6 +30,14 @@ where
                 }
             });
 
+        // Scripts may send their API key in its own header instead.
+        let auth_header = auth_header.or_else(|| {
+            parts
+                .headers
+                .get("X-API-Key")
+                .and_then(|value| value.to_str().ok())
+        });
+
         match auth_header {
             Some(token) => Ok(AuthData {
                 token: token.trim().to_string(),
@@ -0,0 +1,50 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::{self, request::Parts, StatusCode},
+};
+
+use crate::AppState;
+
+/// Address and user agent of the caller. The address is the socket peer, or
+/// the forwarded one when the request came through a trusted proxy.
+#[derive(Debug)]
+pub struct ClientInfo {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for ClientInfo {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let header = |name: &str| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|info| info.0.ip());
+        let ip = state
+            .config
+            .trusted_proxies
+            .client_ip(peer, header("x-forwarded-for").as_deref())
+            .map(|ip| ip.to_string());
+
+        Ok(ClientInfo {
+            ip,
+            user_agent: header(http::header::USER_AGENT.as_str()),
+        })
+    }
+}
@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::request::Parts,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+
+use super::auth_data::AuthData;
+use crate::{
+    app::services::{auth::AuthService, current_user::CurrentUser},
+    routes::error_status,
+    AppState,
+};
+
+/// Authenticates the request once, handlers and services get the resolved
+/// user instead of the raw token.
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for CurrentUser {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        if let Some(current) = parts.extensions.get::<CurrentUser>() {
+            return Ok(current.clone());
+        }
+
+        let auth = match AuthData::from_request_parts(parts, state).await {
+            Ok(auth) => auth,
+            Err(rejection) => return Err(rejection.into_response()),
+        };
+
+        let service = AuthService::new(&state.config, state.db.users.as_ref());
+        match service.authenticate(&auth.token).await {
+            Ok(current) => {
+                parts.extensions.insert(current.clone());
+                Ok(current)
+            }
+            Err(err) => Err((error_status(&err), Json(json!({ "data":  err }))).into_response()),
+        }
+    }
+}
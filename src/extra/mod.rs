@@ -1,2 +1,4 @@
 pub mod auth_data;
+pub mod client_info;
+pub mod current_user;
 pub mod  json_input;
\ No newline at end of file
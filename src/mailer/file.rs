@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use chrono::Utc;
+
+use crate::app::traits::mailer::TMailer;
+
+pub struct FileMailer {
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(dir: String) -> Self {
+        Self {
+            dir: PathBuf::from(dir),
+        }
+    }
+}
+
+#[async_trait]
+impl TMailer for FileMailer {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> Result<(), String> {
+        if let Err(err) = tokio::fs::create_dir_all(&self.dir).await {
+            return Err(err.to_string());
+        }
+
+        let name = format!("{}-{}.eml", Utc::now().format("%Y%m%d%H%M%S%f"), to);
+        let content = format!("To: {}\nSubject: {}\n\n{}\n", to, subject, body);
+
+        match tokio::fs::write(self.dir.join(name), content).await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+}
@@ -0,0 +1,13 @@
+use async_trait::async_trait;
+
+use crate::app::traits::mailer::TMailer;
+
+pub struct LogMailer {}
+
+#[async_trait]
+impl TMailer for LogMailer {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> Result<(), String> {
+        println!("To: {}\nSubject: {}\n\n{}\n", to, subject, body);
+        Ok(())
+    }
+}
@@ -0,0 +1,18 @@
+use crate::app::traits::mailer::TMailer;
+
+use self::{file::FileMailer, log::LogMailer};
+
+mod file;
+mod log;
+
+/// Picks the mailer set by the `MAILER` env variable. `log` prints mails to
+/// stdout and `file` writes each mail into `MAILER_DIR`.
+pub fn from_env() -> Box<dyn TMailer + Sync + Send> {
+    match std::env::var("MAILER").as_deref() {
+        Ok("file") => {
+            let dir = std::env::var("MAILER_DIR").unwrap_or("mail".to_string());
+            Box::new(FileMailer::new(dir))
+        }
+        _ => Box::new(LogMailer {}),
+    }
+}
@@ -1,41 +1,162 @@
-use app::config::Config;
+use app::{
+    config::{Config, JwtKeys, TrustedProxies},
+    entities::role::Role,
+    services::form_scheduler::FormScheduler,
+    traits::{mailer::TMailer, oidc::TOidcProvider},
+};
 use axum::Router;
+use chrono::Utc;
 use db::DB;
 use dotenv::dotenv;
-use routes::{auth, form, respondent, submission};
-use std::sync::Arc;
+use routes::{api_key, audit, auth, form, respondent, submission, user};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tower_http::services::{ServeDir, ServeFile};
 
 mod app;
 mod db;
 mod extra;
+mod mailer;
+mod oidc;
 mod routes;
 
 pub struct AppState {
     db: DB,
     config: Config,
+    mailer: Box<dyn TMailer + Sync + Send>,
+    oidc: Option<Box<dyn TOidcProvider + Sync + Send>>,
 }
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
 
-    let jwt_secret_key = std::env::var("JWT_SECRET_KEY").expect("set JWT_SECRET_KEY env variable");
-    let config = Config { jwt_secret_key };
+    // JWT_SECRET_KEY is the "default" HS256 key; JWT_KEYS_FILE adds more keys
+    // and JWT_ACTIVE_KEY picks the one that signs new tokens.
+    let jwt_keys = JwtKeys::load(
+        std::env::var("JWT_SECRET_KEY").ok(),
+        std::env::var("JWT_KEYS_FILE").ok(),
+        std::env::var("JWT_ACTIVE_KEY").ok(),
+    )
+    .expect("set valid JWT_SECRET_KEY or JWT_KEYS_FILE env variables");
+    // Seconds between scheduler runs, at least one.
+    let form_scheduler_interval = std::env::var("FORM_SCHEDULER_INTERVAL")
+        .ok()
+        .map(|v| {
+            v.parse::<u64>()
+                .ok()
+                .filter(|v| *v >= 1)
+                .expect("set FORM_SCHEDULER_INTERVAL env variable to 1 or more seconds")
+        })
+        .unwrap_or(60);
+    // Minutes for access tokens and days for refresh tokens.
+    let access_token_ttl = std::env::var("ACCESS_TOKEN_TTL")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(15);
+    let refresh_token_ttl = std::env::var("REFRESH_TOKEN_TTL")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(30);
+    // Minutes a password reset or 2FA enrollment link stays valid.
+    let reset_token_ttl = std::env::var("RESET_TOKEN_TTL")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(60);
+    // Failed sign ins before the account is locked, and the lock in minutes.
+    let login_max_attempts = std::env::var("LOGIN_MAX_ATTEMPTS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(10);
+    let login_lockout = std::env::var("LOGIN_LOCKOUT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(15);
+    let totp_issuer = std::env::var("TOTP_ISSUER").unwrap_or("IDP Console".to_string());
+    // Single sign-on creates unknown users with this role, when it is set.
+    let oidc_default_role = std::env::var("OIDC_DEFAULT_ROLE").ok().map(|v| {
+        v.parse::<Role>()
+            .expect("set a valid OIDC_DEFAULT_ROLE env variable")
+    });
+    // Memory in KiB. Existing hashes are upgraded on the next sign in.
+    let argon2_memory = std::env::var("ARGON2_MEMORY")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(19456);
+    let argon2_iterations = std::env::var("ARGON2_ITERATIONS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(2);
+    let argon2_parallelism = std::env::var("ARGON2_PARALLELISM")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(1);
+    argon2::Params::new(argon2_memory, argon2_iterations, argon2_parallelism, None)
+        .expect("set valid ARGON2_* env variables");
+    // Addresses or CIDR ranges of the reverse proxies whose X-Forwarded-For
+    // header is believed.
+    let trusted_proxies =
+        TrustedProxies::parse(&std::env::var("TRUSTED_PROXIES").unwrap_or_default())
+            .expect("set a valid TRUSTED_PROXIES env variable");
+    let app_url = std::env::var("APP_URL").unwrap_or("http://localhost:8080".to_string());
+    let config = Config {
+        jwt_keys,
+        form_scheduler_interval,
+        access_token_ttl,
+        refresh_token_ttl,
+        reset_token_ttl,
+        app_url,
+        login_max_attempts,
+        login_lockout,
+        totp_issuer,
+        oidc_default_role,
+        argon2_memory,
+        argon2_iterations,
+        argon2_parallelism,
+        trusted_proxies,
+    };
     let db = DB::connect().await;
     db.init_default_user(&config).await;
 
-    let app_state = Arc::new(AppState { db, config });
+    let mailer = mailer::from_env();
+    let oidc = oidc::from_env(&config.app_url);
+
+    let app_state = Arc::new(AppState {
+        db,
+        config,
+        mailer,
+        oidc,
+    });
+    tokio::spawn(run_form_scheduler(app_state.clone()));
+
     let app = Router::new()
         .merge(auth::build_routes())
+        .merge(api_key::build_routes())
+        .merge(audit::build_routes())
         .merge(form::build_routes())
         .merge(respondent::build_routes())
         .merge(submission::build_routes())
+        .merge(user::build_routes())
         .with_state(app_state)
         .nest_service("/assets", ServeDir::new("./dist/assets"))
         .fallback_service(ServeFile::new("./dist/index.html"));
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await.unwrap();
 
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
+}
+
+async fn run_form_scheduler(state: Arc<AppState>) {
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(state.config.form_scheduler_interval));
+    loop {
+        interval.tick().await;
+        FormScheduler::new(state.db.forms.as_ref())
+            .run(Utc::now())
+            .await;
+    }
 }
@@ -0,0 +1,217 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use reqwest::{Client, Url};
+use serde::Deserialize;
+
+use crate::app::traits::oidc::{OidcIdentity, TOidcProvider};
+
+#[derive(Debug, Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct IdTokenClaims {
+    email: Option<String>,
+    email_verified: Option<bool>,
+    nonce: Option<String>,
+}
+
+/// Talks to the identity provider over HTTP. The discovery document is read on
+/// every call, so key rotations at the provider are picked up at once.
+pub struct OidcClient {
+    http: Client,
+    issuer: String,
+    client_id: String,
+    client_secret: Option<String>,
+    redirect_url: String,
+    scopes: String,
+    assume_email_verified: bool,
+}
+
+impl OidcClient {
+    pub fn new(
+        issuer: String,
+        client_id: String,
+        client_secret: Option<String>,
+        redirect_url: String,
+        scopes: String,
+        assume_email_verified: bool,
+    ) -> Self {
+        let http = Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("build the HTTP client");
+
+        Self {
+            http,
+            issuer: issuer.trim_end_matches('/').to_string(),
+            client_id,
+            client_secret,
+            redirect_url,
+            scopes,
+            assume_email_verified,
+        }
+    }
+
+    async fn discovery(&self) -> Result<Discovery, String> {
+        let url = format!("{}/.well-known/openid-configuration", self.issuer);
+        self.get_json(&url).await
+    }
+
+    async fn get_json<T: for<'de> Deserialize<'de>>(&self, url: &str) -> Result<T, String> {
+        let res = match self.http.get(url).send().await {
+            Ok(res) => res,
+            Err(err) => return Err(err.to_string()),
+        };
+        if !res.status().is_success() {
+            return Err(format!("{} answered {}", url, res.status()));
+        }
+        match res.json::<T>().await {
+            Ok(data) => Ok(data),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn verify_id_token(
+        &self,
+        discovery: &Discovery,
+        id_token: &str,
+    ) -> Result<IdTokenClaims, String> {
+        let header = match decode_header(id_token) {
+            Ok(header) => header,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        // Only keys published by the provider are trusted, never shared secrets.
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err("ID token algorithm is not supported".to_string());
+        }
+
+        let jwks: JwkSet = match self.get_json(&discovery.jwks_uri).await {
+            Ok(jwks) => jwks,
+            Err(err) => return Err(err),
+        };
+        let jwk = match header.kid {
+            Some(ref kid) => jwks.find(kid),
+            None if jwks.keys.len() == 1 => jwks.keys.first(),
+            None => None,
+        };
+        let key = match jwk.map(DecodingKey::from_jwk) {
+            Some(Ok(key)) => key,
+            Some(Err(err)) => return Err(err.to_string()),
+            None => return Err("ID token key is not found".to_string()),
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&discovery.issuer]);
+        validation.set_audience(&[&self.client_id]);
+
+        match decode::<IdTokenClaims>(id_token, &key, &validation) {
+            Ok(data) => Ok(data.claims),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl TOidcProvider for OidcClient {
+    async fn authorize_url(
+        &self,
+        state: &str,
+        nonce: &str,
+        code_challenge: &str,
+    ) -> Result<String, String> {
+        let discovery = match self.discovery().await {
+            Ok(discovery) => discovery,
+            Err(err) => return Err(err),
+        };
+
+        let url = Url::parse_with_params(
+            &discovery.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", &self.client_id),
+                ("redirect_uri", &self.redirect_url),
+                ("scope", &self.scopes),
+                ("state", state),
+                ("nonce", nonce),
+                ("code_challenge", code_challenge),
+                ("code_challenge_method", "S256"),
+            ],
+        );
+
+        match url {
+            Ok(url) => Ok(url.to_string()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn exchange(&self, code: &str, code_verifier: &str) -> Result<OidcIdentity, String> {
+        let discovery = match self.discovery().await {
+            Ok(discovery) => discovery,
+            Err(err) => return Err(err),
+        };
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.redirect_url),
+            ("client_id", &self.client_id),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(ref secret) = self.client_secret {
+            form.push(("client_secret", secret));
+        }
+
+        let res = self
+            .http
+            .post(&discovery.token_endpoint)
+            .form(&form)
+            .send()
+            .await;
+        let res = match res {
+            Ok(res) => res,
+            Err(err) => return Err(err.to_string()),
+        };
+        if !res.status().is_success() {
+            return Err(format!(
+                "Identity provider refused the code: {}",
+                res.status()
+            ));
+        }
+        let tokens = match res.json::<TokenResponse>().await {
+            Ok(tokens) => tokens,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let claims = match self.verify_id_token(&discovery, &tokens.id_token).await {
+            Ok(claims) => claims,
+            Err(err) => return Err(err),
+        };
+
+        let email = match claims.email {
+            Some(email) => email,
+            None => return Err("Identity provider did not share the email".to_string()),
+        };
+
+        Ok(OidcIdentity {
+            email,
+            email_verified: claims.email_verified.unwrap_or(self.assume_email_verified),
+            nonce: claims.nonce,
+        })
+    }
+}
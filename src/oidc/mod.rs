@@ -0,0 +1,32 @@
+use crate::app::traits::oidc::TOidcProvider;
+
+use self::client::OidcClient;
+
+mod client;
+
+/// Builds the provider from the `OIDC_*` env variables. Single sign-on is off
+/// unless `OIDC_ISSUER` and `OIDC_CLIENT_ID` are set. The redirect URL is the
+/// page of the console that posts the code back to `/api/auth/oidc/callback`.
+/// Emails without the `email_verified` claim count as unverified unless
+/// `OIDC_ASSUME_EMAIL_VERIFIED` is `true`, for providers that only hand out
+/// organisation emails and never send the claim.
+pub fn from_env(app_url: &str) -> Option<Box<dyn TOidcProvider + Sync + Send>> {
+    let issuer = std::env::var("OIDC_ISSUER").ok()?;
+    let client_id = std::env::var("OIDC_CLIENT_ID").ok()?;
+    let client_secret = std::env::var("OIDC_CLIENT_SECRET").ok();
+    let redirect_url =
+        std::env::var("OIDC_REDIRECT_URL").unwrap_or(format!("{}/oidc/callback", app_url));
+    let scopes = std::env::var("OIDC_SCOPES").unwrap_or("openid email profile".to_string());
+    let assume_email_verified = std::env::var("OIDC_ASSUME_EMAIL_VERIFIED")
+        .map(|v| v == "true")
+        .unwrap_or(false);
+
+    Some(Box::new(OidcClient::new(
+        issuer,
+        client_id,
+        client_secret,
+        redirect_url,
+        scopes,
+        assume_email_verified,
+    )))
+}
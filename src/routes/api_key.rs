@@ -0,0 +1,58 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get},
+    Json, Router,
+};
+use serde_json::json;
+
+use super::error_status;
+use crate::{
+    app::services::{
+        api_key::{ApiKeyService, CreateInputData},
+        current_user::CurrentUser,
+    },
+    extra::json_input::JsonInput,
+    AppState,
+};
+
+pub fn build_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/api/api-keys", get(get_api_keys).post(create_api_key))
+        .route("/api/api-keys/:key_id", delete(revoke_api_key))
+}
+
+async fn get_api_keys(State(state): State<Arc<AppState>>, current: CurrentUser) -> Response {
+    let service = ApiKeyService::new(state.db.users.as_ref(), &current);
+    match service.get().await {
+        Ok(data) => (StatusCode::OK, Json(json!({ "data": data }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn create_api_key(
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+    JsonInput(body): JsonInput<CreateInputData>,
+) -> Response {
+    let service = ApiKeyService::new(state.db.users.as_ref(), &current);
+    match service.create(body).await {
+        Ok(data) => (StatusCode::OK, Json(json!({ "data": data }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn revoke_api_key(
+    Path(key_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+) -> Response {
+    let service = ApiKeyService::new(state.db.users.as_ref(), &current);
+    match service.revoke(&key_id).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "data":  {} }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
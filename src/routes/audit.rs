@@ -0,0 +1,35 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde_json::json;
+
+use super::error_status;
+use crate::{
+    app::services::{
+        audit::{AuditService, GetQuery},
+        current_user::CurrentUser,
+    },
+    AppState,
+};
+
+pub fn build_routes() -> Router<Arc<AppState>> {
+    Router::new().route("/api/audit", get(get_audit))
+}
+
+async fn get_audit(
+    Query(query): Query<GetQuery>,
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+) -> Response {
+    let service = AuditService::new(state.db.audit.as_ref(), &current);
+    match service.get(query).await {
+        Ok(data) => (StatusCode::OK, Json(json!({ "data": data }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
@@ -1,35 +1,126 @@
 use std::sync::Arc;
 
 use axum::{
-    extract::State,
-    http::StatusCode,
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
     response::{IntoResponse, Response},
-    routing::post,
+    routing::{delete, get, post},
     Json, Router,
 };
 use serde_json::json;
 
+use super::error_status;
 use crate::{
-    app::services::auth::{AuthService, LoginInputData},
-    extra::{auth_data::AuthData, json_input::JsonInput},
+    app::services::{
+        auth::{
+            AuthService, ChangePasswordInputData, EmailInputData, LoginInputData, RefreshInputData,
+            ResetPasswordInputData, TwoFactorInputData,
+        },
+        current_user::CurrentUser,
+        enrollment::{EnrollmentInputData, EnrollmentService},
+        oidc::{OidcCallbackInputData, OidcService},
+        password::PasswordService,
+        two_factor::{CodeInputData, TwoFactorService},
+        user::UserService,
+    },
+    extra::{auth_data::AuthData, client_info::ClientInfo, json_input::JsonInput},
     AppState,
 };
 
+/// Binds a single sign-on to the browser that started it.
+const OIDC_STATE_COOKIE: &str = "oidc_state";
+
 pub fn build_routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/api/auth/signin", post(sign_in))
+        .route("/api/auth/signin/2fa", post(sign_in_two_factor))
+        .route("/api/auth/signin/2fa/enroll", post(send_enrollment_link))
+        .route("/api/auth/signin/2fa/setup", post(open_enrollment_link))
+        .route("/api/auth/refresh", post(refresh))
         .route("/api/auth/signout", post(revoke_token))
+        .route("/api/auth/sessions", get(get_sessions))
+        .route("/api/auth/sessions", delete(revoke_sessions))
+        .route("/api/auth/sessions/:session_id", delete(revoke_session))
+        .route("/api/auth/password", post(change_password))
+        .route("/api/auth/password/forgot", post(forgot_password))
+        .route("/api/auth/password/reset", post(reset_password))
+        .route("/api/auth/2fa/setup", post(setup_two_factor))
+        .route("/api/auth/2fa/enable", post(enable_two_factor))
+        .route("/api/auth/2fa/disable", post(disable_two_factor))
+        .route("/api/auth/2fa/recovery-codes", post(renew_recovery_codes))
+        .route("/api/auth/oidc/start", post(start_oidc))
+        .route("/api/auth/oidc/callback", post(oidc_callback))
+        .route("/.well-known/jwks.json", get(get_jwks))
 }
 
 async fn sign_in(
     State(state): State<Arc<AppState>>,
+    client: ClientInfo,
     JsonInput(body): JsonInput<LoginInputData>,
 ) -> Response {
     let service = AuthService::new(&state.config, state.db.users.as_ref());
 
-    match service.login(body).await {
-        Ok(token) => (StatusCode::OK, Json(json!({"data": token}))).into_response(),
-        Err(err) => (StatusCode::BAD_REQUEST, Json(json!({ "data":  err }))).into_response(),
+    match service.login(body, client.ip, client.user_agent).await {
+        Ok(tokens) => (StatusCode::OK, Json(json!({"data": tokens}))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn sign_in_two_factor(
+    State(state): State<Arc<AppState>>,
+    client: ClientInfo,
+    JsonInput(body): JsonInput<TwoFactorInputData>,
+) -> Response {
+    let service = AuthService::new(&state.config, state.db.users.as_ref());
+
+    match service
+        .verify_two_factor(body, client.ip, client.user_agent)
+        .await
+    {
+        Ok(tokens) => (StatusCode::OK, Json(json!({"data": tokens}))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn send_enrollment_link(
+    State(state): State<Arc<AppState>>,
+    JsonInput(body): JsonInput<EnrollmentInputData>,
+) -> Response {
+    let service = EnrollmentService::new(
+        &state.config,
+        state.db.users.as_ref(),
+        state.mailer.as_ref(),
+    );
+    match service.send_link(body).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "data":  {} }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn open_enrollment_link(
+    State(state): State<Arc<AppState>>,
+    JsonInput(body): JsonInput<EnrollmentInputData>,
+) -> Response {
+    let service = EnrollmentService::new(
+        &state.config,
+        state.db.users.as_ref(),
+        state.mailer.as_ref(),
+    );
+    match service.open_link(body).await {
+        Ok(challenge) => (StatusCode::OK, Json(json!({"data": challenge}))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn refresh(
+    State(state): State<Arc<AppState>>,
+    JsonInput(body): JsonInput<RefreshInputData>,
+) -> Response {
+    let service = AuthService::new(&state.config, state.db.users.as_ref());
+
+    match service.refresh(body).await {
+        Ok(tokens) => (StatusCode::OK, Json(json!({"data": tokens}))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
     }
 }
 
@@ -37,6 +128,195 @@ async fn revoke_token(State(state): State<Arc<AppState>>, auth: AuthData) -> Res
     let service = AuthService::new(&state.config, state.db.users.as_ref());
     match service.revoke_token(&auth.token).await {
         Ok(_) => (StatusCode::OK, Json(json!({ "data":  {} }))).into_response(),
-        Err(err) => (StatusCode::BAD_REQUEST, Json(json!({ "data":  err }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn get_sessions(State(state): State<Arc<AppState>>, current: CurrentUser) -> Response {
+    let service = UserService::new(&state.config, state.db.users.as_ref(), &current);
+    match service.sessions().await {
+        Ok(data) => (StatusCode::OK, Json(json!({ "data": data }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn revoke_session(
+    Path(session_id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+) -> Response {
+    let service = UserService::new(&state.config, state.db.users.as_ref(), &current);
+    match service.revoke_session(session_id).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "data":  {} }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn revoke_sessions(State(state): State<Arc<AppState>>, current: CurrentUser) -> Response {
+    let service = UserService::new(&state.config, state.db.users.as_ref(), &current);
+    match service.revoke_sessions().await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "data":  {} }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn change_password(
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+    JsonInput(body): JsonInput<ChangePasswordInputData>,
+) -> Response {
+    let service = UserService::new(&state.config, state.db.users.as_ref(), &current);
+    match service.change_password(body).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "data":  {} }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
     }
 }
+
+async fn forgot_password(
+    State(state): State<Arc<AppState>>,
+    client: ClientInfo,
+    JsonInput(body): JsonInput<EmailInputData>,
+) -> Response {
+    let service = PasswordService::new(
+        &state.config,
+        state.db.users.as_ref(),
+        state.mailer.as_ref(),
+    );
+    match service.forgot(body, &client.ip).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "data":  {} }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn reset_password(
+    State(state): State<Arc<AppState>>,
+    JsonInput(body): JsonInput<ResetPasswordInputData>,
+) -> Response {
+    let service = PasswordService::new(
+        &state.config,
+        state.db.users.as_ref(),
+        state.mailer.as_ref(),
+    );
+    match service.reset(body).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "data":  {} }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn setup_two_factor(State(state): State<Arc<AppState>>, current: CurrentUser) -> Response {
+    let service = TwoFactorService::new(&state.config, state.db.users.as_ref(), &current);
+    match service.setup().await {
+        Ok(data) => (StatusCode::OK, Json(json!({ "data": data }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn enable_two_factor(
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+    JsonInput(body): JsonInput<CodeInputData>,
+) -> Response {
+    let service = TwoFactorService::new(&state.config, state.db.users.as_ref(), &current);
+    match service.enable(body).await {
+        Ok(data) => (StatusCode::OK, Json(json!({ "data": data }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn disable_two_factor(
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+    JsonInput(body): JsonInput<CodeInputData>,
+) -> Response {
+    let service = TwoFactorService::new(&state.config, state.db.users.as_ref(), &current);
+    match service.disable(body).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "data":  {} }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn renew_recovery_codes(
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+    JsonInput(body): JsonInput<CodeInputData>,
+) -> Response {
+    let service = TwoFactorService::new(&state.config, state.db.users.as_ref(), &current);
+    match service.recovery_codes(body).await {
+        Ok(data) => (StatusCode::OK, Json(json!({ "data": data }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn start_oidc(State(state): State<Arc<AppState>>) -> Response {
+    let service = OidcService::new(
+        &state.config,
+        state.db.users.as_ref(),
+        state.oidc.as_deref(),
+    );
+
+    match service.start().await {
+        Ok(data) => {
+            let secure = if state.config.app_url.starts_with("https://") {
+                "; Secure"
+            } else {
+                ""
+            };
+            let cookie = format!(
+                "{}={}; Path=/api/auth/oidc; Max-Age=600; HttpOnly; SameSite=Lax{}",
+                OIDC_STATE_COOKIE, data.state, secure
+            );
+            (
+                StatusCode::OK,
+                [(header::SET_COOKIE, cookie)],
+                Json(json!({ "data": data })),
+            )
+                .into_response()
+        }
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn oidc_callback(
+    State(state): State<Arc<AppState>>,
+    client: ClientInfo,
+    headers: HeaderMap,
+    JsonInput(body): JsonInput<OidcCallbackInputData>,
+) -> Response {
+    let service = OidcService::new(
+        &state.config,
+        state.db.users.as_ref(),
+        state.oidc.as_deref(),
+    );
+    let browser_state = cookie(&headers, OIDC_STATE_COOKIE);
+    let clear = format!("{}=; Path=/api/auth/oidc; Max-Age=0", OIDC_STATE_COOKIE);
+
+    match service
+        .callback(body, browser_state, client.ip, client.user_agent)
+        .await
+    {
+        Ok(tokens) => (
+            StatusCode::OK,
+            [(header::SET_COOKIE, clear)],
+            Json(json!({"data": tokens})),
+        )
+            .into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+fn cookie(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
+
+/// Public keys partner services verify our tokens with. Shared secrets are never listed.
+async fn get_jwks(State(state): State<Arc<AppState>>) -> Response {
+    let keys = state.config.jwt_keys.jwks();
+    (StatusCode::OK, Json(json!({ "keys": keys }))).into_response()
+}
@@ -4,21 +4,23 @@ use axum::{
     extract::{Path, State},
     http::StatusCode,
     response::{IntoResponse, Response},
-    routing::{delete, get, patch, post},
+    routing::{delete, get, patch, post, put},
     Json, Router,
 };
 use serde::Deserialize;
 use serde_json::json;
 
+use super::error_status;
 use crate::{
     app::{
-        entities::form::status::FormStatus,
+        entities::{form::status::FormStatus, submission::waitlist::Booking},
         services::{
-            form::{CreateFromData, FormService, UpdateFromData},
+            current_user::CurrentUser,
+            form::{CreateFromData, FormService, SetSlotsData, ShiftData, UpdateFromData},
             submission::{GetQuery, SubmissionService},
         },
     },
-    extra::{auth_data::AuthData, json_input::JsonInput},
+    extra::json_input::JsonInput,
     AppState,
 };
 
@@ -31,141 +33,188 @@ pub fn build_routes() -> Router<Arc<AppState>> {
         .route("/api/forms/:form_id", delete(delete_form))
         .route("/api/forms/:form_id/open", post(open_form))
         .route("/api/forms/:form_id/close", post(close_form))
+        .route("/api/forms/:form_id/reflow", post(reflow_form))
+        .route("/api/forms/:form_id/shift", post(shift_form))
+        .route("/api/forms/:form_id/shifts", get(get_shifts))
+        .route("/api/forms/:form_id/slots", get(get_slots))
+        .route("/api/forms/:form_id/slots", put(set_slots))
+        .route("/api/forms/:form_id/waitlist", get(get_waitlist))
+        .route(
+            "/api/forms/:form_id/waitlist/:entry_id",
+            patch(move_waitlist_entry),
+        )
+        .route(
+            "/api/forms/:form_id/waitlist/:entry_id",
+            delete(remove_waitlist_entry),
+        )
         .route("/api/forms/:form_id/submissions", get(get_submissions))
         .route("/api/forms/:form_id/submissions", post(create_submission))
+        .route(
+            "/api/forms/:form_id/eligibility/:respondent_id",
+            get(get_eligibility),
+        )
 }
 
-async fn get_forms(State(state): State<Arc<AppState>>, auth: AuthData) -> Response {
-    let service = FormService::new(
-        &state.config,
-        state.db.forms.as_ref(),
-        state.db.users.as_ref(),
-        &auth.token,
-    );
+async fn get_forms(State(state): State<Arc<AppState>>, current: CurrentUser) -> Response {
+    let service = FormService::new(state.db.forms.as_ref(), &current);
     match service.get().await {
         Ok(forms) => (StatusCode::OK, Json(json!({"data": forms}))).into_response(),
-        Err(err) => (StatusCode::BAD_REQUEST, Json(json!({ "data":  err }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
     }
 }
 
 async fn create_form(
     State(state): State<Arc<AppState>>,
-    auth: AuthData,
+    current: CurrentUser,
     JsonInput(body): JsonInput<CreateFromData>,
 ) -> Response {
-    let service = FormService::new(
-        &state.config,
-        state.db.forms.as_ref(),
-        state.db.users.as_ref(),
-        &auth.token,
-    );
+    let service = FormService::new(state.db.forms.as_ref(), &current);
 
     match service.create(body).await {
         Ok(id) => (StatusCode::OK, Json(json!({"data": id}))).into_response(),
-        Err(err) => (StatusCode::BAD_REQUEST, Json(json!({ "data":  err }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
     }
 }
 
 async fn get_form(
     Path(form_id): Path<String>,
     State(state): State<Arc<AppState>>,
-    auth: AuthData,
+    current: CurrentUser,
 ) -> Response {
-    let service = FormService::new(
-        &state.config,
-        state.db.forms.as_ref(),
-        state.db.users.as_ref(),
-        &auth.token,
-    );
+    let service = FormService::new(state.db.forms.as_ref(), &current);
 
     match service.get_by_id(&form_id).await {
         Ok(forms) => (StatusCode::OK, Json(json!({"data": forms}))).into_response(),
-        Err(err) => (StatusCode::BAD_REQUEST, Json(json!({ "data":  err }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
     }
 }
 
 async fn update_form(
     Path(form_id): Path<String>,
     State(state): State<Arc<AppState>>,
-    auth: AuthData,
+    current: CurrentUser,
     JsonInput(body): JsonInput<UpdateFromData>,
 ) -> Response {
-    let service = FormService::new(
-        &state.config,
-        state.db.forms.as_ref(),
-        state.db.users.as_ref(),
-        &auth.token,
-    );
+    let service = FormService::new(state.db.forms.as_ref(), &current);
 
     match service.update(form_id.clone(), body).await {
-        Ok(()) => (StatusCode::OK, Json(json!({ "data": { "id": form_id } }))).into_response(),
-        Err(err) => (StatusCode::BAD_REQUEST, Json(json!({ "data":  err }))).into_response(),
+        Ok(moved) => (
+            StatusCode::OK,
+            Json(json!({ "data": { "id": form_id, "moved": moved } })),
+        )
+            .into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
     }
 }
 
 async fn delete_form(
     Path(form_id): Path<String>,
     State(state): State<Arc<AppState>>,
-    auth: AuthData,
+    current: CurrentUser,
 ) -> Response {
-    let service = FormService::new(
-        &state.config,
-        state.db.forms.as_ref(),
-        state.db.users.as_ref(),
-        &auth.token,
-    );
+    let service = FormService::new(state.db.forms.as_ref(), &current);
     match service.delete(&form_id).await {
         Ok(()) => (StatusCode::OK, Json(json!({ "data": {} }))).into_response(),
-        Err(err) => (StatusCode::BAD_REQUEST, Json(json!({ "data":  err }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
     }
 }
 
 async fn open_form(
     Path(form_id): Path<String>,
     State(state): State<Arc<AppState>>,
-    auth: AuthData,
+    current: CurrentUser,
 ) -> Response {
-    let service = FormService::new(
-        &state.config,
-        state.db.forms.as_ref(),
-        state.db.users.as_ref(),
-        &auth.token,
-    );
+    let service = FormService::new(state.db.forms.as_ref(), &current);
     match service.status(form_id, FormStatus::Open).await {
         Ok(()) => (StatusCode::OK, Json(json!({ "data": {} }))).into_response(),
-        Err(err) => (StatusCode::BAD_REQUEST, Json(json!({ "data":  err }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
     }
 }
 
 async fn close_form(
     Path(form_id): Path<String>,
     State(state): State<Arc<AppState>>,
-    auth: AuthData,
+    current: CurrentUser,
 ) -> Response {
-    let service = FormService::new(
-        &state.config,
-        state.db.forms.as_ref(),
-        state.db.users.as_ref(),
-        &auth.token,
-    );
+    let service = FormService::new(state.db.forms.as_ref(), &current);
     match service.status(form_id, FormStatus::Close).await {
         Ok(()) => (StatusCode::OK, Json(json!({ "data": {} }))).into_response(),
-        Err(err) => (StatusCode::BAD_REQUEST, Json(json!({ "data":  err }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn reflow_form(
+    Path(form_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+) -> Response {
+    let service = FormService::new(state.db.forms.as_ref(), &current);
+    match service.reflow(&form_id).await {
+        Ok(data) => (StatusCode::OK, Json(json!({ "data": data }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn shift_form(
+    Path(form_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+    JsonInput(body): JsonInput<ShiftData>,
+) -> Response {
+    let service = FormService::new(state.db.forms.as_ref(), &current);
+    match service.shift(&form_id, body).await {
+        Ok(data) => (StatusCode::OK, Json(json!({ "data": data }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn get_shifts(
+    Path(form_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+) -> Response {
+    let service = FormService::new(state.db.forms.as_ref(), &current);
+    match service.shifts(&form_id).await {
+        Ok(data) => (StatusCode::OK, Json(json!({ "data": data }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn get_slots(
+    Path(form_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+) -> Response {
+    let service = FormService::new(state.db.forms.as_ref(), &current);
+    match service.slots(&form_id).await {
+        Ok(data) => (StatusCode::OK, Json(json!({ "data": data }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn set_slots(
+    Path(form_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+    JsonInput(body): JsonInput<SetSlotsData>,
+) -> Response {
+    let service = FormService::new(state.db.forms.as_ref(), &current);
+    match service.set_slots(&form_id, body).await {
+        Ok(limit) => (StatusCode::OK, Json(json!({ "data": { "limit": limit } }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
     }
 }
 
 async fn get_submissions(
     Path(form_id): Path<String>,
     State(state): State<Arc<AppState>>,
-    auth: AuthData,
+    current: CurrentUser,
 ) -> Response {
     let service = SubmissionService::new(
-        &state.config,
         state.db.submissions.as_ref(),
-        state.db.users.as_ref(),
         state.db.forms.as_ref(),
         state.db.respondents.as_ref(),
-        &auth.token,
+        &current,
     );
     let query = GetQuery {
         form_id: Some(form_id),
@@ -173,7 +222,7 @@ async fn get_submissions(
     };
     match service.get(query).await {
         Ok(data) => (StatusCode::OK, Json(json!({ "data": data}))).into_response(),
-        Err(err) => (StatusCode::BAD_REQUEST, Json(json!({ "data":  err }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
     }
 }
 
@@ -185,19 +234,101 @@ struct CreateSubmissionBody {
 async fn create_submission(
     Path(form_id): Path<String>,
     State(state): State<Arc<AppState>>,
-    auth: AuthData,
+    current: CurrentUser,
     JsonInput(body): JsonInput<CreateSubmissionBody>,
 ) -> Response {
     let service = SubmissionService::new(
-        &state.config,
         state.db.submissions.as_ref(),
-        state.db.users.as_ref(),
         state.db.forms.as_ref(),
         state.db.respondents.as_ref(),
-        &auth.token,
+        &current,
     );
     match service.create(&form_id, &body.respondent_id).await {
+        Ok(Booking::Submission(id)) => {
+            (StatusCode::OK, Json(json!({ "data": id }))).into_response()
+        }
+        Ok(Booking::Waitlist(id)) => (
+            StatusCode::ACCEPTED,
+            Json(json!({ "data": { "waitlistId": id } })),
+        )
+            .into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn get_eligibility(
+    Path((form_id, respondent_id)): Path<(String, String)>,
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+) -> Response {
+    let service = SubmissionService::new(
+        state.db.submissions.as_ref(),
+        state.db.forms.as_ref(),
+        state.db.respondents.as_ref(),
+        &current,
+    );
+    match service.eligibility(&form_id, &respondent_id).await {
+        Ok(data) => (StatusCode::OK, Json(json!({ "data": data }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn get_waitlist(
+    Path(form_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+) -> Response {
+    let service = SubmissionService::new(
+        state.db.submissions.as_ref(),
+        state.db.forms.as_ref(),
+        state.db.respondents.as_ref(),
+        &current,
+    );
+    match service.waitlist(&form_id).await {
         Ok(data) => (StatusCode::OK, Json(json!({ "data": data }))).into_response(),
-        Err(err) => (StatusCode::BAD_REQUEST, Json(json!({ "data":  err }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MoveWaitlistEntryBody {
+    position: u32,
+}
+
+async fn move_waitlist_entry(
+    Path((form_id, entry_id)): Path<(String, String)>,
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+    JsonInput(body): JsonInput<MoveWaitlistEntryBody>,
+) -> Response {
+    let service = SubmissionService::new(
+        state.db.submissions.as_ref(),
+        state.db.forms.as_ref(),
+        state.db.respondents.as_ref(),
+        &current,
+    );
+    match service
+        .move_waitlist_entry(&form_id, &entry_id, body.position)
+        .await
+    {
+        Ok(()) => (StatusCode::OK, Json(json!({ "data": {} }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn remove_waitlist_entry(
+    Path((form_id, entry_id)): Path<(String, String)>,
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+) -> Response {
+    let service = SubmissionService::new(
+        state.db.submissions.as_ref(),
+        state.db.forms.as_ref(),
+        state.db.respondents.as_ref(),
+        &current,
+    );
+    match service.remove_waitlist_entry(&form_id, &entry_id).await {
+        Ok(()) => (StatusCode::OK, Json(json!({ "data": {} }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
     }
 }
@@ -1,4 +1,20 @@
+use axum::http::StatusCode;
+
+use crate::app::errors::{BaseError, ErrorKind};
+
+pub mod api_key;
+pub mod audit;
 pub mod auth;
 pub mod form;
 pub mod respondent;
 pub mod submission;
+pub mod user;
+
+pub fn error_status(err: &BaseError) -> StatusCode {
+    match err.kind {
+        ErrorKind::Unauthorized => StatusCode::UNAUTHORIZED,
+        ErrorKind::Forbidden => StatusCode::FORBIDDEN,
+        ErrorKind::BadRequest => StatusCode::BAD_REQUEST,
+        ErrorKind::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+    }
+}
@@ -10,14 +10,17 @@ use axum::{
 
 use serde_json::json;
 
+use super::error_status;
 use crate::{
     app::services::{
+        current_user::CurrentUser,
         respondent::{
-            self, create_data::CreateData, update_data::UpdateData, MergeData, RespondentService,
+            self, create_data::CreateData, merge_data::MergeData, update_data::UpdateData,
+            RespondentService,
         },
         submission::{self, SubmissionService},
     },
-    extra::{auth_data::AuthData, json_input::JsonInput},
+    extra::json_input::JsonInput,
     AppState,
 };
 
@@ -41,124 +44,92 @@ pub fn build_routes() -> Router<Arc<AppState>> {
 async fn get_respondents(
     Query(query): Query<respondent::GetQuery>,
     State(state): State<Arc<AppState>>,
-    auth: AuthData,
+    current: CurrentUser,
 ) -> Response {
-    let service = RespondentService::new(
-        &state.config,
-        state.db.respondents.as_ref(),
-        state.db.users.as_ref(),
-        &auth.token,
-    );
+    let service = RespondentService::new(state.db.respondents.as_ref(), &current);
     match service.get(query).await {
         Ok(data) => (StatusCode::OK, Json(json!({"data": data}))).into_response(),
-        Err(err) => (StatusCode::BAD_REQUEST, Json(json!({ "data":  err }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
     }
 }
 
 async fn create_respondent(
     State(state): State<Arc<AppState>>,
-    auth: AuthData,
+    current: CurrentUser,
     JsonInput(body): JsonInput<CreateData>,
 ) -> Response {
-    let service = RespondentService::new(
-        &state.config,
-        state.db.respondents.as_ref(),
-        state.db.users.as_ref(),
-        &auth.token,
-    );
+    let service = RespondentService::new(state.db.respondents.as_ref(), &current);
 
     match service.create(&body).await {
         Ok(id) => (StatusCode::OK, Json(json!({"data": id}))).into_response(),
-        Err(err) => (StatusCode::BAD_REQUEST, Json(json!({ "data":  err }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
     }
 }
 
 async fn update_respondent(
     Path(respondent_id): Path<String>,
     State(state): State<Arc<AppState>>,
-    auth: AuthData,
+    current: CurrentUser,
     JsonInput(body): JsonInput<UpdateData>,
 ) -> Response {
-    let service = RespondentService::new(
-        &state.config,
-        state.db.respondents.as_ref(),
-        state.db.users.as_ref(),
-        &auth.token,
-    );
+    let service = RespondentService::new(state.db.respondents.as_ref(), &current);
 
     match service.update(respondent_id, &body).await {
         Ok(data) => (StatusCode::OK, Json(json!({"data": data}))).into_response(),
-        Err(err) => (StatusCode::BAD_REQUEST, Json(json!({ "data":  err }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
     }
 }
 
 async fn get_respondent(
     Path(respondent_id): Path<String>,
     State(state): State<Arc<AppState>>,
-    auth: AuthData,
+    current: CurrentUser,
 ) -> Response {
-    let service = RespondentService::new(
-        &state.config,
-        state.db.respondents.as_ref(),
-        state.db.users.as_ref(),
-        &auth.token,
-    );
+    let service = RespondentService::new(state.db.respondents.as_ref(), &current);
 
     match service.get_by_id(&respondent_id).await {
         Ok(data) => (StatusCode::OK, Json(json!({"data": data}))).into_response(),
-        Err(err) => (StatusCode::BAD_REQUEST, Json(json!({ "data":  err }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
     }
 }
 
 async fn delete_respondent(
     Path(respondent_id): Path<String>,
     State(state): State<Arc<AppState>>,
-    auth: AuthData,
+    current: CurrentUser,
 ) -> Response {
-    let service = RespondentService::new(
-        &state.config,
-        state.db.respondents.as_ref(),
-        state.db.users.as_ref(),
-        &auth.token,
-    );
+    let service = RespondentService::new(state.db.respondents.as_ref(), &current);
 
     match service.delete(respondent_id).await {
         Ok(_) => (StatusCode::OK, Json(json!({"data": {}}))).into_response(),
-        Err(err) => (StatusCode::BAD_REQUEST, Json(json!({ "data":  err }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
     }
 }
 
 async fn merge_respondent(
     Path(respondent_id): Path<String>,
     State(state): State<Arc<AppState>>,
-    auth: AuthData,
+    current: CurrentUser,
     JsonInput(body): JsonInput<MergeData>,
 ) -> Response {
-    let service = RespondentService::new(
-        &state.config,
-        state.db.respondents.as_ref(),
-        state.db.users.as_ref(),
-        &auth.token,
-    );
+    let service = RespondentService::new(state.db.respondents.as_ref(), &current);
 
     match service.merge(&respondent_id, &body).await {
-        Ok(_) => (StatusCode::OK, Json(json!({"data": {}}))).into_response(),
-        Err(err) => (StatusCode::BAD_REQUEST, Json(json!({ "data":  err }))).into_response(),
+        Ok(data) => (StatusCode::OK, Json(json!({"data": data}))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
     }
 }
 
 async fn get_submissions(
     Path(respondent_id): Path<String>,
     State(state): State<Arc<AppState>>,
-    auth: AuthData,
+    current: CurrentUser,
 ) -> Response {
     let service = SubmissionService::new(
-        &state.config,
         state.db.submissions.as_ref(),
-        state.db.users.as_ref(),
         state.db.forms.as_ref(),
         state.db.respondents.as_ref(),
-        &auth.token,
+        &current,
     );
     let query = submission::GetQuery {
         form_id: None,
@@ -166,6 +137,6 @@ async fn get_submissions(
     };
     match service.get(query).await {
         Ok(data) => (StatusCode::OK, Json(json!({ "data": data}))).into_response(),
-        Err(err) => (StatusCode::BAD_REQUEST, Json(json!({ "data":  err }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
     }
 }
@@ -4,15 +4,16 @@ use axum::{
     extract::{Path, State},
     http::StatusCode,
     response::{IntoResponse, Response},
-    routing::{delete, post},
+    routing::{delete, get, post},
     Json, Router,
 };
 use serde::Deserialize;
 use serde_json::json;
 
+use super::error_status;
 use crate::{
-    app::services::submission::SubmissionService,
-    extra::{auth_data::AuthData, json_input::JsonInput},
+    app::services::{current_user::CurrentUser, submission::SubmissionService},
+    extra::json_input::JsonInput,
     AppState,
 };
 
@@ -20,6 +21,7 @@ pub fn build_routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/api/submissions/:sub_id/status", post(udpate_status))
         .route("/api/submissions/:sub_id", delete(delete_sub))
+        .route("/api/submissions/:sub_id/history", get(get_history))
 }
 
 #[derive(Debug, Deserialize)]
@@ -30,39 +32,52 @@ struct UpdateSubStatusBody {
 async fn udpate_status(
     Path(sub_id): Path<String>,
     State(state): State<Arc<AppState>>,
-    auth: AuthData,
+    current: CurrentUser,
     JsonInput(body): JsonInput<UpdateSubStatusBody>,
 ) -> Response {
     let service = SubmissionService::new(
-        &state.config,
         state.db.submissions.as_ref(),
-        state.db.users.as_ref(),
         state.db.forms.as_ref(),
         state.db.respondents.as_ref(),
-        &auth.token,
+        &current,
     );
 
     match service.status(&sub_id, &body.status).await {
         Ok(()) => (StatusCode::OK, Json(json!({ "data": {} }))).into_response(),
-        Err(err) => (StatusCode::BAD_REQUEST, Json(json!({ "data":  err }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
     }
 }
 
 async fn delete_sub(
     Path(sub_id): Path<String>,
     State(state): State<Arc<AppState>>,
-    auth: AuthData,
+    current: CurrentUser,
 ) -> Response {
     let service = SubmissionService::new(
-        &state.config,
         state.db.submissions.as_ref(),
-        state.db.users.as_ref(),
         state.db.forms.as_ref(),
         state.db.respondents.as_ref(),
-        &auth.token,
+        &current,
     );
     match service.delete(&sub_id).await {
         Ok(()) => (StatusCode::OK, Json(json!({ "data": {} }))).into_response(),
-        Err(err) => (StatusCode::BAD_REQUEST, Json(json!({ "data":  err }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn get_history(
+    Path(sub_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+) -> Response {
+    let service = SubmissionService::new(
+        state.db.submissions.as_ref(),
+        state.db.forms.as_ref(),
+        state.db.respondents.as_ref(),
+        &current,
+    );
+    match service.history(&sub_id).await {
+        Ok(data) => (StatusCode::OK, Json(json!({ "data": data }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
     }
 }
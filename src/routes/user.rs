@@ -0,0 +1,181 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use serde_json::json;
+
+use super::error_status;
+use crate::{
+    app::services::{
+        auth::{CreateInputData, PasswordInputData},
+        current_user::CurrentUser,
+        two_factor::{PolicyData, TwoFactorService},
+        user_admin::{UpdateInputData, UserAdminService},
+    },
+    extra::json_input::JsonInput,
+    AppState,
+};
+
+pub fn build_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/api/users", get(get_users).post(create_user))
+        .route(
+            "/api/users/:user_id",
+            get(get_user).patch(update_user).delete(delete_user),
+        )
+        .route("/api/users/:user_id/disable", post(disable_user))
+        .route("/api/users/:user_id/enable", post(enable_user))
+        .route("/api/users/:user_id/unlock", post(unlock_user))
+        .route("/api/users/:user_id/password", post(reset_password))
+        .route("/api/users/:user_id/2fa", delete(reset_two_factor))
+        .route(
+            "/api/settings/two-factor",
+            get(get_two_factor_policy).put(set_two_factor_policy),
+        )
+}
+
+async fn get_users(State(state): State<Arc<AppState>>, current: CurrentUser) -> Response {
+    let service = UserAdminService::new(&state.config, state.db.users.as_ref(), &current);
+    match service.get().await {
+        Ok(data) => (StatusCode::OK, Json(json!({ "data": data }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn create_user(
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+    JsonInput(body): JsonInput<CreateInputData>,
+) -> Response {
+    let service = UserAdminService::new(&state.config, state.db.users.as_ref(), &current);
+    match service.create(body).await {
+        Ok(id) => (StatusCode::OK, Json(json!({ "data": id }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn get_user(
+    Path(user_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+) -> Response {
+    let service = UserAdminService::new(&state.config, state.db.users.as_ref(), &current);
+    match service.get_by_id(&user_id).await {
+        Ok(data) => (StatusCode::OK, Json(json!({ "data": data }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn update_user(
+    Path(user_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+    JsonInput(body): JsonInput<UpdateInputData>,
+) -> Response {
+    let service = UserAdminService::new(&state.config, state.db.users.as_ref(), &current);
+    match service.update(&user_id, body).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "data":  {} }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn delete_user(
+    Path(user_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+) -> Response {
+    let service = UserAdminService::new(&state.config, state.db.users.as_ref(), &current);
+    match service.delete(&user_id).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "data":  {} }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn disable_user(
+    Path(user_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+) -> Response {
+    let service = UserAdminService::new(&state.config, state.db.users.as_ref(), &current);
+    match service.set_disabled(&user_id, true).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "data":  {} }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn enable_user(
+    Path(user_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+) -> Response {
+    let service = UserAdminService::new(&state.config, state.db.users.as_ref(), &current);
+    match service.set_disabled(&user_id, false).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "data":  {} }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn unlock_user(
+    Path(user_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+) -> Response {
+    let service = UserAdminService::new(&state.config, state.db.users.as_ref(), &current);
+    match service.unlock(&user_id).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "data":  {} }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn reset_password(
+    Path(user_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+    JsonInput(body): JsonInput<PasswordInputData>,
+) -> Response {
+    let service = UserAdminService::new(&state.config, state.db.users.as_ref(), &current);
+    match service.reset_password(&user_id, body).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "data":  {} }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn reset_two_factor(
+    Path(user_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+) -> Response {
+    let service = TwoFactorService::new(&state.config, state.db.users.as_ref(), &current);
+    match service.reset(&user_id).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "data":  {} }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn get_two_factor_policy(
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+) -> Response {
+    let service = TwoFactorService::new(&state.config, state.db.users.as_ref(), &current);
+    match service.policy().await {
+        Ok(data) => (StatusCode::OK, Json(json!({ "data": data }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}
+
+async fn set_two_factor_policy(
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+    JsonInput(body): JsonInput<PolicyData>,
+) -> Response {
+    let service = TwoFactorService::new(&state.config, state.db.users.as_ref(), &current);
+    match service.set_policy(body).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "data":  {} }))).into_response(),
+        Err(err) => (error_status(&err), Json(json!({ "data":  err }))).into_response(),
+    }
+}